@@ -1,12 +1,12 @@
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Instant;
+use std::io::Write;
 use rand::Rng;
 use statrs::distribution::ContinuousCDF;
-use board_crab_lib::{fen, lookup_gen, move_gen, async_engine, transpos, time_manager, pgn};
-use board_crab_lib::bitmask::*;
-use board_crab_lib::board::*;
+use board_crab_lib::{fen, async_engine, transpos, time_manager, pgn};
 use board_crab_lib::eval::*;
+use board_crab_lib::game::Game;
 use board_crab_lib::search::SearchConfig;
 
 // Time for both player's clocks
@@ -15,81 +15,51 @@ const GAME_CLOCK_TIME_COMPLEMENT: f64 = 0.0; // Per-move complement
 
 // Eval at which we conclude the game over
 // Both engines must agree on the eval
-const TRUNCATE_EVAL_THRESH: Value = 4.5;
-
-fn is_game_over(board: &Board) -> bool {
-    // Draw by half-move limit
-    if board.half_move_counter >= 50 {
-        return true;
-    }
-
-    // Draw by insufficient material
-    if !is_checkmate_possible(board, 0) && !is_checkmate_possible(board, 1) {
-        return true;
-    }
-
-    // Checkmate
-    if board.checkers != 0 {
-        let king = board.pieces[board.turn_idx][PIECE_KING];
-        let team_occupy = board.occupancy[board.turn_idx];
-        let opp_attack = board.attacks[1 - board.turn_idx];
-        let base_king_moves = lookup_gen::get_piece_base_tos(PIECE_KING, bm_to_idx(king));
-        let king_moves = base_king_moves & !opp_attack & !team_occupy;
-
-        if king_moves == 0 {
-            // King can't move, could be checkmate
-            // Generate moves to know for sure
-            let mut move_buffer = move_gen::MoveBuffer::new();
-            move_gen::generate_moves(board, &mut move_buffer);
-
-            if move_buffer.is_empty() {
-                // It's checkmate
-                return true;
-            }
-        }
-    }
-
-    false
-}
+const TRUNCATE_EVAL_THRESH: Value = 450;
 
 const TEAM_NAMES: [&str; 2] = ["WHITE", "BLACK"];
 
-fn simulate_game(tables: &mut [&mut transpos::Table; 2], search_configs: [SearchConfig; 2], starting_fen: &str, print: bool) -> Option<usize> {
-    let starting_board = fen::load_fen(starting_fen).unwrap();
-    let mut board = starting_board;
+// Finished games are appended here
+const PGN_OUTPUT_PATH: &str = "vcmp_games.pgn";
+
+// Returns the winning team and the game record
+fn simulate_game(tables: &mut [&mut transpos::Table; 2], search_configs: &[SearchConfig; 2], starting_fen: &str, print: bool) -> (Option<usize>, pgn::PgnGame) {
+    let mut game = Game::new(&fen::load_fen(starting_fen).unwrap());
+    let mut pgn_game = pgn::PgnGame::new(game.start_board());
     let mut clock_times = [GAME_CLOCK_TIME; 2];
-    let mut moves = Vec::new();
 
-    let finish_game = |board: &Board, moves: &Vec<Move>, msg: String| {
+    let finish_game = |game: &Game, pgn_game: &mut pgn::PgnGame, winning_team: Option<usize>, msg: String| {
+        pgn_game.result = match winning_team {
+            Some(0) => "1-0",
+            Some(_) => "0-1",
+            None => "1/2-1/2"
+        }.to_string();
+        pgn_game.set_tag("Termination", &msg);
+
         if print {
-            println!("Game over, {} (fen: {}), PGN:", msg, fen::make_fen(&board));
-            println!("{}", pgn::make_pgn(&starting_board, &moves).unwrap());
+            println!("Game over, {} (fen: {}), PGN:", msg, fen::make_fen(game.board()));
+            println!("{}", pgn::make_pgn(game.start_board(), game.moves()).unwrap());
         }
     };
 
-    let mut last_eval: Value = 0.0;
-    let mut position_counts = std::collections::HashMap::<u64, usize>::new();
-    while !is_game_over(&board) {
-
-        let search_config = search_configs[board.turn_idx];
-
-        *position_counts.entry(board.hash).or_insert(0) += 1;
-        if position_counts[&board.hash] >= 3 {
-            finish_game(&board, &moves, "draw by repetition".to_string());
-            return None; // Draw by repetition
+    let mut last_eval: Value = 0;
+    loop {
+        if let Some(result) = game.get_result() {
+            finish_game(&game, &mut pgn_game, result.winner_team_idx(), result.to_string());
+            return (result.winner_team_idx(), pgn_game);
         }
 
+        let board = *game.board();
+        let search_config = search_configs[board.turn_idx].clone();
+
         let start_time = Instant::now();
         let clock_time = clock_times[board.turn_idx];
 
         if clock_time <= 0.0 {
             let opp_wins = is_checkmate_possible(&board, 1 - board.turn_idx);
-            finish_game(&board, &moves, format!("player {}'s clock ran out ({})", TEAM_NAMES[board.turn_idx], if opp_wins { "lost" } else { "draw" }));
-            if opp_wins {
-                return Some(1 - board.turn_idx); // Black wins
-            } else {
-                return None;
-            }
+            let winning_team = if opp_wins { Some(1 - board.turn_idx) } else { None };
+            finish_game(&game, &mut pgn_game, winning_team, format!("player {}'s clock ran out ({})", TEAM_NAMES[board.turn_idx], if opp_wins { "lost" } else { "draw" }));
+            return (winning_team, pgn_game);
         }
 
         let mut time_state = time_manager::TimeState::new();
@@ -101,42 +71,32 @@ fn simulate_game(tables: &mut [&mut transpos::Table; 2], search_configs: [Search
             stop_flag: None,
             start_time,
             time_state: Some(time_state),
+            ponderhit_time: None,
             print_uci: false,
+            chess960: false,
 
             search_config
         };
-        let (best_move_idx, eval) = async_engine::do_search_thread(&board, tables[board.turn_idx], &async_search_config);
-        clock_times[board.turn_idx] += GAME_CLOCK_TIME_COMPLEMENT - start_time.elapsed().as_secs_f64();
+        let result = async_engine::do_search_thread(&board, game.prev_hashes(), tables[board.turn_idx], &async_search_config);
+        let (best_move, eval, depth) = (result.best_move, result.eval, result.depth);
+        let elapsed_time = start_time.elapsed().as_secs_f64();
+        clock_times[board.turn_idx] += GAME_CLOCK_TIME_COMPLEMENT - elapsed_time;
 
         if eval.abs() >= TRUNCATE_EVAL_THRESH && last_eval.abs() >= TRUNCATE_EVAL_THRESH {
             if eval.signum() == -last_eval.signum() {
                 // Engines both agree on a very high eval in the same absolute direction
                 // Truncate the game
-                let winning_team = if eval > 0.0 { board.turn_idx } else { 1 - board.turn_idx };
-                finish_game(&board, &moves, format!("truncated at eval {}, player {} wins", eval, TEAM_NAMES[winning_team]));
-                return Some(winning_team);
+                let winning_team = if eval > 0 { board.turn_idx } else { 1 - board.turn_idx };
+                finish_game(&game, &mut pgn_game, Some(winning_team), format!("truncated at eval {}, player {} wins", eval_to_str(eval), TEAM_NAMES[winning_team]));
+                return (Some(winning_team), pgn_game);
             }
         }
 
-        let mut move_buffer = move_gen::MoveBuffer::new();
-        move_gen::generate_moves(&board, &mut move_buffer);
-
-        assert!(best_move_idx.is_some());
-        assert!((best_move_idx.unwrap() as usize) < move_buffer.len());
-
-        let best_move = move_buffer[best_move_idx.unwrap() as usize];
-        board.do_move(&best_move);
-        moves.push(best_move);
+        let best_move = best_move.expect("No best move found");
+        game.do_move(&best_move);
+        pgn_game.add_move(best_move, Some(format!("{:+.2}/{} {:.1}s", eval as f64 / 100.0, depth, elapsed_time)));
         last_eval = eval;
     }
-
-    if board.checkers != 0 {
-        finish_game(&board, &moves, format!("{} won by checkmate", TEAM_NAMES[1 - board.turn_idx]));
-        Some(1 - board.turn_idx)
-    } else {
-        finish_game(&board, &moves, "draw".to_string());
-        None
-    }
 }
 
 struct GameResults {
@@ -189,9 +149,10 @@ fn main() {
     let mut search_config_new = SearchConfig::new();
     {
         // Here we decide how the new version should play differently by changing the search config
-        search_config_new.late_move_reduction_factor = 0.0;
+        search_config_new.principal_variation_search = true;
     }
-    let search_config_old = SearchConfig::new();
+    let mut search_config_old = SearchConfig::new();
+    search_config_old.principal_variation_search = false;
 
     const NUM_THREADS: usize = 10; // Number of threads to run in parallel
     const TABLE_SIZE_MBS: usize = 25; // Table size (there are two tables per thread)
@@ -199,6 +160,8 @@ fn main() {
     for thread_idx in 0..NUM_THREADS {
         let fen_stack_arc_clone = Arc::clone(&fen_stack_arc);
         println!("Launching thread {}/{}...", thread_idx + 1, NUM_THREADS);
+        let search_config_new = search_config_new.clone();
+        let search_config_old = search_config_old.clone();
         let handle = thread::spawn(move || {
             let mut rng = rand::rng();
             let mut tables = [
@@ -219,14 +182,23 @@ fn main() {
 
                 let new_team_idx = rng.random_range(0..2) as usize;
                 let search_configs = if new_team_idx == 0 {
-                    [search_config_new, search_config_old]
+                    [search_config_new.clone(), search_config_old.clone()]
                 } else {
-                    [search_config_old, search_config_new]
+                    [search_config_old.clone(), search_config_new.clone()]
                 };
 
-                let winning_team = simulate_game(&mut tables, search_configs, &cur_fen, true);
+                let (winning_team, mut pgn_game) = simulate_game(&mut tables, &search_configs, &cur_fen, true);
+                pgn_game.set_tag("Event", "BoardCrab version comparison");
+                pgn_game.set_tag("White", if new_team_idx == 0 { "new" } else { "old" });
+                pgn_game.set_tag("Black", if new_team_idx == 0 { "old" } else { "new" });
                 {
                     let mut game_results = fen_stack_arc_clone.lock().unwrap();
+                    pgn_game.set_tag("Round", &(game_results.total_games() + 1).to_string());
+
+                    // Holding the lock keeps games from different threads from interleaving
+                    let pgn_str = pgn::write_pgn(&pgn_game).unwrap();
+                    let mut pgn_file = std::fs::OpenOptions::new().create(true).append(true).open(PGN_OUTPUT_PATH).unwrap();
+                    writeln!(pgn_file, "{}", pgn_str).unwrap();
 
                     if winning_team.is_some() {
                         if new_team_idx == winning_team.unwrap() {
@@ -0,0 +1,606 @@
+#!/usr/bin/env python3
+# Generates the small 3 piece Syzygy tables in this directory, which are used by tests/syzygy_tests.rs
+# Usage: python3 generate_tables.py [output dir]
+#
+# These aren't copies of the official tables: they're solved from scratch by retrograde analysis, then written following the Syzygy format
+# (layout, indexing and pair/Huffman compression) as described by the original generator and prober
+# This doesn't share any code with src/syzygy.rs, but both were written from the same reading of the format, so these tables
+# can't catch a mistake that both make. Only the official tables can (see syzygy_real_tables_test)
+# Refs:
+# - https://github.com/syzygy1/tb (the original generator)
+# - https://github.com/jdart1/Fathom (the original prober)
+
+import heapq
+import os
+import struct
+import sys
+from collections import Counter
+
+# Squares are 0-63 with a1 = 0, b1 = 1, ..., h8 = 63
+def file_of(sq): return sq % 8
+def rank_of(sq): return sq // 8
+
+PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING = 1, 2, 3, 4, 5, 6
+PIECE_CHARS = {PAWN: 'P', KNIGHT: 'N', BISHOP: 'B', ROOK: 'R', QUEEN: 'Q'}
+BLACK = 8 # Added to the piece codes for black pieces
+
+WDL_LOSS, WDL_DRAW, WDL_WIN = -2, 0, 2
+
+###############################################################################
+# Move generation, for white's king and one other piece against black's king
+
+def _step_targets(sq, deltas):
+    targets = []
+    for dx, dy in deltas:
+        x, y = file_of(sq) + dx, rank_of(sq) + dy
+        if 0 <= x < 8 and 0 <= y < 8:
+            targets.append(x + y * 8)
+    return targets
+
+KING_DELTAS = [(dx, dy) for dx in (-1, 0, 1) for dy in (-1, 0, 1) if (dx, dy) != (0, 0)]
+KNIGHT_DELTAS = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)]
+ROOK_DIRS = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+BISHOP_DIRS = [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+
+KING_TARGETS = [_step_targets(sq, KING_DELTAS) for sq in range(64)]
+KNIGHT_TARGETS = [_step_targets(sq, KNIGHT_DELTAS) for sq in range(64)]
+
+def _rays(sq, dirs):
+    rays = []
+    for dx, dy in dirs:
+        ray = []
+        x, y = file_of(sq) + dx, rank_of(sq) + dy
+        while 0 <= x < 8 and 0 <= y < 8:
+            ray.append(x + y * 8)
+            x, y = x + dx, y + dy
+        rays.append(ray)
+    return rays
+
+PIECE_RAYS = {
+    ROOK: [_rays(sq, ROOK_DIRS) for sq in range(64)],
+    BISHOP: [_rays(sq, BISHOP_DIRS) for sq in range(64)],
+    QUEEN: [_rays(sq, ROOK_DIRS + BISHOP_DIRS) for sq in range(64)]
+}
+
+def kings_touch(sq1, sq2):
+    return abs(file_of(sq1) - file_of(sq2)) <= 1 and abs(rank_of(sq1) - rank_of(sq2)) <= 1
+
+# Squares a white piece attacks, where blockers are the other occupied squares
+def piece_attacks(piece, sq, blockers):
+    if piece == KNIGHT:
+        return KNIGHT_TARGETS[sq]
+    if piece == PAWN:
+        return _step_targets(sq, [(-1, 1), (1, 1)])
+
+    attacks = []
+    for ray in PIECE_RAYS[piece][sq]:
+        for target in ray:
+            attacks.append(target)
+            if target in blockers:
+                break
+    return attacks
+
+# A position is (white king, white piece, black king), with white's piece type fixed for each table
+def is_legal(piece, wk, x, bk, white_to_move):
+    if wk == x or wk == bk or x == bk or kings_touch(wk, bk):
+        return False
+    if piece == PAWN and rank_of(x) in (0, 7):
+        return False
+    if white_to_move and bk in piece_attacks(piece, x, (wk,)):
+        return False # Black can't be in check when it's white's move
+    return True
+
+# White's moves, as (is pawn move, promotion piece or None, new white king, new white piece square)
+def white_moves(piece, wk, x, bk):
+    moves = []
+    for target in KING_TARGETS[wk]:
+        if target != x and not kings_touch(target, bk):
+            moves.append((False, None, target, x))
+
+    if piece == PAWN:
+        pushes = [x + 8]
+        if rank_of(x) == 1 and x + 8 not in (wk, bk):
+            pushes.append(x + 16)
+        for target in pushes:
+            if target in (wk, bk):
+                break
+            if rank_of(target) == 7:
+                for promo_piece in (QUEEN, ROOK, BISHOP, KNIGHT):
+                    moves.append((True, promo_piece, wk, target))
+            else:
+                moves.append((True, None, wk, target))
+    else:
+        for target in piece_attacks(piece, x, (wk, bk)):
+            if target not in (wk, bk):
+                moves.append((False, None, wk, target))
+    return moves
+
+# Black's moves, as (is capture, new black king)
+def black_moves(piece, wk, x, bk):
+    moves = []
+    for target in KING_TARGETS[bk]:
+        if target == wk or kings_touch(target, wk):
+            continue
+        if target == x:
+            moves.append((True, target)) # Only legal when the king doesn't defend it, which the check above covers
+        elif target not in piece_attacks(piece, x, (wk,)):
+            moves.append((False, target))
+    return moves
+
+def black_in_check(piece, wk, x, bk):
+    return bk in piece_attacks(piece, x, (wk,))
+
+###############################################################################
+# Solving
+# dtz[stm][pos] is the distance to zeroing (or mate) in plies for the winner (white) and the loser (black), 0 for draws and None for illegal positions
+# Like in Syzygy, being checkmated is 1, and a move that mates or makes a winning pawn move is 1
+
+def pos_idx(wk, x, bk):
+    return (wk * 64 + x) * 64 + bk
+
+def pos_squares(pos):
+    return pos // 4096, (pos // 64) % 64, pos % 64
+
+class Solved:
+    def __init__(self, piece):
+        self.piece = piece
+        self.dtz = [[None] * (64 * 64 * 64), [None] * (64 * 64 * 64)]
+
+    def wdl(self, stm, wk, x, bk):
+        value = self.dtz[stm][pos_idx(wk, x, bk)]
+        if not value:
+            return WDL_DRAW
+        return WDL_WIN if stm == 0 else WDL_LOSS
+
+# Solves the positions with white's piece on one of the given squares
+# Pawn moves leave these squares, so pawn endings are solved one pawn square at a time, from the 7th rank down
+def _solve_squares(solved, piece_squares, promotion_tables):
+    piece = solved.piece
+    dtz = solved.dtz
+
+    white_positions = []
+    black_positions = []
+    white_children = {}
+    white_zeroing_win = set()
+    black_children = {}
+    for x in piece_squares:
+        for wk in range(64):
+            for bk in range(64):
+                pos = pos_idx(wk, x, bk)
+                if is_legal(piece, wk, x, bk, True):
+                    dtz[0][pos] = 0
+                    children = []
+                    for is_pawn_move, promo_piece, new_wk, new_x in white_moves(piece, wk, x, bk):
+                        if is_pawn_move:
+                            child_table = promotion_tables[promo_piece] if promo_piece else solved
+                            if child_table.wdl(1, new_wk, new_x, bk) == WDL_LOSS:
+                                white_zeroing_win.add(pos)
+                        else:
+                            children.append(pos_idx(new_wk, new_x, bk))
+                    white_positions.append(pos)
+                    white_children[pos] = children
+
+                if is_legal(piece, wk, x, bk, False):
+                    dtz[1][pos] = 0
+                    moves = black_moves(piece, wk, x, bk)
+                    if not moves and black_in_check(piece, wk, x, bk):
+                        dtz[1][pos] = 1 # Checkmated
+                    elif moves and not any(is_capture for is_capture, _ in moves):
+                        # Capturing the piece (or stalemate) is always a draw
+                        black_positions.append(pos)
+                        black_children[pos] = [pos_idx(wk, x, new_bk) for _, new_bk in moves]
+
+    # Each round finds the white wins with a DTZ of d, and then the black losses that it completes
+    # White's DTZs are odd and black's are even, so nothing new for two rounds in a row means the rest are draws
+    d = 1
+    last_found = 0
+    while d - last_found <= 2:
+        remaining_white = []
+        found = False
+        for pos in white_positions:
+            children_dtz = [dtz[1][child] for child in white_children[pos]]
+            if d == 1 and (pos in white_zeroing_win or 1 in children_dtz):
+                dtz[0][pos] = 1
+                found = True
+            elif d > 1 and (d - 1) in children_dtz:
+                dtz[0][pos] = d
+                found = True
+            else:
+                remaining_white.append(pos)
+        white_positions = remaining_white
+
+        remaining_black = []
+        for pos in black_positions:
+            children_dtz = [dtz[0][child] for child in black_children[pos]]
+            if all(children_dtz):
+                dtz[1][pos] = max(children_dtz) + 1
+                found = True
+            else:
+                remaining_black.append(pos)
+        black_positions = remaining_black
+
+        if found:
+            last_found = d
+        d += 1
+
+def solve(piece, promotion_tables=None):
+    solved = Solved(piece)
+    if piece == PAWN:
+        for rank in range(6, 0, -1):
+            _solve_squares(solved, [file + rank * 8 for file in range(8)], promotion_tables)
+    else:
+        _solve_squares(solved, range(64), None)
+    return solved
+
+###############################################################################
+# Indexing, like encode_piece() and encode_pawn() in the original prober
+
+def _off_diag(sq):
+    return rank_of(sq) - file_of(sq)
+
+def _make_index_tables():
+    global MAP_B1H1H7, MAP_A1D1D4, BINOMIAL, PAWN_IDX, PAWN_FACTOR
+    MAP_B1H1H7 = [0] * 64
+    code = 0
+    for sq in range(64):
+        if _off_diag(sq) < 0:
+            MAP_B1H1H7[sq] = code
+            code += 1
+
+    MAP_A1D1D4 = [0] * 64
+    code = 0
+    diagonal = []
+    for sq in range(28):
+        if file_of(sq) <= 3:
+            if _off_diag(sq) < 0:
+                MAP_A1D1D4[sq] = code
+                code += 1
+            elif _off_diag(sq) == 0:
+                diagonal.append(sq)
+    for sq in diagonal:
+        MAP_A1D1D4[sq] = code
+        code += 1
+
+    BINOMIAL = [[0] * 64 for _ in range(7)]
+    for k in range(7):
+        for n in range(64):
+            f, l = 1, 1
+            for i in range(k):
+                f *= n - i
+                l *= i + 1
+            BINOMIAL[k][n] = f // l
+
+    # With a single leading pawn, its index is just its rank on its file
+    PAWN_IDX = [0] * 64
+    for sq in range(8, 56):
+        PAWN_IDX[sq] = rank_of(sq) - 1
+    PAWN_FACTOR = 6
+
+_make_index_tables()
+
+# Index for three unique pieces in the table's piece order, in a table without pawns
+def encode_piece(squares):
+    squares = list(squares)
+    if file_of(squares[0]) > 3:
+        squares = [sq ^ 7 for sq in squares]
+    if rank_of(squares[0]) > 3:
+        squares = [sq ^ 56 for sq in squares]
+    for i in range(3):
+        if _off_diag(squares[i]) == 0:
+            continue
+        if _off_diag(squares[i]) > 0:
+            squares = squares[:i] + [((sq >> 3) | (sq << 3)) & 63 for sq in squares[i:]]
+        break
+
+    s0, s1, s2 = squares
+    adjust1 = int(s1 > s0)
+    adjust2 = int(s2 > s0) + int(s2 > s1)
+    if _off_diag(s0):
+        return (MAP_A1D1D4[s0] * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+    elif _off_diag(s1):
+        return (6 * 63 + rank_of(s0) * 28 + MAP_B1H1H7[s1]) * 62 + s2 - adjust2
+    elif _off_diag(s2):
+        return 6 * 63 * 62 + 4 * 28 * 62 + rank_of(s0) * 7 * 28 + (rank_of(s1) - adjust1) * 28 + MAP_B1H1H7[s2]
+    else:
+        return 6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank_of(s0) * 7 * 6 + (rank_of(s1) - adjust1) * 6 + (rank_of(s2) - adjust2)
+
+PIECE_TABLE_SIZE = 31332
+
+# Index for a pawn followed by two pieces, and the leading pawn's file (a-d)
+def encode_pawn(squares):
+    squares = list(squares)
+    if file_of(squares[0]) > 3:
+        squares = [sq ^ 7 for sq in squares]
+    idx = PAWN_IDX[squares[0]]
+    factor = PAWN_FACTOR
+    for i in range(1, 3):
+        adjust = sum(1 for other in squares[:i] if squares[i] > other)
+        idx += (squares[i] - adjust) * factor
+        factor *= 64 - i
+    return idx, file_of(squares[0])
+
+PAWN_TABLE_SIZE = 6 * 63 * 62
+
+###############################################################################
+# Compression
+
+MAX_SYMS = 4095
+MAX_SYM_VALUES = 256 # Max values a symbol can expand to
+BLOCK_SIZE_BITS = 6
+SPAN_BITS = 6
+MAX_BLOCK_VALUES = 60000
+
+# Repeatedly replaces the most common pair of adjacent symbols with a new symbol (Re-Pair)
+def _pair_symbols(values):
+    leaf_values = sorted(set(values))
+    syms = [(value, None) for value in leaf_values] # (left, right), or (value, None) for leaves
+    sym_values = [1] * len(syms)
+    leaf_syms = {value: sym for sym, value in enumerate(leaf_values)}
+    seq = [leaf_syms[value] for value in values]
+
+    while len(syms) < 200:
+        pair_counts = Counter(zip(seq, seq[1:]))
+        candidates = [(count, pair) for pair, count in pair_counts.items() if sym_values[pair[0]] + sym_values[pair[1]] <= MAX_SYM_VALUES]
+        if not candidates:
+            break
+        count, pair = max(candidates)
+        if count < 16:
+            break
+
+        new_sym = len(syms)
+        syms.append(pair)
+        sym_values.append(sym_values[pair[0]] + sym_values[pair[1]])
+        new_seq = []
+        i = 0
+        while i < len(seq):
+            if i + 1 < len(seq) and (seq[i], seq[i + 1]) == pair:
+                new_seq.append(new_sym)
+                i += 2
+            else:
+                new_seq.append(seq[i])
+                i += 1
+        seq = new_seq
+
+    return syms, sym_values, seq
+
+def _huffman_lens(freqs):
+    heap = [(freq, i, [sym]) for i, (sym, freq) in enumerate(freqs.items())]
+    heapq.heapify(heap)
+    lens = {sym: 0 for sym in freqs}
+    tie = len(heap)
+    while len(heap) > 1:
+        freq1, _, syms1 = heapq.heappop(heap)
+        freq2, _, syms2 = heapq.heappop(heap)
+        for sym in syms1 + syms2:
+            lens[sym] += 1
+        heapq.heappush(heap, (freq1 + freq2, tie, syms1 + syms2))
+        tie += 1
+    return lens
+
+# A table part compressed with Re-Pair and Huffman codes, split into the sections of the file it goes in
+class Compressed:
+    def __init__(self, values, flags):
+        self.flags = flags
+        self.values = values
+
+        if len(set(values)) == 1:
+            self.flags |= 0x80
+            self.info = bytes([self.flags, values[0]])
+            self.sparse_index = b''
+            self.block_lengths = b''
+            self.data = b''
+            return
+
+        syms, sym_values, seq = _pair_symbols(values)
+        lens = _huffman_lens(Counter(seq))
+        min_len, max_len = min(lens.values()), max(lens.values())
+        assert max_len <= 32, "Symbol too long"
+
+        # Canonical code where longer symbols have lower codes, and the symbols are renumbered so that longer ones come first
+        num_lens = max_len - min_len + 1
+        coded_syms = sorted(lens, key=lambda sym: (-lens[sym], sym))
+        uncoded_syms = [sym for sym in range(len(syms)) if sym not in lens]
+        new_ids = {sym: new_id for new_id, sym in enumerate(coded_syms + uncoded_syms)}
+        counts = [sum(1 for sym in lens if lens[sym] == min_len + i) for i in range(num_lens)]
+        lowest_sym = [0] * num_lens
+        base = [0] * num_lens
+        for i in range(num_lens - 2, -1, -1):
+            lowest_sym[i] = lowest_sym[i + 1] + counts[i + 1]
+            assert (base[i + 1] + counts[i + 1]) % 2 == 0
+            base[i] = (base[i + 1] + counts[i + 1]) // 2
+        codes = {}
+        for sym in lens:
+            i = lens[sym] - min_len
+            codes[sym] = (base[i] + new_ids[sym] - lowest_sym[i], lens[sym])
+
+        btree = bytearray()
+        for sym in coded_syms + uncoded_syms:
+            left, right = syms[sym]
+            if right is None:
+                right = 0xFFF
+            else:
+                left, right = new_ids[left], new_ids[right]
+            btree += bytes([left & 0xFF, ((left >> 8) & 0xF) | ((right & 0xF) << 4), right >> 4])
+        assert len(syms) <= MAX_SYMS
+
+        # Blocks hold whole symbols, starting from the top bit
+        block_size = 1 << BLOCK_SIZE_BITS
+        blocks = [] # (first value, number of values, bytes)
+        bits, num_bits, block_start, block_values = 0, 0, 0, 0
+        def finish_block():
+            blocks.append((block_start, block_values, (bits << (block_size * 8 - num_bits)).to_bytes(block_size, 'big')))
+        for sym in seq:
+            code, code_len = codes[sym]
+            if num_bits + code_len > block_size * 8 or block_values + sym_values[sym] > MAX_BLOCK_VALUES:
+                finish_block()
+                bits, num_bits, block_start, block_values = 0, 0, block_start + block_values, 0
+            bits = (bits << code_len) | code
+            num_bits += code_len
+            block_values += sym_values[sym]
+        finish_block()
+
+        # Every span-th value (from the middle of the span) gets the block it's in, and where it is in that block
+        span = 1 << SPAN_BITS
+        sparse_index = bytearray()
+        block = 0
+        for k in range((len(values) + span - 1) // span):
+            target = k * span + span // 2
+            while block + 1 < len(blocks) and blocks[block + 1][0] <= target:
+                block += 1
+            sparse_index += struct.pack('<IH', block, target - blocks[block][0])
+
+        self.info = bytes([self.flags, BLOCK_SIZE_BITS, SPAN_BITS, 0]) + struct.pack('<I', len(blocks)) + bytes([max_len, min_len])
+        self.info += b''.join(struct.pack('<H', lowest_sym[i]) for i in range(num_lens))
+        self.info += struct.pack('<H', len(syms)) + bytes(btree)
+        if len(syms) % 2:
+            self.info += b'\0'
+        self.sparse_index = bytes(sparse_index)
+        self.block_lengths = b''.join(struct.pack('<H', num_values - 1) for _, num_values, _ in blocks)
+        self.data = b''.join(block_data for _, _, block_data in blocks)
+
+# Positions that can't happen (or aren't reachable by the index) can have any value, so copy the one before them
+def _fill_unused(values):
+    last = next((value for value in values if value is not None), 0)
+    for i, value in enumerate(values):
+        if value is None:
+            values[i] = last
+        else:
+            last = value
+    return values
+
+###############################################################################
+# Table files
+
+WDL_MAGIC = bytes([0x71, 0xE8, 0x23, 0x5D])
+DTZ_MAGIC = bytes([0xD7, 0x66, 0x0C, 0xA5])
+
+FLAG_STM = 1
+FLAG_MAPPED = 2
+FLAG_WIN_PLIES = 4
+FLAG_LOSS_PLIES = 8
+
+# Collects the values for each index, checking that positions with the same index agree
+def _index_values(piece, value_fn, num_files, table_size):
+    tables = [[None] * table_size for _ in range(num_files)]
+    for wk in range(64):
+        for x in range(64):
+            for bk in range(64):
+                value = value_fn(wk, x, bk)
+                if value is None:
+                    continue
+                if piece == PAWN:
+                    idx, file = encode_pawn([x, wk, bk])
+                else:
+                    idx, file = encode_piece([wk, x, bk]), 0
+                assert tables[file][idx] in (None, value), "Symmetric positions have different values"
+                tables[file][idx] = value
+    return tables
+
+def write_table(path, magic, piece, compressed, dtz_maps=None):
+    # Tables with a pawn have a part for each file of the leading pawn (a-d), with the pawn first
+    data = bytearray(magic)
+    data.append(1 | (2 if piece == PAWN else 0)) # Split (sides are different), has pawns
+    piece_order = [PAWN, KING, KING | BLACK] if piece == PAWN else [KING, piece, KING | BLACK]
+    for _ in compressed:
+        data.append(0) # The first group is indexed first for both sides
+        for piece_code in piece_order:
+            data.append(piece_code | (piece_code << 4)) # Same order for both sides
+    if len(data) % 2:
+        data.append(0)
+
+    for file_parts in compressed:
+        for part in file_parts:
+            data += part.info
+
+    if dtz_maps is not None:
+        for file_maps in dtz_maps:
+            for dtz_map in file_maps:
+                data.append(len(dtz_map))
+                data += bytes(dtz_map)
+        if len(data) % 2:
+            data.append(0)
+
+    for file_parts in compressed:
+        for part in file_parts:
+            data += part.sparse_index
+    for file_parts in compressed:
+        for part in file_parts:
+            data += part.block_lengths
+    for file_parts in compressed:
+        for part in file_parts:
+            data += b'\0' * (-len(data) % 64)
+            data += part.data
+
+    # Decoding reads a little past the end of a block
+    data += b'\0' * 64
+
+    with open(path, 'wb') as f:
+        f.write(data)
+
+def write_wdl(out_dir, name, solved):
+    piece = solved.piece
+    num_files = 4 if piece == PAWN else 1
+    table_size = PAWN_TABLE_SIZE if piece == PAWN else PIECE_TABLE_SIZE
+
+    sides = []
+    for stm in range(2):
+        def value_fn(wk, x, bk, stm=stm):
+            if solved.dtz[stm][pos_idx(wk, x, bk)] is None:
+                return None
+            return solved.wdl(stm, wk, x, bk) + 2
+        sides.append(_index_values(piece, value_fn, num_files, table_size))
+
+    compressed = [[Compressed(_fill_unused(sides[stm][file]), 0) for stm in range(2)] for file in range(num_files)]
+    write_table(os.path.join(out_dir, name + '.rtbw'), WDL_MAGIC, piece, compressed)
+
+# DTZ tables only store one side to move
+def write_dtz(out_dir, name, solved, stm):
+    piece = solved.piece
+    num_files = 4 if piece == PAWN else 1
+    table_size = PAWN_TABLE_SIZE if piece == PAWN else PIECE_TABLE_SIZE
+
+    def value_fn(wk, x, bk):
+        return solved.dtz[stm][pos_idx(wk, x, bk)]
+    files = _index_values(piece, value_fn, num_files, table_size)
+
+    compressed = []
+    dtz_maps = []
+    for file_values in files:
+        # Values are stored in plies (minus one), mapped so that the most common ones for each result are the lowest
+        counts = Counter(value - 1 for value in file_values if value)
+        dtz_map = [value for value, _ in sorted(counts.items(), key=lambda item: (-item[1], item[0]))]
+        assert len(dtz_map) <= 255 and all(value < 100 for value in dtz_map), "Cursed results aren't supported"
+        mapped = {value + 1: i for i, value in enumerate(dtz_map)}
+        stored = [mapped[value] if value else None for value in file_values]
+
+        # Maps are for a win, loss, cursed win and blessed loss
+        maps = [[], [], [], []]
+        maps[0 if stm == 0 else 1] = dtz_map
+        dtz_maps.append(maps)
+
+        flags = (FLAG_STM if stm else 0) | FLAG_MAPPED | FLAG_WIN_PLIES | FLAG_LOSS_PLIES
+        compressed.append([Compressed(_fill_unused(stored), flags)])
+
+    write_table(os.path.join(out_dir, name + '.rtbz'), DTZ_MAGIC, piece, compressed, dtz_maps)
+
+def main():
+    out_dir = sys.argv[1] if len(sys.argv) > 1 else os.path.dirname(os.path.abspath(__file__))
+    os.makedirs(out_dir, exist_ok=True)
+
+    promotion_tables = {}
+    for piece in (QUEEN, ROOK, BISHOP, KNIGHT):
+        name = 'K' + PIECE_CHARS[piece] + 'vK'
+        print('Solving', name)
+        promotion_tables[piece] = solve(piece)
+        write_wdl(out_dir, name, promotion_tables[piece])
+        if piece in (QUEEN, ROOK):
+            write_dtz(out_dir, name, promotion_tables[piece], 0)
+
+    # The pawn table stores the losing side's DTZ, so that both sides get tested
+    print('Solving KPvK')
+    solved = solve(PAWN, promotion_tables)
+    write_wdl(out_dir, 'KPvK', solved)
+    write_dtz(out_dir, 'KPvK', solved, 1)
+
+if __name__ == '__main__':
+    main()
@@ -1,8 +1,9 @@
 use std::thread;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use std::time::{Instant, Duration};
 use crate::board::*;
-use crate::move_gen;
+use crate::game::Game;
 use crate::search;
 use crate::eval::*;
 use crate::search::SearchConfig;
@@ -10,39 +11,58 @@ use crate::transpos;
 use crate::thread_flag::ThreadFlag;
 use crate::uci;
 use crate::time_manager;
+use crate::zobrist::Hash;
+use crate::syzygy;
+use crate::tablebase;
 
 pub struct AsyncSearchConfig<'a> {
     pub max_depth: Option<u8>,
     pub stop_flag: Option<&'a ThreadFlag>,
     pub start_time: Instant,
     pub time_state: Option<time_manager::TimeState>,
+    pub ponderhit_time: Option<&'a OnceLock<Instant>>, // Only for ponder searches, which ignore time_state until this is set
     pub print_uci: bool,
+    pub chess960: bool, // Use Chess960 castling notation when printing UCI
 
     pub search_config: SearchConfig
 }
 
-pub fn do_search_thread(board: &Board, table: &mut transpos::Table, async_search_cfg: &AsyncSearchConfig) -> (Option<u8>, Value) {
+#[derive(Debug, Copy, Clone)]
+pub struct SearchThreadResult {
+    pub best_move: Option<Move>,
+    pub eval: Value,
+    pub ponder_move: Option<Move>, // The expected reply to the best move
+    pub depth: u8, // The last depth that was fully searched
+    pub total_nodes: usize
+}
+
+// prev_hashes are the hashes of the game's positions before this board (see Game::prev_hashes())
+pub fn do_search_thread(board: &Board, prev_hashes: &[Hash], table: &transpos::Table, async_search_cfg: &AsyncSearchConfig) -> SearchThreadResult {
+    table.new_search();
+    _do_search_thread(board, prev_hashes, table, async_search_cfg, None)
+}
 
+// Returns the time we'd like to use (the soft limit, if there is one), and the time to stop searching by, counted from start_time
+fn _get_time_limits(board: &Board, time_state: Option<time_manager::TimeState>, start_time: Instant) -> (Option<f64>, Option<Instant>) {
     // Only exists if we have a soft time limit
     let mut max_time_to_use: Option<f64> = None;
 
     // The time at which we should stop searching, either due to a soft or hard limit
     let mut stop_time: Option<Instant> = None;
 
-    if async_search_cfg.time_state.is_some() {
+    if let Some(time_state) = time_state {
         // Possibly determine the maximum time to use (this will be our soft time limit)
-        let time_state = async_search_cfg.time_state.clone().unwrap();
         max_time_to_use = time_manager::get_max_time_to_use(board, &time_state);
 
         let mut soft_stop_time: Option<Instant> = None;
         if max_time_to_use.is_some() {
-            soft_stop_time = Some(async_search_cfg.start_time + Duration::from_secs_f64(max_time_to_use.unwrap()));
+            soft_stop_time = Some(start_time + Duration::from_secs_f64(max_time_to_use.unwrap()));
         }
 
         let mut hard_stop_time: Option<Instant> = None;
-        let hard_max_time = time_state.hard_max_time.clone();
+        let hard_max_time = time_state.hard_max_time;
         if hard_max_time.is_some() {
-            hard_stop_time = Some(async_search_cfg.start_time + Duration::from_secs_f64(hard_max_time.unwrap()));
+            hard_stop_time = Some(start_time + Duration::from_secs_f64(hard_max_time.unwrap()));
         }
 
         if soft_stop_time.is_some() && hard_stop_time.is_some() {
@@ -56,145 +76,450 @@ pub fn do_search_thread(board: &Board, table: &mut transpos::Table, async_search
         }
     }
 
+    (max_time_to_use, stop_time)
+}
+
+// shared_depth is the deepest depth that any thread has finished, so that helpers can skip ahead to depths that haven't been searched yet
+// The main thread still searches every depth, since its results are the ones that are reported and used for time management
+fn _do_search_thread(
+    board: &Board, prev_hashes: &[Hash], table: &transpos::Table, async_search_cfg: &AsyncSearchConfig,
+    shared_depth: Option<&AtomicU8>) -> SearchThreadResult {
+
+    // Ponder searches have no time limits until the ponderhit, and then count their time from it
+    let mut time_start = match async_search_cfg.ponderhit_time {
+        Some(ponderhit_time) => ponderhit_time.get().copied(),
+        None => Some(async_search_cfg.start_time)
+    };
+    let (mut max_time_to_use, mut stop_time) = match time_start {
+        Some(time_start) => _get_time_limits(board, async_search_cfg.time_state, time_start),
+        None => (None, None)
+    };
+
+    // With a tablebase, only search the moves that keep the best result
+    let mut search_config = async_search_cfg.search_config.clone();
+    if let Some(tablebase) = &search_config.tablebase {
+        if let Some(tb_moves) = tablebase.filter_root_moves(board, prev_hashes) {
+            if search_config.root_moves.is_empty() {
+                search_config.root_moves = tb_moves;
+            } else {
+                let filtered_moves: Vec<Move> = search_config.root_moves.iter().copied().filter(|mv| tb_moves.contains(mv)).collect();
+                if !filtered_moves.is_empty() {
+                    search_config.root_moves = filtered_moves;
+                }
+            }
+        }
+    }
+
+    // Nodes are counted over the whole search, including the other threads' nodes with SMP (see do_smp_search())
+    let shared_nodes = search_config.shared_nodes.get_or_insert_with(|| Arc::new(AtomicUsize::new(0))).clone();
+
     let mut best_moves = Vec::new();
+    let mut ponder_move: Option<Move> = None;
+    let mut total_nodes: usize = 0;
+    let mut completed_depth: u8 = 0;
     let mut guessed_next_eval: Option<Value> = None;
+    let mut guessed_next_evals: Vec<Value> = Vec::new(); // For each PV, in ranked order
     let max_depth = if async_search_cfg.max_depth.is_some() {
         async_search_cfg.max_depth.unwrap()
     } else {
         u8::MAX
     };
-    for depth_minus_one in 0..max_depth {
-        let depth = depth_minus_one + 1;
+    while completed_depth < max_depth {
+        if time_start.is_none() {
+            if let Some(ponderhit_time) = async_search_cfg.ponderhit_time.and_then(|ponderhit_time| ponderhit_time.get()) {
+                time_start = Some(*ponderhit_time);
+                (max_time_to_use, stop_time) = _get_time_limits(board, async_search_cfg.time_state, *ponderhit_time);
+            }
+        }
+
+        let mut depth = completed_depth + 1;
+        if let Some(shared_depth) = shared_depth.filter(|_| search_config.helper_idx > 0) {
+            // Half of the helpers search a depth ahead, so that the threads aren't all on the same depth
+            let depth_offset = (search_config.helper_idx % 2) as u8;
+            let next_shared_depth = shared_depth.load(Ordering::Relaxed).saturating_add(1 + depth_offset);
+            depth = depth.max(next_shared_depth).min(max_depth);
+        }
+
+        // The node limit is for the whole search, not each depth
+        if search_config.max_nodes.is_some_and(|max_nodes| shared_nodes.load(Ordering::Relaxed) >= max_nodes) {
+            break;
+        }
 
         {
-            let (search_eval, search_info) = search::search(
-                &board, table, &async_search_cfg.search_config, depth,
-                guessed_next_eval,
+            let search_results = search::search_multi_pv(
+                &board, prev_hashes, table, &search_config, depth,
+                &guessed_next_evals,
                 async_search_cfg.stop_flag, stop_time
             );
 
-            if search_eval.is_infinite() {
-                // Search aborted
-                break;
-            }
+            let search_results = match search_results {
+                Some(search_results) => search_results,
+                None => break // Search aborted
+            };
 
+            let (search_eval, search_info) = &search_results[0];
             if search_info.root_best_move.is_some() {
                 best_moves.push(search_info.root_best_move.unwrap());
             }
+            ponder_move = search_info.get_pv().get(1).copied();
+            total_nodes += search_info.total_nodes;
+            completed_depth = depth;
+            if let Some(shared_depth) = shared_depth {
+                shared_depth.fetch_max(depth, Ordering::Relaxed);
+            }
 
-            guessed_next_eval = Some(search_eval);
+            guessed_next_eval = Some(*search_eval);
+            guessed_next_evals = search_results.iter().map(|(search_eval, _)| *search_eval).collect();
 
             let cur_time = Instant::now();
             let elapsed_time_f64 = (cur_time - async_search_cfg.start_time).as_secs_f64();
             if async_search_cfg.print_uci {
                 // TODO: Somewhat lame to be calling UCI stuff from async_engine
 
-                uci::print_search_results(&board, table, depth, search_eval, &search_info, elapsed_time_f64);
+                for (pv_idx, (pv_eval, pv_search_info)) in search_results.iter().enumerate() {
+                    uci::print_search_results(
+                        depth, pv_idx + 1, *pv_eval, pv_search_info, shared_nodes.load(Ordering::Relaxed), elapsed_time_f64, async_search_cfg.chess960
+                    );
+                }
+            }
+
+            if let (Some(max_time_to_use), Some(time_start)) = (max_time_to_use, time_start) {
+                if time_manager::should_exit_early(max_time_to_use, (cur_time - time_start).as_secs_f64(), &best_moves) {
+                    break;
+                }
             }
 
-            if max_time_to_use.is_some() {
-                if time_manager::should_exit_early(max_time_to_use.unwrap(), elapsed_time_f64, &best_moves) {
+            if let Some(mate_moves) = search_config.mate_moves {
+                if get_mate_moves(*search_eval).is_some_and(|search_mate_moves| search_mate_moves > 0 && search_mate_moves <= mate_moves as i32) {
+                    // Found a short enough mate
                     break;
                 }
             }
         }
     }
 
-    if best_moves.len() > 0 {
-        (Some(*best_moves.last().unwrap()), guessed_next_eval.unwrap())
-    } else {
-        (None, guessed_next_eval.unwrap())
+    // Helpers can be stopped before they finish any depth
+    SearchThreadResult {
+        best_move: best_moves.last().copied(),
+        eval: guessed_next_eval.unwrap_or(0),
+        ponder_move,
+        depth: completed_depth,
+        total_nodes
+    }
+}
+
+// Picks the best result out of all of the threads' results
+// Each thread votes for its best move, weighted by how deep it searched and how good its eval is compared to the other threads
+// See https://www.chessprogramming.org/Lazy_SMP
+pub fn vote_best_result(results: &[SearchThreadResult]) -> Option<SearchThreadResult> {
+    let voting_results: Vec<&SearchThreadResult> = results.iter().filter(|result| result.best_move.is_some()).collect();
+    let min_eval = voting_results.iter().map(|result| result.eval).fold(Value::MAX, Value::min);
+
+    let mut votes: Vec<(Move, Value)> = Vec::new();
+    for result in &voting_results {
+        let best_move = result.best_move.unwrap();
+        let vote = (result.eval - min_eval + 15) * result.depth as Value;
+        match votes.iter_mut().find(|(mv, _)| *mv == best_move) {
+            Some((_, move_votes)) => *move_votes += vote,
+            None => votes.push((best_move, vote))
+        }
+    }
+
+    // Ties go to the move found first, which is the main thread's move
+    let mut best_vote: Option<(Move, Value)> = None;
+    for (mv, move_votes) in votes {
+        if best_vote.is_none_or(|(_, best_votes)| move_votes > best_votes) {
+            best_vote = Some((mv, move_votes));
+        }
+    }
+
+    // Use the deepest result for the winning move, for its eval and ponder move
+    let (best_move, _) = best_vote?;
+    let mut best_result: Option<SearchThreadResult> = None;
+    for result in voting_results {
+        if result.best_move == Some(best_move) && best_result.is_none_or(|best_result| result.depth > best_result.depth) {
+            best_result = Some(*result);
+        }
+    }
+    best_result
+}
+
+// Lazy SMP: helper threads search the same position with the shared table, which fills the table with results the main thread can use
+// Helpers are varied by depth and move order, so they don't all search the same moves
+// The main thread uses the limits in async_search_cfg, and decides when to stop, while the helpers are stopped along with it
+// Helpers only share the node limit, which counts the nodes of every thread
+// The returned node count is the total for all threads
+pub fn do_smp_search(
+    board: &Board, prev_hashes: &[Hash], table: &transpos::Table, async_search_cfg: &AsyncSearchConfig,
+    num_threads: usize) -> SearchThreadResult {
+
+    table.new_search();
+    let shared_depth = AtomicU8::new(0);
+    let helper_stop_flag = ThreadFlag::new();
+    let mut main_search_config = async_search_cfg.search_config.clone();
+    main_search_config.shared_nodes.get_or_insert_with(|| Arc::new(AtomicUsize::new(0)));
+
+    let results: Vec<SearchThreadResult> = thread::scope(|scope| {
+        let mut helper_handles = Vec::new();
+        for helper_idx in 1..num_threads {
+            let mut search_config = main_search_config.clone();
+            search_config.helper_idx = helper_idx;
+            search_config.mate_moves = None;
+
+            let helper_search_cfg = AsyncSearchConfig {
+                max_depth: async_search_cfg.max_depth,
+                stop_flag: Some(&helper_stop_flag),
+                start_time: async_search_cfg.start_time,
+                time_state: None,
+                ponderhit_time: None,
+                print_uci: false,
+                chess960: async_search_cfg.chess960,
+
+                search_config
+            };
+
+            let shared_depth = &shared_depth;
+            helper_handles.push(
+                scope.spawn(move || _do_search_thread(board, prev_hashes, table, &helper_search_cfg, Some(shared_depth)))
+            );
+        }
+
+        let main_search_cfg = AsyncSearchConfig {
+            max_depth: async_search_cfg.max_depth,
+            stop_flag: async_search_cfg.stop_flag,
+            start_time: async_search_cfg.start_time,
+            time_state: async_search_cfg.time_state,
+            ponderhit_time: async_search_cfg.ponderhit_time,
+            print_uci: async_search_cfg.print_uci,
+            chess960: async_search_cfg.chess960,
+
+            search_config: main_search_config
+        };
+        let main_result = _do_search_thread(board, prev_hashes, table, &main_search_cfg, Some(&shared_depth));
+        helper_stop_flag.clone().trigger();
+
+        let mut results = vec![main_result];
+        for handle in helper_handles {
+            results.push(handle.join().expect("Search helper thread crashed"));
+        }
+        results
+    });
+
+    let total_nodes = results.iter().map(|result| result.total_nodes).sum();
+    let mut result = vote_best_result(&results).unwrap_or(results[0]);
+    result.total_nodes = total_nodes;
+    result
+}
+
+// Stops a ponder search at its time limit once the ponderhit comes, even in the middle of a depth
+// The search itself only picks up its time limits when it starts the next depth
+fn _watch_ponder_search(
+    board: &Board, time_state: Option<time_manager::TimeState>, ponderhit_time: &OnceLock<Instant>,
+    stop_flag: &ThreadFlag, search_done_flag: &ThreadFlag) {
+
+    let mut stop_time: Option<Instant> = None;
+    while !search_done_flag.get() && !stop_flag.get() {
+        if let Some(stop_time) = stop_time {
+            if Instant::now() >= stop_time {
+                stop_flag.clone().trigger();
+                break;
+            }
+        } else if let Some(ponderhit_time) = ponderhit_time.get() {
+            stop_time = _get_time_limits(board, time_state, *ponderhit_time).1;
+            if stop_time.is_none() {
+                break; // No time limits
+            }
+        }
+
+        thread::sleep(Duration::from_millis(1));
     }
 }
 
 pub struct AsyncEngine {
-    board: Board,
+    game: Game,
     arc_table: Arc<transpos::Table>,
     stop_flag: ThreadFlag,
-    thread_join_handles: Vec<thread::JoinHandle<Option<u8>>> // Outputs best move idx
+    chess960: bool,
+    multi_pv: usize,
+    tablebase: Option<Arc<syzygy::Tablebase>>,
+    dtm_tablebase: Option<Arc<tablebase::DtmTablebase>>,
+    ponderhit_time: Option<Arc<OnceLock<Instant>>>, // Only while pondering, see ponderhit()
+    thread_join_handles: Vec<thread::JoinHandle<Option<Move>>> // Outputs best move (the search thread runs its own helper threads)
 }
 
 impl AsyncEngine {
     pub fn new(table_size_mbs: usize) -> AsyncEngine {
         AsyncEngine {
-            board: Board::start_pos(),
+            game: Game::new(&Board::start_pos()),
             arc_table: Arc::new(transpos::Table::new(table_size_mbs)),
             stop_flag: ThreadFlag::new(),
+            chess960: false,
+            multi_pv: 1,
+            tablebase: None,
+            dtm_tablebase: None,
+            ponderhit_time: None,
             thread_join_handles: Vec::new()
         }
     }
 
-    pub fn start_search(&mut self, max_depth: Option<u8>, time_state: Option<time_manager::TimeState>, num_threads: usize) {
+    // search_config only needs the search limits (root moves, nodes and mate), the rest comes from the engine
+    // If infinite is set, the best move isn't sent until the search is stopped
+    pub fn start_search(
+        &mut self, max_depth: Option<u8>, time_state: Option<time_manager::TimeState>, num_threads: usize,
+        search_config: SearchConfig, infinite: bool) {
+
+        self._start_search(max_depth, time_state, num_threads, search_config, infinite, None);
+    }
+
+    // With ponderhit_time, the search waits for it to be set before using time_state
+    fn _start_search(
+        &mut self, max_depth: Option<u8>, time_state: Option<time_manager::TimeState>, num_threads: usize,
+        mut search_config: SearchConfig, infinite: bool, ponderhit_time: Option<Arc<OnceLock<Instant>>>) {
 
         self.stop_search();
 
         let start_time = Instant::now();
+        let chess960 = self.chess960;
+        let board = *self.game.board();
+        let prev_hashes = self.game.prev_hashes().to_vec();
+        let stop_flag = self.stop_flag.clone();
+        let table_ref = Arc::clone(&self.arc_table);
+
+        search_config.tablebase = self.tablebase.clone();
+        search_config.dtm_tablebase = self.dtm_tablebase.clone();
+        search_config.multi_pv = self.multi_pv;
+
+        self.thread_join_handles.push(
+            thread::spawn(move || {
+
+                // The table is lock-free, so every thread can use it at once
+                let table: &transpos::Table = &table_ref;
+
+                let async_search_config = AsyncSearchConfig {
+                    max_depth,
+                    stop_flag: Some(&stop_flag),
+                    start_time,
+                    time_state,
+                    ponderhit_time: ponderhit_time.as_deref(),
+                    print_uci: true,
+                    chess960,
+
+                    search_config
+                };
+
+                let result = thread::scope(|scope| {
+                    let mut search_done_flag = ThreadFlag::new();
+                    if let Some(ponderhit_time) = &ponderhit_time {
+                        let (stop_flag, watcher_done_flag) = (&stop_flag, search_done_flag.clone());
+                        scope.spawn(move || _watch_ponder_search(&board, time_state, ponderhit_time, stop_flag, &watcher_done_flag));
+                    }
+
+                    let result = do_smp_search(&board, &prev_hashes, table, &async_search_config, num_threads);
+                    search_done_flag.trigger();
+                    result
+                });
 
-        for thread_idx in 0..num_threads {
-            let board = self.board.clone();
-            let stop_flag = self.stop_flag.clone();
-            let table_ref = Arc::clone(&self.arc_table);
-            self.thread_join_handles.push(
-                thread::spawn(move || {
-
-                    // Unsafe deference the table
-                    let table_ptr = Arc::as_ptr(&table_ref);
-                    let table = unsafe { &mut *(table_ptr as *mut transpos::Table) };
-                    let is_leader_thread = thread_idx == 0;
-
-                    let mut search_config = SearchConfig::new();
-
-                    let async_search_config = AsyncSearchConfig {
-                        max_depth,
-                        stop_flag: Some(&stop_flag),
-                        start_time,
-                        time_state,
-                        print_uci: is_leader_thread,
-
-                        search_config
-                    };
-
-                    let (best_move, _eval) = do_search_thread(&board, table, &async_search_config);
-
-                    if is_leader_thread {
-                        if best_move.is_some() {
-                            let mut moves = move_gen::MoveBuffer::new();
-                            move_gen::generate_moves(&board, &mut moves);
-                            uci::print_best_move(moves[best_move.unwrap() as usize]);
-                        } else {
-                            panic!("No best move found in time")
-                        }
+                // A ponder search stops waiting once the ponderhit comes, as it's then a normal search
+                if infinite {
+                    while !stop_flag.get() && ponderhit_time.as_ref().is_none_or(|ponderhit_time| ponderhit_time.get().is_none()) {
+                        thread::sleep(Duration::from_millis(1));
                     }
+                }
 
-                    best_move
-                })
-            );
+                if let Some(best_move) = result.best_move {
+                    uci::print_best_move(best_move, result.ponder_move, chess960);
+                } else {
+                    panic!("No best move found in time")
+                }
+
+                result.best_move
+            })
+        );
+    }
+
+    // Searches without time limits until ponderhit() is called, and then with time_state (or until stop_search() is called)
+    pub fn start_ponder(
+        &mut self, max_depth: Option<u8>, time_state: Option<time_manager::TimeState>, num_threads: usize,
+        search_config: SearchConfig) {
+
+        let ponderhit_time = Arc::new(OnceLock::new());
+        self._start_search(max_depth, time_state, num_threads, search_config, true, Some(ponderhit_time.clone()));
+        self.ponderhit_time = Some(ponderhit_time);
+    }
+
+    // The opponent played the move we were pondering on, so the ponder search carries on as the real search
+    // From now on it uses the time limits it was started with, counted from the ponderhit
+    pub fn ponderhit(&mut self) {
+        if let Some(ponderhit_time) = self.ponderhit_time.take() {
+            ponderhit_time.set(Instant::now()).unwrap();
         }
     }
 
-    // Returns the best move index
-    pub fn stop_search(&mut self) -> Option<u8> {
+    pub fn is_pondering(&self) -> bool {
+        self.ponderhit_time.is_some()
+    }
+
+    // True until the search sends its best move (or would, without UCI printing)
+    pub fn is_searching(&self) -> bool {
+        self.thread_join_handles.iter().any(|handle| !handle.is_finished())
+    }
+
+    // Returns the best move
+    pub fn stop_search(&mut self) -> Option<Move> {
+        self.ponderhit_time = None;
         self.stop_flag.trigger();
-        let mut best_move_idx: Option<u8> = None;
+        let mut best_move: Option<Move> = None;
         for handle in self.thread_join_handles.drain(..) {
             let handle_result = handle.join();
             if handle_result.is_ok() {
-                best_move_idx = handle_result.unwrap();
+                best_move = handle_result.unwrap();
             } else {
                 panic!("Search thread crashed");
             }
 
         }
         self.stop_flag.reset();
-        best_move_idx
+        best_move
     }
 
     pub fn get_board(&self) -> &Board {
-        &self.board
+        self.game.board()
+    }
+
+    pub fn get_game(&self) -> &Game {
+        &self.game
     }
 
+    // Starts a new game from this board, with no history
     pub fn set_board(&mut self, new_board: &Board) {
-        self.board = new_board.clone();
+        self.game = Game::new(new_board);
+    }
+
+    pub fn set_game(&mut self, new_game: &Game) {
+        self.game = new_game.clone();
+    }
+
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    // Number of best moves to report while searching
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv;
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<syzygy::Tablebase>>) {
+        self.stop_search();
+        self.tablebase = tablebase;
+    }
+
+    pub fn set_dtm_tablebase(&mut self, dtm_tablebase: Option<Arc<tablebase::DtmTablebase>>) {
+        self.stop_search();
+        self.dtm_tablebase = dtm_tablebase;
     }
 
     // NOTE: Doesn't reset the table if the size matches
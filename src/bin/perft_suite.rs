@@ -0,0 +1,53 @@
+use board_crab_lib::epd;
+use board_crab_lib::perft;
+
+// Runs a perft test suite, e.g. "perft_suite data/perftsuite.epd depth 5 threads 8"
+// Exits with 1 if any position fails, or 2 if the suite can't be loaded
+fn main() {
+    board_crab_lib::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut path = "data/perftsuite.epd".to_string();
+    let mut max_depth: u8 = 6;
+    let mut num_threads = std::thread::available_parallelism().map(|num| num.get()).unwrap_or(1);
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "depth" | "threads" => {
+                let Some(val) = args.get(i + 1).and_then(|val_str| val_str.parse::<usize>().ok()) else {
+                    eprintln!("Missing or invalid value for \"{}\"", args[i]);
+                    std::process::exit(2);
+                };
+                if args[i] == "depth" { max_depth = val.min(u8::MAX as usize) as u8 } else { num_threads = val }
+                i += 1;
+            },
+            _ => path = args[i].clone()
+        }
+        i += 1;
+    }
+
+    let entries = match epd::load_epd_file(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    println!("Running {} positions from \"{}\" up to depth {} with {} threads", entries.len(), path, max_depth, num_threads);
+    let start_time = std::time::Instant::now();
+    let results = perft::run_test_suite(&entries, max_depth, num_threads, true);
+    let elapsed_time = start_time.elapsed().as_secs_f64();
+
+    println!(
+        "\nPassed {}/{} positions ({} nodes in {:.2}s, {} nps)",
+        results.num_passed, entries.len(), results.total_nodes, elapsed_time,
+        (results.total_nodes as f64 / elapsed_time.max(0.001)) as u64
+    );
+    for failure in &results.failures {
+        println!("Failed #{} at depth {}: \"{}\"", failure.entry_idx + 1, failure.depth, failure.fen);
+    }
+
+    std::process::exit(if results.num_failed > 0 { 1 } else { 0 });
+}
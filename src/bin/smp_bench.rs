@@ -0,0 +1,86 @@
+use board_crab_lib::async_engine;
+use board_crab_lib::fen;
+use board_crab_lib::search::SearchConfig;
+use board_crab_lib::transpos;
+use std::time::Instant;
+
+const BENCH_FENS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "r2q1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2Q1RK1 w - - 0 10",
+    "r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1 w - - 0 7",
+    "2r3k1/pp3ppp/2n1b3/3p4/3P4/2N1B3/PP3PPP/2R3K1 w - - 0 20",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5p2/6p1/8/7p/8/6PP/6K1 b - - 0 1"
+];
+
+// Measures the Lazy SMP time-to-depth speedup, e.g. "smp_bench threads 8 depth 10 hash 64"
+// Every position is searched to the same depth with 1, 2, 4... up to the given number of threads, each time with a fresh table
+fn main() {
+    board_crab_lib::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut max_threads = std::thread::available_parallelism().map(|num| num.get()).unwrap_or(1);
+    let mut depth: u8 = 10;
+    let mut hash_size_mbs: usize = 64;
+
+    let mut i = 0;
+    while i < args.len() {
+        let Some(val) = args.get(i + 1).and_then(|val_str| val_str.parse::<usize>().ok()) else {
+            eprintln!("Missing or invalid value for \"{}\"", args[i]);
+            std::process::exit(2);
+        };
+        match args[i].as_str() {
+            "threads" => max_threads = val.max(1),
+            "depth" => depth = val.clamp(1, u8::MAX as usize) as u8,
+            "hash" => hash_size_mbs = val.max(1),
+            _ => {
+                eprintln!("Unknown argument \"{}\"", args[i]);
+                std::process::exit(2);
+            }
+        }
+        i += 2;
+    }
+
+    let mut thread_counts = vec![1];
+    while thread_counts.last().unwrap() * 2 <= max_threads {
+        thread_counts.push(thread_counts.last().unwrap() * 2);
+    }
+    if *thread_counts.last().unwrap() != max_threads {
+        thread_counts.push(max_threads);
+    }
+
+    println!("Searching {} positions to depth {} with up to {} threads", BENCH_FENS.len(), depth, max_threads);
+    let mut base_time: Option<f64> = None;
+    for num_threads in thread_counts {
+        let mut total_time = 0.0;
+        let mut total_nodes = 0;
+        for fen_str in BENCH_FENS {
+            let board = fen::load_fen(fen_str).unwrap();
+            let table = transpos::Table::new(hash_size_mbs);
+            let async_search_config = async_engine::AsyncSearchConfig {
+                max_depth: Some(depth),
+                stop_flag: None,
+                start_time: Instant::now(),
+                time_state: None,
+                ponderhit_time: None,
+                print_uci: false,
+                chess960: false,
+
+                search_config: SearchConfig::new()
+            };
+
+            let start_time = Instant::now();
+            let result = async_engine::do_smp_search(&board, &[], &table, &async_search_config, num_threads);
+            total_time += start_time.elapsed().as_secs_f64();
+            total_nodes += result.total_nodes;
+        }
+
+        let base_time = *base_time.get_or_insert(total_time);
+        println!(
+            "{:>3} threads: {:.2}s, {} nodes, {} nps, {:.2}x speedup",
+            num_threads, total_time, total_nodes, (total_nodes as f64 / total_time.max(0.001)) as u64, base_time / total_time.max(0.001)
+        );
+    }
+}
@@ -18,7 +18,7 @@ pub const PIECE_NAMES: [&str; NUM_PIECES] = ["Pawn", "Knight", "Bishop", "Rook",
 
 ////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Move {
     pub from: BitMask,
     pub to: BitMask,
@@ -52,12 +52,29 @@ impl Move {
     pub fn is_quiet(&self) -> bool {
         !self.has_flag(Move::FLAG_CAPTURE) && !self.has_flag(Move::FLAG_PROMOTION)
     }
+
+    // Castle moves are stored as king-takes-rook, so "to" is the square of the rook being castled with
+    // This is what lets us support Chess960, where the king and rook can start anywhere on the back rank
+    pub fn castle_king_to(&self) -> BitMask {
+        debug_assert!(self.has_flag(Move::FLAG_CASTLE));
+        let y = bm_to_xy(self.from).1;
+        bm_from_xy(if self.to > self.from { 6 } else { 2 }, y)
+    }
+
+    pub fn castle_rook_to(&self) -> BitMask {
+        debug_assert!(self.has_flag(Move::FLAG_CASTLE));
+        let y = bm_to_xy(self.from).1;
+        bm_from_xy(if self.to > self.from { 5 } else { 3 }, y)
+    }
 }
 
+// Writes the move in UCI notation
+// Castling is written as the king's destination ("e1g1"), or as king-takes-rook ("e1h1") with the alternate flag ("{:#}")
 impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut stream: String = String::new();
-        write!(stream, "{}{}", bm_to_coord(self.from), bm_to_coord(self.to))?;
+        let to = if self.has_flag(Move::FLAG_CASTLE) && !f.alternate() { self.castle_king_to() } else { self.to };
+        write!(stream, "{}{}", bm_to_coord(self.from), bm_to_coord(to))?;
         if self.to_piece_idx != self.from_piece_idx {
             // Promotion
             write!(stream, "{}", PIECE_CHARS[self.to_piece_idx].to_ascii_lowercase())?;
@@ -68,7 +85,92 @@ impl std::fmt::Display for Move {
 
 ////////////////////////////////////////////////////////////////////////////
 
+// A move packed into 16 bits, for storing moves without needing the move list they came from (e.g. in the transposition table)
+// Layout: from (6 bits), to (6 bits), promotion piece (2 bits), special type (2 bits)
+// The rest of the move (piece types, capture, double pawn move) is recovered from the board it is played on
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PackedMove(pub u16);
+
+impl PackedMove {
+    pub const NULL: PackedMove = PackedMove(0); // A1 to A1, which can never be a real move
+
+    const TYPE_NORMAL: u16 = 0;
+    const TYPE_PROMOTION: u16 = 1;
+    const TYPE_EN_PASSANT: u16 = 2;
+    const TYPE_CASTLE: u16 = 3;
+
+    pub fn from_move(mv: &Move) -> PackedMove {
+        let mut promotion_bits: u16 = 0;
+        let special_type = if mv.has_flag(Move::FLAG_PROMOTION) {
+            promotion_bits = (mv.to_piece_idx - PIECE_KNIGHT) as u16;
+            PackedMove::TYPE_PROMOTION
+        } else if mv.has_flag(Move::FLAG_EN_PASSANT) {
+            PackedMove::TYPE_EN_PASSANT
+        } else if mv.has_flag(Move::FLAG_CASTLE) {
+            PackedMove::TYPE_CASTLE
+        } else {
+            PackedMove::TYPE_NORMAL
+        };
+
+        PackedMove(
+            (bm_to_idx(mv.from) as u16)
+                | ((bm_to_idx(mv.to) as u16) << 6)
+                | (promotion_bits << 12)
+                | (special_type << 14)
+        )
+    }
+
+    pub fn is_null(&self) -> bool { *self == PackedMove::NULL }
+
+    pub fn from_idx(&self) -> usize { (self.0 & 63) as usize }
+    pub fn to_idx(&self) -> usize { ((self.0 >> 6) & 63) as usize }
+
+    // Returns PIECE_KNIGHT-PIECE_QUEEN if this is a promotion
+    pub fn promotion_piece_idx(&self) -> Option<usize> {
+        if (self.0 >> 14) == PackedMove::TYPE_PROMOTION {
+            Some(PIECE_KNIGHT + ((self.0 >> 12) & 3) as usize)
+        } else {
+            None
+        }
+    }
+
+    // Unpacks into the matching legal move on this board
+    // Returns None if the move isn't legal (e.g. it came from a hash collision)
+    pub fn to_move(&self, board: &Board) -> Option<Move> {
+        if self.is_null() {
+            return None;
+        }
+
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+        moves.iter().find(|mv| PackedMove::from_move(mv) == *self).copied()
+    }
+
+    pub fn is_legal(&self, board: &Board) -> bool {
+        self.to_move(board).is_some()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////
+
+// Everything Board::unmake_move() needs to restore that can't be worked out from the move itself
 #[derive(Debug, Copy, Clone)]
+pub struct UndoInfo {
+    captured_piece_idx: Option<usize>,
+
+    attacks: [BitMask; 2],
+    checkers: BitMask,
+    pinned: [BitMask; 2],
+    en_passant_mask: BitMask,
+    castle_rights: [[Option<u8>; 2]; 2],
+    half_move_counter: u8,
+    full_move_counter: u16,
+    hash: zobrist::Hash
+}
+
+////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Board {
 
     pub turn_idx: usize,
@@ -92,11 +194,15 @@ pub struct Board {
     pub en_passant_mask: BitMask,
 
     // Castle rights for each side, for each player
+    // Stored as the file (x) of the rook we can castle with, so that Chess960 positions work
     // Order is [left,right] (left being queenside)
-    pub castle_rights: [[bool; 2]; 2],
+    pub castle_rights: [[Option<u8>; 2]; 2],
 
     pub half_move_counter: u8,
 
+    // Starts at 1, and goes up after each of black's moves
+    pub full_move_counter: u16,
+
     pub hash: zobrist::Hash,
 }
 
@@ -112,8 +218,9 @@ impl Board {
             pieces: [[0; NUM_PIECES]; 2],
             turn_idx: 0,
             en_passant_mask: 0,
-            castle_rights: [[false; 2]; 2],
+            castle_rights: [[None; 2]; 2],
             half_move_counter: 0,
+            full_move_counter: 1,
 
             hash: 0
         }
@@ -139,7 +246,7 @@ impl Board {
         }
 
         // Enable castling
-        board.castle_rights = [[true; 2]; 2];
+        board.castle_rights = [[Some(0), Some(7)]; 2];
 
         board.full_update();
 
@@ -156,6 +263,14 @@ impl Board {
         self.occupancy[0] | self.occupancy[1]
     }
 
+    // Returns the mask of the rook we can castle with on that side, or 0 if we can't castle there
+    pub fn castle_rook_mask(&self, team_idx: usize, side: usize) -> BitMask {
+        match self.castle_rights[team_idx][side] {
+            Some(rook_x) => bm_from_xy(rook_x as i64, [0, 7][team_idx]),
+            None => 0
+        }
+    }
+
     // Updates everything persistent, for after you set up the board
     // Only to be used infrequently
     pub fn full_update(&mut self) {
@@ -180,8 +295,8 @@ impl Board {
             }
 
             self.hash ^= zobrist::hash_castle_rights(self.castle_rights);
-            self.hash ^= zobrist::hash_en_passant(self.en_passant_mask);
-            if self.turn_idx == 1 {
+            self.hash ^= zobrist::hash_en_passant(self.en_passant_mask, self.pieces[self.turn_idx][PIECE_PAWN]);
+            if self.turn_idx == 0 {
                 self.hash ^= zobrist::hash_turn();
             }
         }
@@ -237,20 +352,6 @@ impl Board {
     }
 
     pub fn do_move(&mut self, mv: &Move) {
-        // From: https://github.com/ZealanL/BoardMouse/blob/4d3b6c608a3cb82a1299580a90dcb3c831fc02f8/src/Engine/BoardState/BoardState.cpp
-        // Order: Left/Queen-side, Right/King-side
-        const CASTLING_ROOK_FROM_MASKS: [[BitMask; 2]; 2] = [
-            [ // White
-                bm_from_coord("A1"), bm_from_coord("H1")
-            ],
-            [ // Black
-                bm_from_coord("A8"), bm_from_coord("H8")
-            ]
-        ];
-
-        const CASTLING_ROOK_FROM_COMBINED_MASK: BitMask =
-            CASTLING_ROOK_FROM_MASKS[0][0] | CASTLING_ROOK_FROM_MASKS[0][1] | CASTLING_ROOK_FROM_MASKS[1][0] | CASTLING_ROOK_FROM_MASKS[1][1];
-
         let from_idx = bm_to_idx(mv.from);
         let to_idx = bm_to_idx(mv.to);
         let inv_from = !mv.from;
@@ -258,24 +359,26 @@ impl Board {
 
         // Undo castle and en passant hashes
         self.hash ^= zobrist::hash_castle_rights(self.castle_rights);
-        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask);
-
-        // Update pieces
-        self.pieces[self.turn_idx][mv.from_piece_idx] &= inv_from;
-        self.pieces[self.turn_idx][mv.to_piece_idx] |= mv.to;
-        self.hash ^= zobrist::hash_piece(self.turn_idx, mv.from_piece_idx, from_idx);
-        self.hash ^= zobrist::hash_piece(self.turn_idx, mv.to_piece_idx, to_idx);
-        for opp_piece_idx in 0..NUM_PIECES {
-            if (self.pieces[1 - self.turn_idx][opp_piece_idx] & mv.to) != 0 {
-                self.hash ^= zobrist::hash_piece(1 - self.turn_idx, opp_piece_idx, to_idx);
+        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask, self.pieces[self.turn_idx][PIECE_PAWN]);
+
+        if !mv.has_flag(Move::FLAG_CASTLE) {
+            // Update pieces
+            self.pieces[self.turn_idx][mv.from_piece_idx] &= inv_from;
+            self.pieces[self.turn_idx][mv.to_piece_idx] |= mv.to;
+            self.hash ^= zobrist::hash_piece(self.turn_idx, mv.from_piece_idx, from_idx);
+            self.hash ^= zobrist::hash_piece(self.turn_idx, mv.to_piece_idx, to_idx);
+            for opp_piece_idx in 0..NUM_PIECES {
+                if (self.pieces[1 - self.turn_idx][opp_piece_idx] & mv.to) != 0 {
+                    self.hash ^= zobrist::hash_piece(1 - self.turn_idx, opp_piece_idx, to_idx);
+                }
+                self.pieces[1 - self.turn_idx][opp_piece_idx] &= inv_to;
             }
-            self.pieces[1 - self.turn_idx][opp_piece_idx] &= inv_to;
-        }
 
-        // Update occupancy
-        self.occupancy[self.turn_idx] |= mv.to;
-        self.occupancy[self.turn_idx] &= inv_from;
-        self.occupancy[1 - self.turn_idx] &= inv_to;
+            // Update occupancy
+            self.occupancy[self.turn_idx] |= mv.to;
+            self.occupancy[self.turn_idx] &= inv_from;
+            self.occupancy[1 - self.turn_idx] &= inv_to;
+        }
 
         self.en_passant_mask = 0; // Reset en passant mask (we will set it only if it is a double pawn move)
         if mv.has_flag(Move::FLAG_DOUBLE_PAWN_MOVE) {
@@ -289,20 +392,28 @@ impl Board {
             self.hash ^= zobrist::hash_piece(1 - self.turn_idx, PIECE_PAWN, bm_to_idx(en_passant_pos));
 
         } else if mv.has_flag(Move::FLAG_CASTLE) {
-            // We are castling, find and move the rook
-
-            let castle_right: bool = mv.to > mv.from; // This works because we cant castle with a vertical king move
+            // We are castling, move both the king and the rook
+            // The move is king-takes-rook, so the rook starts on the "to" square
 
-            let rook_from = CASTLING_ROOK_FROM_MASKS[self.turn_idx][castle_right as usize];
-            let rook_to = if castle_right { bm_shift(mv.to, -1, 0) } else { bm_shift(mv.to, 1, 0) };
+            let rook_from = mv.to;
+            let king_to = mv.castle_king_to();
+            let rook_to = mv.castle_rook_to();
 
             debug_assert!(self.pieces[self.turn_idx][PIECE_ROOK] & rook_from == rook_from);
-            debug_assert!(self.combined_occupancy() & rook_to == 0);
 
-            let rook_flip = rook_from | rook_to;
-            self.pieces[self.turn_idx][PIECE_ROOK] ^= rook_flip;
-            self.occupancy[self.turn_idx] ^= rook_flip;
+            // Lift both pieces before placing them, since in Chess960 the destinations can overlap the origins
+            self.pieces[self.turn_idx][PIECE_KING] &= inv_from;
+            self.pieces[self.turn_idx][PIECE_ROOK] &= !rook_from;
+            self.occupancy[self.turn_idx] &= !(mv.from | rook_from);
+
+            debug_assert!(self.combined_occupancy() & (king_to | rook_to) == 0);
 
+            self.pieces[self.turn_idx][PIECE_KING] |= king_to;
+            self.pieces[self.turn_idx][PIECE_ROOK] |= rook_to;
+            self.occupancy[self.turn_idx] |= king_to | rook_to;
+
+            self.hash ^= zobrist::hash_piece(self.turn_idx, PIECE_KING, from_idx);
+            self.hash ^= zobrist::hash_piece(self.turn_idx, PIECE_KING, bm_to_idx(king_to));
             self.hash ^= zobrist::hash_piece(self.turn_idx, PIECE_ROOK, bm_to_idx(rook_from));
             self.hash ^= zobrist::hash_piece(self.turn_idx, PIECE_ROOK, bm_to_idx(rook_to));
 
@@ -311,28 +422,28 @@ impl Board {
 
         if mv.from_piece_idx == PIECE_KING {
             // Castling is now banned
-            self.castle_rights[self.turn_idx] = [false; 2];
+            self.castle_rights[self.turn_idx] = [None; 2];
         }
 
-        // Detect move that disables castling
+        // Detect move that disables castling (moving a castling rook, or capturing one)
         let combined_to_from = mv.to | mv.from;
-        if (combined_to_from & CASTLING_ROOK_FROM_COMBINED_MASK) != 0 {
-            for i in 0..2 {
-                for j in 0..2 {
-                    if (combined_to_from & CASTLING_ROOK_FROM_MASKS[i][j]) != 0 {
-                        self.castle_rights[i][j] = false;
-                    }
+        for team_idx in 0..2 {
+            for side in 0..2 {
+                if (combined_to_from & self.castle_rook_mask(team_idx, side)) != 0 {
+                    self.castle_rights[team_idx][side] = None;
                 }
             }
         }
 
-        let is_capture_or_pawn_move = (
-            self.occupancy[1 - self.turn_idx] & mv.to != 0)
-            || (mv.from_piece_idx == PIECE_PAWN);
+        let is_capture_or_pawn_move = mv.has_flag(Move::FLAG_CAPTURE) || (mv.from_piece_idx == PIECE_PAWN);
         if is_capture_or_pawn_move {
             self.half_move_counter = 0;
         } else {
-            self.half_move_counter += 1;
+            self.half_move_counter = self.half_move_counter.saturating_add(1);
+        }
+
+        if self.turn_idx == 1 {
+            self.full_move_counter = self.full_move_counter.saturating_add(1);
         }
 
         self.update_attacks(self.turn_idx);
@@ -340,20 +451,114 @@ impl Board {
 
         // Redo castle and en passant hashes
         self.hash ^= zobrist::hash_castle_rights(self.castle_rights);
-        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask);
+        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask, self.pieces[self.turn_idx][PIECE_PAWN]);
 
         // Flip turn hash
         self.hash ^= zobrist::hash_turn();
     }
 
     pub fn do_null_move(&mut self) {
-        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask);
+        self.hash ^= zobrist::hash_en_passant(self.en_passant_mask, self.pieces[self.turn_idx][PIECE_PAWN]);
         self.en_passant_mask = 0;
         self.update_attacks(self.turn_idx);
         self.turn_idx = 1 - self.turn_idx;
 
         self.hash ^= zobrist::hash_turn();
     }
+
+    fn make_undo_info(&self, captured_piece_idx: Option<usize>) -> UndoInfo {
+        UndoInfo {
+            captured_piece_idx,
+
+            attacks: self.attacks,
+            checkers: self.checkers,
+            pinned: self.pinned,
+            en_passant_mask: self.en_passant_mask,
+            castle_rights: self.castle_rights,
+            half_move_counter: self.half_move_counter,
+            full_move_counter: self.full_move_counter,
+            hash: self.hash
+        }
+    }
+
+    fn restore_undo_info(&mut self, undo_info: &UndoInfo) {
+        self.attacks = undo_info.attacks;
+        self.checkers = undo_info.checkers;
+        self.pinned = undo_info.pinned;
+        self.en_passant_mask = undo_info.en_passant_mask;
+        self.castle_rights = undo_info.castle_rights;
+        self.half_move_counter = undo_info.half_move_counter;
+        self.full_move_counter = undo_info.full_move_counter;
+        self.hash = undo_info.hash;
+    }
+
+    // Same as do_move(), but returns what is needed to take the move back with unmake_move()
+    pub fn make_move(&mut self, mv: &Move) -> UndoInfo {
+        let mut captured_piece_idx = None;
+        if mv.has_flag(Move::FLAG_EN_PASSANT) {
+            captured_piece_idx = Some(PIECE_PAWN);
+        } else if mv.has_flag(Move::FLAG_CAPTURE) {
+            for opp_piece_idx in 0..NUM_PIECES {
+                if (self.pieces[1 - self.turn_idx][opp_piece_idx] & mv.to) != 0 {
+                    captured_piece_idx = Some(opp_piece_idx);
+                    break;
+                }
+            }
+        }
+
+        let undo_info = self.make_undo_info(captured_piece_idx);
+        self.do_move(mv);
+        undo_info
+    }
+
+    // Takes back a move played with make_move(), restoring the board exactly
+    pub fn unmake_move(&mut self, mv: &Move, undo_info: &UndoInfo) {
+        self.turn_idx = 1 - self.turn_idx;
+
+        if mv.has_flag(Move::FLAG_CASTLE) {
+            let king_to = mv.castle_king_to();
+            let rook_to = mv.castle_rook_to();
+
+            // Lift both pieces before placing them, same as in do_move()
+            self.pieces[self.turn_idx][PIECE_KING] &= !king_to;
+            self.pieces[self.turn_idx][PIECE_ROOK] &= !rook_to;
+            self.occupancy[self.turn_idx] &= !(king_to | rook_to);
+
+            self.pieces[self.turn_idx][PIECE_KING] |= mv.from;
+            self.pieces[self.turn_idx][PIECE_ROOK] |= mv.to;
+            self.occupancy[self.turn_idx] |= mv.from | mv.to;
+        } else {
+            self.pieces[self.turn_idx][mv.to_piece_idx] &= !mv.to;
+            self.pieces[self.turn_idx][mv.from_piece_idx] |= mv.from;
+            self.occupancy[self.turn_idx] &= !mv.to;
+            self.occupancy[self.turn_idx] |= mv.from;
+
+            if let Some(captured_piece_idx) = undo_info.captured_piece_idx {
+                let captured_pos = if mv.has_flag(Move::FLAG_EN_PASSANT) {
+                    bm_shift(mv.to, 0, if self.turn_idx == 0 { -1 } else { 1 })
+                } else {
+                    mv.to
+                };
+
+                self.pieces[1 - self.turn_idx][captured_piece_idx] |= captured_pos;
+                self.occupancy[1 - self.turn_idx] |= captured_pos;
+            }
+        }
+
+        self.restore_undo_info(undo_info);
+    }
+
+    // Same as do_null_move(), but returns what is needed to take it back with unmake_null_move()
+    pub fn make_null_move(&mut self) -> UndoInfo {
+        let undo_info = self.make_undo_info(None);
+        self.do_null_move();
+        undo_info
+    }
+
+    pub fn unmake_null_move(&mut self, undo_info: &UndoInfo) {
+        self.turn_idx = 1 - self.turn_idx;
+        self.restore_undo_info(undo_info);
+    }
 }
 
 impl std::fmt::Display for Board {
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use rand::Rng;
+use crate::bitmask::*;
+use crate::board::*;
+use crate::fen;
+use crate::move_gen;
+use crate::pgn;
+use crate::zobrist::Hash;
+
+// Polyglot opening book support
+// Ref: http://hgm.nubati.net/book_format.html
+
+type Result<T> = std::result::Result<T, BookError>;
+
+#[derive(Debug, Clone)]
+pub struct BookError(String);
+
+impl std::fmt::Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "BookError: {}", self.0)
+    }
+}
+
+// Size of each entry in the file
+pub const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: Hash,
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32
+}
+
+impl BookEntry {
+    // Entries are stored big-endian
+    pub fn from_bytes(bytes: &[u8]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            raw_move: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().unwrap()),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().unwrap())
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.raw_move.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+// Our zobrist keys are the Polyglot keys, so the board hash is already the book key
+pub fn polyglot_key(board: &Board) -> Hash {
+    board.hash
+}
+
+// Finds the legal move matching a Polyglot move, if there is one
+// Polyglot moves are from (6 bits) | to (6 bits) | promotion piece (3 bits), with "to" listed first
+// Castling is king-takes-rook, which is the same as our moves
+pub fn decode_move(board: &Board, raw_move: u16) -> Option<Move> {
+    let to_idx = (raw_move & 0x3F) as usize;
+    let from_idx = ((raw_move >> 6) & 0x3F) as usize;
+    let promotion = ((raw_move >> 12) & 0x7) as usize; // 0 is none, 1 is a knight, ..., 4 is a queen
+
+    let mut move_buffer = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut move_buffer);
+    for mv in move_buffer.iter() {
+        if bm_to_idx(mv.from) != from_idx || bm_to_idx(mv.to) != to_idx {
+            continue;
+        }
+
+        let mv_promotion = if mv.has_flag(Move::FLAG_PROMOTION) { mv.to_piece_idx } else { 0 };
+        if mv_promotion == promotion {
+            return Some(*mv);
+        }
+    }
+
+    None
+}
+
+// Inverse of decode_move()
+pub fn encode_move(mv: &Move) -> u16 {
+    let promotion = if mv.has_flag(Move::FLAG_PROMOTION) { mv.to_piece_idx as u16 } else { 0 };
+    (bm_to_idx(mv.to) as u16) | ((bm_to_idx(mv.from) as u16) << 6) | (promotion << 12)
+}
+
+pub struct Book {
+    entries: Vec<BookEntry> // Sorted by key
+}
+
+impl Book {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book> {
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError(format!("Invalid book size {}, must be a multiple of {}", bytes.len(), ENTRY_SIZE)));
+        }
+
+        let mut entries: Vec<BookEntry> = bytes.chunks_exact(ENTRY_SIZE).map(BookEntry::from_bytes).collect();
+
+        // Books should already be sorted, but we rely on it for searching
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Book { entries })
+    }
+
+    pub fn load(path: &str) -> Result<Book> {
+        match std::fs::read(path) {
+            Ok(bytes) => Book::from_bytes(&bytes),
+            Err(e) => Err(BookError(format!("Failed to read book file \"{}\": {}", path, e)))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get_entries(&self, key: Hash) -> &[BookEntry] {
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let end = self.entries.partition_point(|entry| entry.key <= key);
+        &self.entries[start..end]
+    }
+
+    // Returns the legal book moves for this position along with their weights
+    pub fn get_moves(&self, board: &Board) -> Vec<(Move, u16)> {
+        let mut result = Vec::new();
+        for entry in self.get_entries(polyglot_key(board)) {
+            // Entries for illegal moves can exist from hash collisions or bad books
+            if let Some(mv) = decode_move(board, entry.raw_move) {
+                result.push((mv, entry.weight));
+            }
+        }
+
+        result
+    }
+
+    // Picks the book move with the highest weight
+    pub fn get_best_move(&self, board: &Board) -> Option<Move> {
+        self.get_moves(board).into_iter()
+            .filter(|(_, weight)| *weight > 0)
+            .max_by_key(|(_, weight)| *weight)
+            .map(|(mv, _)| mv)
+    }
+
+    // Picks a random book move, where the chance of each move is proportional to its weight
+    pub fn get_random_move(&self, board: &Board) -> Option<Move> {
+        let moves = self.get_moves(board);
+        let total_weight: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total_weight == 0 {
+            return None;
+        }
+
+        let mut choice = rand::rng().random_range(0..total_weight);
+        for (mv, weight) in moves {
+            if choice < weight as u64 {
+                return Some(mv);
+            }
+            choice -= weight as u64;
+        }
+
+        unreachable!()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Results of a move, from the perspective of the team that played it
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MoveStats {
+    pub count: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32
+}
+
+impl MoveStats {
+    // Polyglot weights are usually 2 points per win and 1 per draw
+    pub fn score(&self) -> u64 {
+        (self.wins as u64) * 2 + (self.draws as u64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BookPosition {
+    pub fen: String,
+    pub moves: HashMap<u16, MoveStats> // Keyed by Polyglot move
+}
+
+// Builds an opening book from a collection of games
+pub struct BookBuilder {
+    pub max_ply: usize, // Positions after this many plies are not included in the book
+    pub positions: HashMap<Hash, BookPosition>,
+
+    // Positions reached after exactly max_ply plies, with how many games reached them
+    pub end_positions: HashMap<Hash, (String, u32)>,
+
+    pub num_games: usize
+}
+
+// Removes the move counters, like the FENs in data/gm_opening_fens.txt
+fn _make_short_fen(board: &Board) -> String {
+    fen::make_fen(board).split(' ').take(4).collect::<Vec<&str>>().join(" ")
+}
+
+impl BookBuilder {
+    pub fn new(max_ply: usize) -> BookBuilder {
+        BookBuilder {
+            max_ply,
+            positions: HashMap::new(),
+            end_positions: HashMap::new(),
+            num_games: 0
+        }
+    }
+
+    // The result should be a PGN result string, games with an unknown result ("*") only add to move counts
+    pub fn add_game(&mut self, start_board: &Board, moves: &[Move], result: &str) {
+        let winner_team_idx: Option<Option<usize>> = match result {
+            "1-0" => Some(Some(0)),
+            "0-1" => Some(Some(1)),
+            "1/2-1/2" => Some(None),
+            _ => None
+        };
+
+        let mut board = *start_board;
+        for (ply, mv) in moves.iter().enumerate() {
+            if ply >= self.max_ply {
+                break;
+            }
+
+            let position = self.positions.entry(polyglot_key(&board)).or_insert_with(|| BookPosition {
+                fen: _make_short_fen(&board),
+                moves: HashMap::new()
+            });
+
+            let stats = position.moves.entry(encode_move(mv)).or_default();
+            stats.count += 1;
+            match winner_team_idx {
+                Some(Some(team_idx)) if team_idx == board.turn_idx => stats.wins += 1,
+                Some(Some(_)) => stats.losses += 1,
+                Some(None) => stats.draws += 1,
+                None => {}
+            }
+
+            board.do_move(mv);
+        }
+
+        if moves.len() >= self.max_ply {
+            let end_position = self.end_positions.entry(polyglot_key(&board)).or_insert_with(|| (_make_short_fen(&board), 0));
+            end_position.1 += 1;
+        }
+
+        self.num_games += 1;
+    }
+
+    pub fn add_pgn_game(&mut self, game: &pgn::PgnGame) {
+        self.add_game(&game.start_board, &game.moves, &game.result);
+    }
+
+    // Makes sorted Polyglot book entries for moves played at least min_count times
+    // Moves that only lost (zero score) are left out
+    pub fn make_entries(&self, min_count: u32) -> Vec<BookEntry> {
+        let mut entries = Vec::new();
+        for (key, position) in &self.positions {
+            let position_moves: Vec<(&u16, &MoveStats)> = position.moves.iter()
+                .filter(|(_, stats)| stats.count >= min_count && stats.score() > 0)
+                .collect();
+
+            // Scale weights down if they don't fit
+            let max_score = position_moves.iter().map(|(_, stats)| stats.score()).max().unwrap_or(0);
+            let scale = if max_score > u16::MAX as u64 { u16::MAX as f64 / max_score as f64 } else { 1.0 };
+
+            for (raw_move, stats) in position_moves {
+                entries.push(BookEntry {
+                    key: *key,
+                    raw_move: *raw_move,
+                    weight: ((stats.score() as f64 * scale) as u16).max(1),
+                    learn: 0
+                });
+            }
+        }
+
+        // Sort by key, then highest weight first (like Polyglot does)
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(b.weight.cmp(&a.weight)).then(a.raw_move.cmp(&b.raw_move)));
+        entries
+    }
+
+    pub fn make_bin(&self, min_count: u32) -> Vec<u8> {
+        self.make_entries(min_count).iter().flat_map(|entry| entry.to_bytes()).collect()
+    }
+
+    pub fn make_book(&self, min_count: u32) -> Book {
+        Book { entries: self.make_entries(min_count) }
+    }
+
+    // Deduplicated FENs (without move counters) of the positions reached after max_ply plies by at least min_count games
+    // Sorted by how many games reached them, most common first
+    pub fn make_fen_list(&self, min_count: u32) -> Vec<String> {
+        let mut end_positions: Vec<&(String, u32)> = self.end_positions.values()
+            .filter(|(_, count)| *count >= min_count)
+            .collect();
+
+        end_positions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        end_positions.into_iter().map(|(fen_str, _)| fen_str.clone()).collect()
+    }
+}
+
+// Reads every game in the PGN files into a book builder
+// Malformed games are skipped, and the number skipped is returned
+pub fn build_from_pgn_files(paths: &[String], max_ply: usize) -> Result<(BookBuilder, usize)> {
+    let mut builder = BookBuilder::new(max_ply);
+    let mut num_skipped = 0;
+    for path in paths {
+        let file = std::fs::File::open(path)
+            .map_err(|e| BookError(format!("Failed to open PGN file \"{}\": {}", path, e)))?;
+
+        for game in pgn::PgnReader::new(std::io::BufReader::new(file)) {
+            match game {
+                Ok(game) => builder.add_pgn_game(&game),
+                Err(_) => num_skipped += 1
+            }
+        }
+    }
+
+    Ok((builder, num_skipped))
+}
@@ -0,0 +1,310 @@
+use std::time::Instant;
+use crate::board::*;
+use crate::fen;
+use crate::pgn;
+use crate::move_gen;
+use crate::eval::*;
+use crate::transpos;
+use crate::async_engine;
+use crate::search::SearchConfig;
+use crate::time_manager::TimeState;
+
+// Ref: https://www.chessprogramming.org/Extended_Position_Description
+
+type Result<T> = std::result::Result<T, EpdError>;
+
+#[derive(Debug, Clone)]
+pub struct EpdError(String);
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "EpdError: {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EpdEntry {
+    pub board: Board,
+    pub best_moves: Vec<Move>, // "bm"
+    pub avoid_moves: Vec<Move>, // "am"
+    pub id: Option<String>, // "id"
+    pub comments: [Option<String>; 10], // "c0" through "c9"
+    pub analysis_depth: Option<u8>, // "acd"
+    pub direct_mate: Option<u32>, // "dm", in full moves
+    pub perft_counts: Vec<(u8, usize)>, // "D1", "D2", etc. from perft suites, as (depth, node count)
+
+    // Any opcodes we don't use, along with their operands
+    pub other_ops: Vec<(String, Vec<String>)>
+}
+
+impl EpdEntry {
+    pub fn new(board: &Board) -> EpdEntry {
+        EpdEntry {
+            board: *board,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            id: None,
+            comments: Default::default(),
+            analysis_depth: None,
+            direct_mate: None,
+            perft_counts: Vec::new(),
+            other_ops: Vec::new()
+        }
+    }
+
+    // Whether this entry has anything to test a search result against
+    pub fn is_testable(&self) -> bool {
+        !self.best_moves.is_empty() || !self.avoid_moves.is_empty() || self.direct_mate.is_some()
+    }
+
+    // Whether a search result satisfies the "bm", "am" and "dm" opcodes
+    pub fn is_passed(&self, best_move: Option<Move>, eval: Value) -> bool {
+        if !self.best_moves.is_empty() && !best_move.is_some_and(|mv| self.best_moves.contains(&mv)) {
+            return false;
+        }
+
+        if best_move.is_some_and(|mv| self.avoid_moves.contains(&mv)) {
+            return false;
+        }
+
+        if let Some(direct_mate) = self.direct_mate {
+            // Getting mated doesn't count
+            if !get_mate_moves(eval).is_some_and(|mate_moves| mate_moves > 0 && mate_moves as u32 <= direct_mate) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Splits the operations into opcodes and their operands
+// Operands can be quoted strings, which may contain spaces and semicolons
+fn _split_operations(ops_str: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut ops = Vec::new();
+    let mut cur_parts: Vec<String> = Vec::new();
+    let mut cur_part = String::new();
+    let mut chars = ops_str.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(str_ch) => cur_part.push(str_ch),
+                    None => return Err(EpdError(format!("Invalid EPD operations \"{ops_str}\", unterminated string")))
+                }
+            }
+            cur_parts.push(std::mem::take(&mut cur_part));
+        } else if ch.is_whitespace() || ch == ';' {
+            if !cur_part.is_empty() {
+                cur_parts.push(std::mem::take(&mut cur_part));
+            }
+
+            if ch == ';' && !cur_parts.is_empty() {
+                let opcode = cur_parts.remove(0);
+                ops.push((opcode, std::mem::take(&mut cur_parts)));
+            }
+        } else {
+            cur_part.push(ch);
+        }
+    }
+
+    if !cur_part.is_empty() {
+        cur_parts.push(cur_part);
+    }
+    if !cur_parts.is_empty() {
+        // Last operation is missing its semicolon, which is common enough to allow
+        let opcode = cur_parts.remove(0);
+        ops.push((opcode, cur_parts));
+    }
+
+    Ok(ops)
+}
+
+// Moves should be in SAN, but some suites use UCI notation
+fn _parse_epd_move(board: &Board, move_str: &str) -> Result<Move> {
+    if let Ok(mv) = pgn::parse_algebraic(board, move_str) {
+        return Ok(mv);
+    }
+
+    let mut move_buffer = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut move_buffer);
+    for mv in move_buffer.iter() {
+        if format!("{mv}") == move_str || format!("{mv:#}") == move_str {
+            return Ok(*mv);
+        }
+    }
+
+    Err(EpdError(format!("Invalid EPD move \"{}\" for position \"{}\"", move_str, fen::make_fen(board))))
+}
+
+pub fn load_epd(epd: &str) -> Result<EpdEntry> {
+    let throw_err = |msg: &str| -> Result<EpdEntry> {
+        Err(EpdError(format!("Invalid epd: \"{}\", {}", epd.trim(), msg)))
+    };
+
+    // The first four fields are the same as a FEN, without the move counters
+    let mut fen_parts: Vec<String> = Vec::new();
+    let mut ops_str = epd.trim();
+    while fen_parts.len() < 4 {
+        let ops_start = ops_str.find(char::is_whitespace).unwrap_or(ops_str.len());
+        if ops_start == 0 {
+            return throw_err("missing position fields");
+        }
+
+        fen_parts.push(ops_str[..ops_start].to_string());
+        ops_str = ops_str[ops_start..].trim_start();
+    }
+
+    let board = match fen::load_fen_from_parts(&fen_parts) {
+        Ok(board) => board,
+        Err(e) => return throw_err(e.to_string().as_str())
+    };
+
+    let mut entry = EpdEntry::new(&board);
+    for (opcode, operands) in _split_operations(ops_str)? {
+        match opcode.as_str() {
+            "bm" | "am" => {
+                if operands.is_empty() {
+                    return throw_err(format!("opcode \"{opcode}\" has no moves").as_str());
+                }
+
+                for move_str in &operands {
+                    let mv = _parse_epd_move(&board, move_str)?;
+                    if opcode == "bm" {
+                        entry.best_moves.push(mv);
+                    } else {
+                        entry.avoid_moves.push(mv);
+                    }
+                }
+            },
+            "id" => {
+                entry.id = operands.first().cloned();
+            },
+            "acd" | "dm" => {
+                let parsed_val = operands.first().and_then(|val_str| val_str.parse::<u32>().ok());
+                match parsed_val {
+                    Some(val) if opcode == "acd" => entry.analysis_depth = Some(val.min(u8::MAX as u32) as u8),
+                    Some(val) => entry.direct_mate = Some(val),
+                    None => return throw_err(format!("opcode \"{opcode}\" needs a number").as_str())
+                }
+            },
+            _ => {
+                let comment_idx = opcode.strip_prefix('c')
+                    .filter(|idx_str| idx_str.len() == 1)
+                    .and_then(|idx_str| idx_str.parse::<usize>().ok());
+
+                let perft_depth = opcode.strip_prefix('D').and_then(|depth_str| depth_str.parse::<u8>().ok());
+
+                if let Some(idx) = comment_idx {
+                    entry.comments[idx] = Some(operands.join(" "));
+                } else if let Some(depth) = perft_depth {
+                    match operands.first().and_then(|val_str| val_str.parse::<usize>().ok()) {
+                        Some(node_count) => entry.perft_counts.push((depth, node_count)),
+                        None => return throw_err(format!("opcode \"{opcode}\" needs a number").as_str())
+                    }
+                } else {
+                    entry.other_ops.push((opcode, operands));
+                }
+            }
+        }
+    }
+
+    Ok(entry)
+}
+
+// Loads every non-empty line of an EPD file
+pub fn load_epd_file(path: &str) -> Result<Vec<EpdEntry>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| EpdError(format!("Failed to read EPD file \"{path}\": {e}")))?;
+
+    let mut entries = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        entries.push(load_epd(line)?);
+    }
+
+    Ok(entries)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct EpdSuiteResults {
+    pub num_passed: usize,
+    pub num_failed: usize,
+    pub num_skipped: usize, // Entries without any "bm", "am" or "dm" opcode
+    pub failed_ids: Vec<String>
+}
+
+// Searches each position to a fixed depth and/or for a fixed time, and checks the results
+// Entries are searched one at a time, each starting with a cleared table
+pub fn run_test_suite(entries: &[EpdEntry], max_depth: Option<u8>, max_time: Option<f64>,
+                      table_size_mbs: usize, search_config: &SearchConfig, print: bool) -> EpdSuiteResults {
+    let mut results = EpdSuiteResults {
+        num_passed: 0,
+        num_failed: 0,
+        num_skipped: 0,
+        failed_ids: Vec::new()
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let id = entry.id.clone().unwrap_or_else(|| format!("#{}", i + 1));
+
+        if !entry.is_testable() {
+            results.num_skipped += 1;
+            continue;
+        }
+
+        let table = transpos::Table::new(table_size_mbs);
+
+        let mut time_state = TimeState::new();
+        time_state.hard_max_time = max_time;
+        let async_search_config = async_engine::AsyncSearchConfig {
+            max_depth,
+            stop_flag: None,
+            start_time: Instant::now(),
+            time_state: Some(time_state),
+            ponderhit_time: None,
+            print_uci: false,
+            chess960: false,
+
+            search_config: search_config.clone()
+        };
+
+        let result = async_engine::do_search_thread(&entry.board, &[], &table, &async_search_config);
+        let (best_move, eval) = (result.best_move, result.eval);
+
+        let passed = entry.is_passed(best_move, eval);
+        if passed {
+            results.num_passed += 1;
+        } else {
+            results.num_failed += 1;
+            results.failed_ids.push(id.clone());
+        }
+
+        if print {
+            let best_move_str = match best_move {
+                Some(mv) => pgn::move_to_algebraic_str(&entry.board, &mv).unwrap_or_else(|_| format!("{mv}")),
+                None => "none".to_string()
+            };
+
+            println!(
+                "{} {}: {} (eval: {})",
+                if passed { "PASS" } else { "FAIL" }, id, best_move_str, eval_to_str(eval)
+            );
+        }
+    }
+
+    if print {
+        println!(
+            "Passed {}/{} ({} skipped)",
+            results.num_passed, results.num_passed + results.num_failed, results.num_skipped
+        );
+    }
+
+    results
+}
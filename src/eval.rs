@@ -3,42 +3,83 @@ use crate::board::*;
 use crate::lookup_gen;
 use crate::eval_lookup;
 
-pub type Value = f32; // Note: MUST be a float type
-pub const VALUE_INF: Value = Value::INFINITY;
-pub const VALUE_CHECKMATE: Value = 1_000.0;
-pub const VALUE_CHECKMATE_MIN: Value = 500.0;
+// Scores are in centipawns, from the perspective of the player to move
+// Mates are scored relative to the root of the search: mating in N plies is VALUE_CHECKMATE - N, and being mated in N plies is -VALUE_CHECKMATE + N
+pub type Value = i32;
+pub const VALUE_INF: Value = 32_000; // Only used as a search bound, never as a score
+pub const VALUE_CHECKMATE: Value = 30_000;
+pub const MAX_MATE_PLIES: Value = 1_000;
+pub const VALUE_CHECKMATE_MIN: Value = VALUE_CHECKMATE - MAX_MATE_PLIES; // Every mate score is at least this far from 0
+pub const VALUE_TABLEBASE_WIN: Value = 20_000; // Above any normal eval, but not a checkmate
+pub const VALUE_TABLEBASE_WIN_MIN: Value = VALUE_TABLEBASE_WIN - MAX_MATE_PLIES; // Tablebase wins are also scored relative to the root
+
+pub fn value_mate_in(plies: u32) -> Value {
+    VALUE_CHECKMATE - plies as Value
+}
 
-pub fn eval_to_str(eval: Value) -> String {
-    if eval.abs() >= VALUE_CHECKMATE_MIN {
-        let mate_move_count = VALUE_CHECKMATE - eval.abs();
-        let ply_till_mate = (mate_move_count * eval.signum()) as i64;
-        format!("#{}", (ply_till_mate + 1) / 2)
+pub fn value_mated_in(plies: u32) -> Value {
+    -VALUE_CHECKMATE + plies as Value
+}
+
+pub fn is_mate_value(value: Value) -> bool {
+    value.abs() >= VALUE_CHECKMATE_MIN
+}
+
+// Number of plies until the mate, negative if we're getting mated
+pub fn get_mate_plies(value: Value) -> Option<i32> {
+    if is_mate_value(value) {
+        Some((VALUE_CHECKMATE - value.abs()) * value.signum())
     } else {
-        eval.to_string()
+        None
     }
 }
 
-pub fn decay_eval(eval: Value) -> Value {
-    if eval.abs() >= VALUE_CHECKMATE_MIN {
-        eval - eval.signum()
+// Number of our moves until the mate (as in "mate in N"), negative if we're getting mated
+pub fn get_mate_moves(value: Value) -> Option<i32> {
+    get_mate_plies(value).map(|plies| if plies > 0 { (plies + 1) / 2 } else { plies / 2 })
+}
+
+// The table is shared between plies, so mate and tablebase win scores are stored relative to the node instead of the root
+pub fn value_to_table(value: Value, ply: usize) -> Value {
+    if value >= VALUE_TABLEBASE_WIN_MIN {
+        value + ply as Value
+    } else if value <= -VALUE_TABLEBASE_WIN_MIN {
+        value - ply as Value
     } else {
-        eval
+        value
+    }
+}
+
+pub fn value_from_table(value: Value, ply: usize) -> Value {
+    if value >= VALUE_TABLEBASE_WIN_MIN {
+        value - ply as Value
+    } else if value <= -VALUE_TABLEBASE_WIN_MIN {
+        value + ply as Value
+    } else {
+        value
+    }
+}
+
+pub fn eval_to_str(eval: Value) -> String {
+    match get_mate_moves(eval) {
+        Some(mate_moves) => format!("#{}", mate_moves),
+        None => format!("{:.2}", eval as f64 / 100.0)
     }
 }
 
 //////////////////////////////////////////////////////////
 
-const LIGHT_SQUARES: BitMask = 0x55aa55aa55aa55aa;
-const DARK_SQUARES: BitMask = !LIGHT_SQUARES;
+pub const LIGHT_SQUARES: BitMask = 0x55aa55aa55aa55aa;
+pub const DARK_SQUARES: BitMask = !LIGHT_SQUARES;
 
 // Returns the "attacking power" of a team from 0-1
 // This is meant to represent how capable the player is of making a deadly attack on the king
-pub fn calc_attacking_power(board: &Board, team_idx: usize) -> Value {
+pub fn calc_attacking_power(board: &Board, team_idx: usize) -> f32 {
     let rook_count = board.pieces[team_idx][PIECE_ROOK].count_ones();
     if board.pieces[team_idx][PIECE_QUEEN] != 0 {
         let bishop_count = board.pieces[team_idx][PIECE_BISHOP].count_ones();
         let knight_count = board.pieces[team_idx][PIECE_BISHOP].count_ones();
-        Value::min(1.0, 0.8 + (rook_count as Value) * 0.15 + (bishop_count as Value) * 0.05 + (knight_count as Value) * 0.03)
+        f32::min(1.0, 0.8 + (rook_count as f32) * 0.15 + (bishop_count as f32) * 0.05 + (knight_count as f32) * 0.03)
     } else {
         if rook_count >= 2 {
             0.4
@@ -49,7 +90,7 @@ pub fn calc_attacking_power(board: &Board, team_idx: usize) -> Value {
     }
 }
 
-fn dual_weight(weights: [Value; 2], scale: Value) -> Value {
+fn dual_weight(weights: [f32; 2], scale: f32) -> f32 {
     weights[0] * scale + weights[1] * (1.0 - scale)
 }
 
@@ -64,19 +105,19 @@ fn get_pawn_attack_mask(board: &Board, team_idx: usize) -> BitMask {
     capture_mask
 }
 
-pub fn eval_material(board: &Board, team_idx: usize, opp_attack_power: Value) -> Value {
-    let mut value: Value = 0.0;
+pub fn eval_material(board: &Board, team_idx: usize, opp_attack_power: f32) -> f32 {
+    let mut value: f32 = 0.0;
     for piece_idx in 0..NUM_PIECES_NO_KING {
         value +=
-            (board.pieces[team_idx][piece_idx].count_ones() as Value)
+            (board.pieces[team_idx][piece_idx].count_ones() as f32)
                 * dual_weight(eval_lookup::PIECE_BASE_VALUE[piece_idx], opp_attack_power);
     }
 
     value
 }
 
-pub fn eval_piece_type(board: &Board, team_idx: usize, piece_idx: usize, piece_mask: BitMask, opp_attack_power: Value) -> Value {
-    let mut value: Value = 0.0;
+pub fn eval_piece_type(board: &Board, team_idx: usize, piece_idx: usize, piece_mask: BitMask, opp_attack_power: f32) -> f32 {
+    let mut value: f32 = 0.0;
 
     let opp_pawns = board.pieces[1 - team_idx][PIECE_PAWN];
     let pawn_attacks = get_pawn_attack_mask(board, team_idx);
@@ -136,12 +177,12 @@ pub fn eval_piece_type(board: &Board, team_idx: usize, piece_idx: usize, piece_m
     value
 }
 
-pub fn eval_mobility(board: &Board, team_idx: usize) -> Value {
+pub fn eval_mobility(board: &Board, team_idx: usize) -> f32 {
     let attacks = board.attacks[team_idx];
-    (attacks.count_ones() as Value) * 0.02 // Per square-attacked
+    (attacks.count_ones() as f32) * 0.02 // Per square-attacked
 }
 
-pub fn eval_king_safety(board: &Board, team_idx: usize, opp_attack_power: Value) -> Value {
+pub fn eval_king_safety(board: &Board, team_idx: usize, opp_attack_power: f32) -> f32 {
     if opp_attack_power <= 0.0 {
         return 0.0;
     }
@@ -166,8 +207,8 @@ pub fn eval_king_safety(board: &Board, team_idx: usize, opp_attack_power: Value)
     let accessibility =
         lookup_gen::get_piece_tos(PIECE_QUEEN, king, king_pos_idx, board.occupancy[team_idx]).count_ones();
 
-    dual_weight(eval_lookup::KING_PAWN_COVER, opp_attack_power) * (covering_pawns as Value) +
-    dual_weight(eval_lookup::KING_ACCESSIBILITY, opp_attack_power) * (accessibility as Value)
+    dual_weight(eval_lookup::KING_PAWN_COVER, opp_attack_power) * (covering_pawns as f32) +
+    dual_weight(eval_lookup::KING_ACCESSIBILITY, opp_attack_power) * (accessibility as f32)
 }
 
 pub fn is_special_draw(board: &Board) -> bool {
@@ -178,15 +219,15 @@ pub fn is_special_draw(board: &Board) -> bool {
         let num_pieces = (board.combined_occupancy().count_ones() - 2) as usize;
 
         if num_pieces <= MAX_PIECES_FOR_DRAW {
-            const SIMPLE_PIECE_VALS: [Value; NUM_PIECES] = [1.0, 3.0, 3.5, 5.0, 10.0, 0.0];
-            let mut material_evals: [Value; 2] = [0.0, 0.0];
+            const SIMPLE_PIECE_VALS: [f32; NUM_PIECES] = [1.0, 3.0, 3.5, 5.0, 10.0, 0.0];
+            let mut material_evals: [f32; 2] = [0.0, 0.0];
             for cur_team_idx in 0..2 {
                 for piece_idx in 0..NUM_PIECES_NO_KING {
                     if piece_idx == PIECE_PAWN {
                         continue;
                     }
                     let count = board.pieces[cur_team_idx][piece_idx].count_ones();
-                    material_evals[cur_team_idx] += (count as Value) * SIMPLE_PIECE_VALS[piece_idx];
+                    material_evals[cur_team_idx] += (count as f32) * SIMPLE_PIECE_VALS[piece_idx];
                 }
             }
 
@@ -207,10 +248,10 @@ pub fn is_special_draw(board: &Board) -> bool {
     false
 }
 
-fn eval_team(board: &Board, team_idx: usize) -> Value {
+fn eval_team(board: &Board, team_idx: usize) -> f32 {
     let opp_attack_power = calc_attacking_power(board, 1 - team_idx);
 
-    let mut value: Value = eval_material(board, team_idx, opp_attack_power);
+    let mut value: f32 = eval_material(board, team_idx, opp_attack_power);
     for piece_idx in 0..NUM_PIECES {
         value += eval_piece_type(board, team_idx, piece_idx, board.pieces[team_idx][piece_idx], opp_attack_power);
     }
@@ -248,16 +289,17 @@ pub fn is_checkmate_possible(board: &Board, team_idx: usize) -> bool {
 }
 
 // Evaluates the position from the perspective of the current turn
+// The eval terms are tuned in pawns, and the result is rounded to centipawns
 pub fn eval_board(board: &Board) -> Value {
 
     if is_special_draw(board) {
-        return 0.0;
+        return 0;
     }
 
     let self_eval = eval_team(board, board.turn_idx);
     let opp_eval = eval_team(board, 1 - board.turn_idx);
 
-    self_eval - opp_eval
+    ((self_eval - opp_eval) * 100.0).round() as Value
 }
 
 pub fn print_eval(board: &Board) {
@@ -300,13 +342,13 @@ pub fn print_eval(board: &Board) {
 }
 
 // Evaluates a move
-pub fn eval_move(board: &Board, mv: &Move) -> Value {
-    const CAPTURE_BASE_BONUS: Value = 1.0;
-    const CHECK_BONUS: Value = 1.0;
-    const PIN_BONUS: Value = 0.0;
-    const TURN_BONUS: Value = 0.1;
+pub fn eval_move(board: &Board, mv: &Move) -> f32 {
+    const CAPTURE_BASE_BONUS: f32 = 1.0;
+    const CHECK_BONUS: f32 = 1.0;
+    const PIN_BONUS: f32 = 0.0;
+    const TURN_BONUS: f32 = 0.1;
 
-    let mut eval: Value = 0.0;
+    let mut eval: f32 = 0.0;
 
     let to_idx = bm_to_idx(mv.to);
     let to_defended = (board.attacks[1 - board.turn_idx] & mv.to) != 0;
@@ -320,7 +362,7 @@ pub fn eval_move(board: &Board, mv: &Move) -> Value {
     }
 
     if mv.has_flag(Move::FLAG_CAPTURE) {
-        let mut capture_val: Value = CAPTURE_BASE_BONUS;
+        let mut capture_val: f32 = CAPTURE_BASE_BONUS;
 
         if mv.has_flag(Move::FLAG_EN_PASSANT) {
             capture_val += eval_lookup::PIECE_BASE_VALUE[PIECE_PAWN][0];
@@ -369,8 +411,4 @@ pub fn eval_move(board: &Board, mv: &Move) -> Value {
     eval += TURN_BONUS;
 
     eval
-}
-
-pub fn to_centipawns(value: Value) -> i64 {
-    (value * 100.0).round() as i64
 }
\ No newline at end of file
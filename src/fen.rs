@@ -1,4 +1,4 @@
-use crate::bitmask::{bm_from_coord, bm_from_xy, bm_get, bm_to_coord};
+use crate::bitmask::{bm_from_coord, bm_from_xy, bm_get, bm_make_row, bm_to_coord, bm_to_idx};
 use crate::board::*;
 
 type Result<T> = std::result::Result<T, FenError>;
@@ -124,13 +124,37 @@ pub fn load_fen_from_parts(fen_parts: &Vec<String>) -> Result<Board> {
 
             for ch in castle_str.chars() {
                 let team_idx = if ch.is_ascii_uppercase() { 0 } else { 1 };
-                if ch.eq_ignore_ascii_case(&'K') {
-                    board.castle_rights[team_idx][1] = true;
-                } else if ch.eq_ignore_ascii_case(&'Q') {
-                    board.castle_rights[team_idx][0] = true;
-                } else {
-                    throw_err(format!("invalid castle string \"{castle_str}\", bad char \'{ch}\'").as_str())?;
+                let back_rank = bm_make_row([0, 7][team_idx]);
+                let king = board.pieces[team_idx][PIECE_KING] & back_rank;
+                if king == 0 {
+                    throw_err(format!("invalid castle string \"{castle_str}\", king of team idx {team_idx} is not on its back rank").as_str())?;
                 }
+
+                let king_x = (bm_to_idx(king) % 8) as u8;
+                let rooks = board.pieces[team_idx][PIECE_ROOK] & back_rank;
+                let rook_x: u8 = if ch.eq_ignore_ascii_case(&'K') || ch.eq_ignore_ascii_case(&'Q') {
+                    // Standard/X-FEN, which means the outermost rook on that side of the king
+                    let is_king_side = ch.eq_ignore_ascii_case(&'K');
+                    let side_rooks = if is_king_side { rooks & !((king << 1) - 1) } else { rooks & (king - 1) };
+                    if side_rooks == 0 {
+                        throw_err(format!("invalid castle string \"{castle_str}\", no rook to castle with for \'{ch}\'").as_str())?;
+                    }
+
+                    let rook_idx = if is_king_side { 63 - side_rooks.leading_zeros() } else { side_rooks.trailing_zeros() };
+                    (rook_idx % 8) as u8
+                } else if ('a'..='h').contains(&ch.to_ascii_lowercase()) {
+                    // Shredder-FEN (or X-FEN for an inner rook), which gives the file of the rook
+                    let file_x = (ch.to_ascii_lowercase() as u8) - b'a';
+                    if file_x == king_x || (rooks & bm_from_xy(file_x as i64, [0, 7][team_idx])) == 0 {
+                        throw_err(format!("invalid castle string \"{castle_str}\", no rook to castle with for \'{ch}\'").as_str())?;
+                    }
+                    file_x
+                } else {
+                    return throw_err(format!("invalid castle string \"{castle_str}\", bad char \'{ch}\'").as_str());
+                };
+
+                let side = (rook_x > king_x) as usize;
+                board.castle_rights[team_idx][side] = Some(rook_x);
             }
         }
     }
@@ -163,7 +187,14 @@ pub fn load_fen_from_parts(fen_parts: &Vec<String>) -> Result<Board> {
         }
     }
 
-    // We don't need the move number
+    // Read full-move counter
+    if fen_parts.len() >= 6 {
+        let full_move_counter = &fen_parts[5];
+        match full_move_counter.parse::<u16>() {
+            Ok(x) => { board.full_move_counter = x.max(1) },
+            _ => { throw_err(format!("invalid full-move counter \"{full_move_counter}\"").as_str())?; }
+        }
+    }
 
     // Full update again
     board.full_update();
@@ -176,7 +207,17 @@ pub fn load_fen(fen: &str) -> Result<Board> {
     load_fen_from_parts(&fen_parts)
 }
 
+// Writes castle rights as X-FEN, which is the same as standard FEN for normal chess positions
 pub fn make_fen(board: &Board) -> String {
+    _make_fen(board, false)
+}
+
+// Writes castle rights as Shredder-FEN (the files of the castling rooks, e.g. "HAha")
+pub fn make_shredder_fen(board: &Board) -> String {
+    _make_fen(board, true)
+}
+
+fn _make_fen(board: &Board, shredder_castling: bool) -> String {
     // TODO: This code is pretty messy and generally lame
 
     use std::fmt::Write;
@@ -237,8 +278,20 @@ pub fn make_fen(board: &Board) -> String {
     let mut castle_rights_stream: String = String::new();
     for team_idx in 0..2 {
         for side in (0..2).rev() {
-            if board.castle_rights[team_idx][side] {
-                let side_char = if side == 0 { 'Q' } else { 'K' };
+            if let Some(rook_x) = board.castle_rights[team_idx][side] {
+                let rook = board.castle_rook_mask(team_idx, side);
+                let back_rank_rooks = board.pieces[team_idx][PIECE_ROOK] & bm_make_row([0, 7][team_idx]);
+
+                // X-FEN only uses the file when there is another rook further out on the same side
+                let outer_rooks = if side == 0 { back_rank_rooks & (rook - 1) } else { back_rank_rooks & !((rook << 1) - 1) };
+
+                let side_char = if shredder_castling || outer_rooks != 0 {
+                    (b'A' + rook_x) as char
+                } else if side == 0 {
+                    'Q'
+                } else {
+                    'K'
+                };
                 write!(
                     castle_rights_stream, "{}",
                     if team_idx == 0 { side_char.to_ascii_uppercase() } else { side_char.to_ascii_lowercase() }
@@ -265,8 +318,7 @@ pub fn make_fen(board: &Board) -> String {
         }
     }
 
-    write!(result, " {} {}", board.half_move_counter, 1).unwrap(); // Write half move and normal move counter
-    // TODO: Add actual move counter
+    write!(result, " {} {}", board.half_move_counter, board.full_move_counter).unwrap(); // Write half move and full move counter
 
     result
 }
@@ -0,0 +1,143 @@
+use crate::board::*;
+use crate::eval::{DARK_SQUARES, LIGHT_SQUARES};
+use crate::move_gen;
+use crate::zobrist::Hash;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameResult {
+    Checkmate { winner_team_idx: usize },
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial
+}
+
+impl GameResult {
+    // Returns the team that won, or None for a draw
+    pub fn winner_team_idx(&self) -> Option<usize> {
+        match self {
+            GameResult::Checkmate { winner_team_idx } => Some(*winner_team_idx),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameResult::Checkmate { winner_team_idx } => write!(f, "{} wins by checkmate", ["White", "Black"][*winner_team_idx]),
+            GameResult::Stalemate => write!(f, "draw by stalemate"),
+            GameResult::ThreefoldRepetition => write!(f, "draw by threefold repetition"),
+            GameResult::FiftyMoveRule => write!(f, "draw by the fifty-move rule"),
+            GameResult::InsufficientMaterial => write!(f, "draw by insufficient material")
+        }
+    }
+}
+
+// Returns true if neither player can ever checkmate, no matter how badly the other plays
+// That is: only kings, a single minor piece, or only bishops that are all on the same color
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let heavy_pieces = board.combined_pieces(PIECE_PAWN) | board.combined_pieces(PIECE_ROOK) | board.combined_pieces(PIECE_QUEEN);
+    if heavy_pieces != 0 {
+        return false;
+    }
+
+    let knights = board.combined_pieces(PIECE_KNIGHT);
+    let bishops = board.combined_pieces(PIECE_BISHOP);
+    if (knights | bishops).count_ones() <= 1 {
+        return true;
+    }
+
+    knights == 0 && ((bishops & LIGHT_SQUARES) == 0 || (bishops & DARK_SQUARES) == 0)
+}
+
+//////////////////////////////////////////////////////////////////////////
+
+// A game from a starting position, with every move and position hash since
+#[derive(Debug, Clone)]
+pub struct Game {
+    start_board: Board,
+    board: Board,
+    moves: Vec<Move>,
+
+    // Hash of every position in the game, starting with the start position and ending with the current one
+    hashes: Vec<Hash>
+}
+
+impl Game {
+    pub fn new(start_board: &Board) -> Game {
+        Game {
+            start_board: *start_board,
+            board: *start_board,
+            moves: Vec::new(),
+            hashes: vec![start_board.hash]
+        }
+    }
+
+    pub fn start_board(&self) -> &Board {
+        &self.start_board
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn moves(&self) -> &Vec<Move> {
+        &self.moves
+    }
+
+    pub fn hash_history(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    // Hashes of every position before the current one, for seeding repetition detection in the search
+    pub fn prev_hashes(&self) -> &[Hash] {
+        &self.hashes[..(self.hashes.len() - 1)]
+    }
+
+    pub fn do_move(&mut self, mv: &Move) {
+        self.board.do_move(mv);
+        self.moves.push(*mv);
+        self.hashes.push(self.board.hash);
+    }
+
+    // How many times the current position has occurred in the game, including now
+    pub fn repetition_count(&self) -> usize {
+        // Positions from before the last capture or pawn move can't repeat
+        let cur_idx = self.hashes.len() - 1;
+        let max_lookback = usize::min(self.board.half_move_counter as usize, cur_idx);
+
+        let mut count = 1;
+        for i in (4..=max_lookback).step_by(2) {
+            if self.hashes[cur_idx - i] == self.board.hash {
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    // Returns the result if the game is over
+    pub fn get_result(&self) -> Option<GameResult> {
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(&self.board, &mut moves);
+        if moves.is_empty() {
+            return if self.board.checkers != 0 {
+                Some(GameResult::Checkmate { winner_team_idx: 1 - self.board.turn_idx })
+            } else {
+                Some(GameResult::Stalemate)
+            };
+        }
+
+        if self.board.half_move_counter >= 100 {
+            // 50 moves for each player
+            Some(GameResult::FiftyMoveRule)
+        } else if self.repetition_count() >= 3 {
+            Some(GameResult::ThreefoldRepetition)
+        } else if is_insufficient_material(&self.board) {
+            Some(GameResult::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+}
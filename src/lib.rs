@@ -1,7 +1,13 @@
 pub mod board;
+pub mod game;
 pub mod fen;
 pub mod pgn;
+pub mod epd;
+pub mod book;
+pub mod syzygy;
+pub mod tablebase;
 pub mod search;
+pub mod perft;
 pub mod move_gen;
 pub mod eval;
 pub mod transpos;
@@ -12,16 +18,19 @@ pub mod zobrist;
 pub mod bitmask;
 pub mod lookup_gen;
 pub mod lookup_gen_magic;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub mod lookup_gen_pext;
 pub mod time_manager;
 mod eval_lookup;
+mod polyglot_keys;
 
 static INIT_ONCE: std::sync::Once = std::sync::Once::new();
 
 fn _init() {
     lookup_gen::init();
-    #[cfg(not(debug_assertions))]
-    lookup_gen_magic::init();
-    zobrist::init();
+    lookup_gen_magic::init(); // Needs the basic lookup tables
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    lookup_gen_pext::init();
 }
 
 pub fn init() {
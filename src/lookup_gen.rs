@@ -1,6 +1,8 @@
 use crate::bitmask::*;
 use crate::board::*;
 use crate::lookup_gen_magic;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+use crate::lookup_gen_pext;
 
 // Basic lookup tables for possible moves
 static mut LT_KNIGHT_MOVE: [BitMask; 64] = [0; 64];
@@ -211,7 +213,17 @@ pub fn get_slider_tos_slow(piece_idx: usize, piece_pos_idx: usize, occupy: BitMa
     }
 }
 
+// Uses PEXT lookups if they are enabled, otherwise magic lookups
 pub fn get_slider_tos_fast(piece_idx: usize, piece_pos_idx: usize, occupy: BitMask) -> BitMask {
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    if lookup_gen_pext::is_enabled() {
+        return get_slider_tos_pext(piece_idx, piece_pos_idx, occupy);
+    }
+
+    get_slider_tos_magic(piece_idx, piece_pos_idx, occupy)
+}
+
+pub fn get_slider_tos_magic(piece_idx: usize, piece_pos_idx: usize, occupy: BitMask) -> BitMask {
     match piece_idx {
         PIECE_BISHOP => lookup_gen_magic::get_bishop_moves(piece_pos_idx, occupy),
         PIECE_ROOK => lookup_gen_magic::get_rook_moves(piece_pos_idx, occupy),
@@ -222,15 +234,25 @@ pub fn get_slider_tos_fast(piece_idx: usize, piece_pos_idx: usize, occupy: BitMa
     }
 }
 
+// Only call this if lookup_gen_pext::is_enabled()
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub fn get_slider_tos_pext(piece_idx: usize, piece_pos_idx: usize, occupy: BitMask) -> BitMask {
+    match piece_idx {
+        PIECE_BISHOP => lookup_gen_pext::get_bishop_moves(piece_pos_idx, occupy),
+        PIECE_ROOK => lookup_gen_pext::get_rook_moves(piece_pos_idx, occupy),
+        PIECE_QUEEN => lookup_gen_pext::get_bishop_moves(piece_pos_idx, occupy) | lookup_gen_pext::get_rook_moves(piece_pos_idx, occupy),
+        _ => {
+            panic!("Piece is not a slider")
+        }
+    }
+}
+
 pub fn get_piece_tos(piece_idx: usize, piece_pos: BitMask, piece_pos_idx: usize, occupy: BitMask) -> BitMask {
     let occupy = occupy & !piece_pos;
 
     match piece_idx {
         PIECE_BISHOP | PIECE_ROOK | PIECE_QUEEN => {
-            #[cfg(not(debug_assertions))]
-            return get_slider_tos_fast(piece_idx, piece_pos_idx, occupy);
-            #[cfg(debug_assertions)]
-            get_slider_tos_slow(piece_idx, piece_pos_idx, occupy)
+            get_slider_tos_fast(piece_idx, piece_pos_idx, occupy)
         },
         _ => { // Non-sliding
             get_piece_base_tos(piece_idx, piece_pos_idx)
@@ -250,14 +272,11 @@ pub fn get_ray_mask(idx_from: usize, idx_towards: usize) -> BitMask {
     unsafe { LT_RAY[idx_from][idx_towards] }
 }
 
+// Nothing is printed, as stdout is the UCI stream
 pub fn init() {
-    println!("Initializing move lookup tables...");
     for x in 0..8 {
         for y in 0..8 {
             init_at_pos(x, y);
         }
     }
-
-    println!("Initializing lookup tables...");
-    println!(" > Done!");
 }
\ No newline at end of file
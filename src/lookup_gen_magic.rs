@@ -1,4 +1,3 @@
-use rand::RngCore;
 use crate::bitmask::*;
 use crate::board::*;
 use crate::lookup_gen;
@@ -27,6 +26,44 @@ impl MagicEntry {
     }
 }
 
+// Found by find_magic_factors(), so that the tables are the same every run
+pub const MAGIC_FACTORS_BISHOP: [u64; 64] = [
+    0x0010810801044200, 0x20B0042800504010, 0x0088182100200802, 0x0808218062008040,
+    0x8B01114001A05000, 0x2204882088400000, 0x0004210802100064, 0x0202004048080888,
+    0x0140102008010060, 0x4020102908310040, 0x0006A88805006004, 0x02240C0411800004,
+    0x00000404A0004000, 0x00452501084006A0, 0x008C488210100C01, 0x000061004804040A,
+    0x2004A44010040130, 0x00201218680084A0, 0x00C8004404401202, 0x0004009A02520280,
+    0x0004005180A08392, 0x8000800102600200, 0x1146008108010494, 0x4040440026021001,
+    0x0984107804111000, 0x0184024120080143, 0x080C10120A008010, 0x0208080001820022,
+    0x0106040002008201, 0x822802032C220100, 0x0821084002081418, 0x2004022143028600,
+    0x0010082101040408, 0x2010882882041010, 0x080200F010020182, 0x00082020A0380080,
+    0x0010028200026200, 0x0001100300002400, 0xD018080040013500, 0x0002040245410040,
+    0x4804022010000400, 0x202280A820831808, 0x0082041402000401, 0x0000648420204400,
+    0x5000400109000600, 0x0440022404480101, 0x003001A204000080, 0x0013022082001100,
+    0x090406080C041000, 0x102A208404A01000, 0x8004004A08040750, 0x00450000420A1044,
+    0x0000801002120080, 0x0104200A30224341, 0x0C04200206020400, 0x0004100204490200,
+    0x0000141401080800, 0x2020128400C21090, 0x8942000201A41400, 0x0200080160840450,
+    0x020200040430C400, 0x00020020C4050200, 0x1000081005020400, 0x002A204404860042,
+];
+pub const MAGIC_FACTORS_ROOK: [u64; 64] = [
+    0x0080002C40001080, 0x204002D001200048, 0x0A00208042001108, 0x0080100004280080,
+    0x1080080014000280, 0x0100190004004608, 0x0400100212940801, 0x0300002080490006,
+    0x0102800486400060, 0xC0C20040E1020280, 0x0002002200104080, 0x120A004059201200,
+    0x0202000422001088, 0x0042001810A200C4, 0x0402000A00010C88, 0x1260800180004300,
+    0x4400808000C00020, 0x080844C000201008, 0x0001820023104200, 0x000A020050400820,
+    0x000180800C000800, 0x120080804E000C00, 0x005014004110482A, 0x00400A0000806104,
+    0x0040248080004000, 0x021000C240002002, 0x2001130100402000, 0x2120080480100080,
+    0x0008150100106800, 0x0802000A00281014, 0x001008A400061001, 0x1005114200040191,
+    0x2189804010800A20, 0x1020200A82804000, 0x0005002009003040, 0x2020100080800800,
+    0x0014000800800481, 0x0020800200800400, 0x00A0820804000110, 0x2000248112000244,
+    0x8044400080E08000, 0x0C10002001C84000, 0x0010002000108080, 0x0100900300610008,
+    0x0921000D08010010, 0x000200A89002000C, 0x0488300142040028, 0x00041090C4020011,
+    0x0022A1028000C900, 0x00090142002A8200, 0x0416200010410100, 0x04480820C0120200,
+    0x4020340081080080, 0x0442005008440200, 0x832002080F100400, 0x0010800900314080,
+    0x4002004920110282, 0x0040804204201102, 0x2004800810220042, 0x0000201000481501,
+    0x443A00100860E482, 0x0012002410011882, 0x1301005405820001, 0x4010004300802402,
+];
+
 static mut LT_MAGICS_BISHOP: [MagicEntry; 64] = [MagicEntry::new(); 64];
 static mut LT_MAGICS_ROOK: [MagicEntry; 64] = [MagicEntry::new(); 64];
 
@@ -46,92 +83,135 @@ pub fn get_rook_moves(pos_idx: usize, occupy: BitMask) -> BitMask {
     }
 }
 
-pub fn init() {
-    println!("Generating magic bitboards...");
+// Simple xorshift PRNG, so that the same magic factors are found every time
+// Ref: https://www.chessprogramming.org/Looking_for_Magics
+struct MagicRng(u64);
 
-    let mut rng = rand::rngs::ThreadRng::default();
+impl MagicRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
 
-    let mut total_table_size = 0;
+    // Magic factors with few bits set work best
+    fn next_sparse_u64(&mut self) -> u64 {
+        self.next_u64() & self.next_u64() & self.next_u64()
+    }
+}
 
-    for pos_idx in 0..64 {
-        let mask = bm_from_idx(pos_idx);
-        let (x, y) = bm_to_xy(mask);
-        const BOARD_EDGES_X: BitMask = bm_make_column(0) | bm_make_column(7);
-        const BOARD_EDGES_Y: BitMask = bm_make_row(0) | bm_make_row(7);
+const MAGIC_RNG_SEED: u64 = 0x42_6F_61_72_64_43_72_62; // "BoardCrb"
 
-        let base_moves_bishop = lookup_gen::get_piece_base_tos(PIECE_BISHOP, pos_idx) & !BOARD_EDGES_X & !BOARD_EDGES_Y;
-        let base_moves_rook = (bm_make_column(x) & !BOARD_EDGES_Y) | (bm_make_row(y) & !BOARD_EDGES_X) & !mask;
+// Squares a slider's moves depend on (the ones that can be blocked)
+pub fn get_relevant_mask(is_bishop: bool, pos_idx: usize) -> BitMask {
+    let mask = bm_from_idx(pos_idx);
+    let (x, y) = bm_to_xy(mask);
+    const BOARD_EDGES_X: BitMask = bm_make_column(0) | bm_make_column(7);
+    const BOARD_EDGES_Y: BitMask = bm_make_row(0) | bm_make_row(7);
 
-        for i in 0..2 {
+    if is_bishop {
+        lookup_gen::get_piece_base_tos(PIECE_BISHOP, pos_idx) & !BOARD_EDGES_X & !BOARD_EDGES_Y
+    } else {
+        ((bm_make_column(x) & !BOARD_EDGES_Y) | (bm_make_row(y) & !BOARD_EDGES_X)) & !mask
+    }
+}
 
-            let is_bishop = i == 0;
-            let piece_idx = if is_bishop { PIECE_BISHOP } else { PIECE_ROOK };
-            let base_moves = if is_bishop { base_moves_bishop } else { base_moves_rook };
+// Every subset of the relevant squares
+// Ref: https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
+pub fn get_occupancy_subsets(relevant_mask: BitMask) -> Vec<BitMask> {
+    let mut occ_subsets = Vec::new();
+    let mut occ_subset: BitMask = 0;
+    loop {
+        occ_subsets.push(occ_subset);
+
+        occ_subset = occ_subset.wrapping_sub(relevant_mask) & relevant_mask;
+        if occ_subset == 0 {
+            break;
+        }
+    }
+    occ_subsets
+}
 
-            let mut occ_subsets = Vec::new();
+fn _is_valid_magic_factor(relevant_mask: BitMask, magic_factor: u64, occ_subsets: &[BitMask], test_table: &mut Vec<bool>) -> bool {
+    let magic_entry = MagicEntry {
+        mask: relevant_mask,
+        magic_factor,
+        shift: (64 - relevant_mask.count_ones()) as u8,
+        table_offset: 0
+    };
+
+    test_table.clear();
+    test_table.resize(1 << relevant_mask.count_ones(), false);
+    for subset in occ_subsets {
+        let table_bool = &mut test_table[magic_entry.index(*subset)];
+        if *table_bool {
+            return false; // Duplicate
+        }
+        *table_bool = true;
+    }
 
-            // https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
-            let mut occ_subset = 0;
-            loop {
-                occ_subsets.push(occ_subset);
+    true
+}
 
-                occ_subset = (occ_subset - base_moves) & base_moves;
-                if occ_subset == 0 {
-                    break;
+// Searches for the magic factors from scratch, which is how MAGIC_FACTORS_BISHOP and MAGIC_FACTORS_ROOK were made
+// Returns (bishop factors, rook factors)
+pub fn find_magic_factors() -> ([u64; 64], [u64; 64]) {
+    let mut rng = MagicRng(MAGIC_RNG_SEED);
+    let mut bishop_factors = [0; 64];
+    let mut rook_factors = [0; 64];
+    let mut test_table = Vec::new();
+    for pos_idx in 0..64 {
+        for is_bishop in [true, false] {
+            let relevant_mask = get_relevant_mask(is_bishop, pos_idx);
+            let occ_subsets = get_occupancy_subsets(relevant_mask);
+            let magic_factor = loop {
+                let magic_factor = rng.next_sparse_u64();
+                if _is_valid_magic_factor(relevant_mask, magic_factor, &occ_subsets, &mut test_table) {
+                    break magic_factor;
                 }
+            };
+
+            if is_bishop {
+                bishop_factors[pos_idx] = magic_factor;
+            } else {
+                rook_factors[pos_idx] = magic_factor;
             }
+        }
+    }
 
-            let num_bits = base_moves.count_ones();
-            let mut magic_entry = MagicEntry {
-                mask: base_moves,
-                magic_factor: 0, // To be determined
-                shift: (64 - num_bits) as u8,
-                table_offset: 0 // Set after
-            };
+    (bishop_factors, rook_factors)
+}
 
-            let cur_table_size = (1 << num_bits) as usize;
+pub fn init() {
+    let mut total_table_size = 0;
 
-            let mut test_table = Vec::new();
-            test_table.resize(cur_table_size, false);
-
-            loop {
-                // Reset the table
-                test_table.fill(false);
-
-                magic_entry.magic_factor = rng.next_u64() & rng.next_u64() & rng.next_u64();
-
-                let mut has_duplicates = false;
-                for subset in &occ_subsets {
-                    let idx = magic_entry.index(*subset);
-                    let table_bool = &mut test_table[idx];
-                    if *table_bool {
-                        // Duplicate
-                        has_duplicates = true;
-                        break;
-                    } else {
-                        *table_bool = true;
-                    }
-                }
+    for pos_idx in 0..64 {
+        for is_bishop in [true, false] {
+            let piece_idx = if is_bishop { PIECE_BISHOP } else { PIECE_ROOK };
+            let relevant_mask = get_relevant_mask(is_bishop, pos_idx);
+            let num_bits = relevant_mask.count_ones();
+            let magic_entry = MagicEntry {
+                mask: relevant_mask,
+                magic_factor: if is_bishop { MAGIC_FACTORS_BISHOP[pos_idx] } else { MAGIC_FACTORS_ROOK[pos_idx] },
+                shift: (64 - num_bits) as u8,
+                table_offset: total_table_size
+            };
 
-                if !has_duplicates {
-                    // Valid hash found, stop searching
-                    magic_entry.table_offset = total_table_size;
-                    if is_bishop {
-                        unsafe { LT_MAGICS_BISHOP[pos_idx] = magic_entry; }
-                    } else {
-                        unsafe { LT_MAGICS_ROOK[pos_idx] = magic_entry; }
-                    }
-                    break;
-                }
+            if is_bishop {
+                unsafe { LT_MAGICS_BISHOP[pos_idx] = magic_entry; }
+            } else {
+                unsafe { LT_MAGICS_ROOK[pos_idx] = magic_entry; }
             }
 
+            let cur_table_size = (1 << num_bits) as usize;
             if total_table_size != unsafe { LT_ALL_MOVES.len() } {
                 panic!("Total table size doesn't match expected size");
             }
             unsafe { LT_ALL_MOVES.resize(total_table_size + cur_table_size, 0); }
 
             // Populate
-            for occ_subset in occ_subsets {
+            for occ_subset in get_occupancy_subsets(relevant_mask) {
                 let idx = magic_entry.index(occ_subset);
 
                 if idx < total_table_size || idx >= total_table_size + cur_table_size {
@@ -140,7 +220,7 @@ pub fn init() {
 
                 let valid_moves = lookup_gen::get_slider_tos_slow(piece_idx, pos_idx, occ_subset);
 
-                if unsafe { LT_ALL_MOVES[idx] != 0 } {
+                if unsafe { LT_ALL_MOVES[idx] != 0 && LT_ALL_MOVES[idx] != valid_moves } {
                     panic!("Hash collision while populating table (this should never happen)");
                 }
                 unsafe { LT_ALL_MOVES[idx] = valid_moves; }
@@ -149,6 +229,4 @@ pub fn init() {
             total_table_size += cur_table_size;
         }
     }
-
-    println!(" > Total table size: {}", unsafe { LT_ALL_MOVES.len() });
-}
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+use std::arch::x86_64::_pext_u64;
+use crate::bitmask::*;
+use crate::board::*;
+use crate::lookup_gen;
+use crate::lookup_gen_magic;
+
+// Slider move lookups indexed with the BMI2 PEXT instruction, which packs the relevant occupied squares into an index
+// Only used when the CPU supports it, see is_enabled()
+// Ref: https://www.chessprogramming.org/BMI2#PEXTBitboards
+
+#[derive(Debug, Copy, Clone)]
+struct PextEntry {
+    mask: BitMask,
+    table_offset: usize
+}
+
+static mut LT_PEXT_BISHOP: [PextEntry; 64] = [PextEntry { mask: 0, table_offset: 0 }; 64];
+static mut LT_PEXT_ROOK: [PextEntry; 64] = [PextEntry { mask: 0, table_offset: 0 }; 64];
+
+static mut LT_ALL_MOVES: Vec<BitMask> = Vec::new();
+
+static mut PEXT_ENABLED: bool = false;
+
+// Always true when compiled for BMI2 (e.g. with "-C target-cpu=native"), otherwise detected in init()
+pub fn is_enabled() -> bool {
+    #[cfg(target_feature = "bmi2")]
+    return true;
+    #[cfg(not(target_feature = "bmi2"))]
+    unsafe { PEXT_ENABLED }
+}
+
+// Without BMI2 enabled at compile time, this can't be inlined, which costs some speed
+#[target_feature(enable = "bmi2")]
+fn _pext(value: u64, mask: u64) -> u64 {
+    _pext_u64(value, mask)
+}
+
+// Same as _pext(), for building the tables on any CPU
+fn _pext_slow(value: u64, mask: u64) -> u64 {
+    let mut result = 0;
+    for (i, bit) in bm_iter_bits(mask).enumerate() {
+        if value & bit != 0 {
+            result |= 1 << i;
+        }
+    }
+    result
+}
+
+// Only call these if is_enabled()
+pub fn get_bishop_moves(pos_idx: usize, occupy: BitMask) -> BitMask {
+    unsafe {
+        let entry = LT_PEXT_BISHOP[pos_idx];
+        LT_ALL_MOVES[entry.table_offset + _pext(occupy, entry.mask) as usize]
+    }
+}
+
+pub fn get_rook_moves(pos_idx: usize, occupy: BitMask) -> BitMask {
+    unsafe {
+        let entry = LT_PEXT_ROOK[pos_idx];
+        LT_ALL_MOVES[entry.table_offset + _pext(occupy, entry.mask) as usize]
+    }
+}
+
+pub fn init() {
+    unsafe { PEXT_ENABLED = std::arch::is_x86_feature_detected!("bmi2"); }
+
+    let mut all_moves = Vec::new();
+    for pos_idx in 0..64 {
+        for is_bishop in [true, false] {
+            let piece_idx = if is_bishop { PIECE_BISHOP } else { PIECE_ROOK };
+            let relevant_mask = lookup_gen_magic::get_relevant_mask(is_bishop, pos_idx);
+            let entry = PextEntry {
+                mask: relevant_mask,
+                table_offset: all_moves.len()
+            };
+
+            all_moves.resize(all_moves.len() + (1 << relevant_mask.count_ones()), 0);
+            for occ_subset in lookup_gen_magic::get_occupancy_subsets(relevant_mask) {
+                let idx = entry.table_offset + _pext_slow(occ_subset, relevant_mask) as usize;
+                all_moves[idx] = lookup_gen::get_slider_tos_slow(piece_idx, pos_idx, occ_subset);
+            }
+
+            if is_bishop {
+                unsafe { LT_PEXT_BISHOP[pos_idx] = entry; }
+            } else {
+                unsafe { LT_PEXT_ROOK[pos_idx] = entry; }
+            }
+        }
+    }
+
+    unsafe { LT_ALL_MOVES = all_moves; }
+}
@@ -135,43 +135,46 @@ pub fn generate_attacks(board: &Board, team_idx: usize, piece_idx: usize, from:
     attacks
 }
 
+// Works for any king and rook placement on the back rank, so it also covers Chess960
+// See: https://www.chessprogramming.org/Chess960#Castling_Rules
 pub fn can_castle(side: usize, board: &Board, team_idx: usize, is_in_check: bool) -> bool {
-    // From: https://github.com/ZealanL/BoardMouse/blob/4d3b6c608a3cb82a1299580a90dcb3c831fc02f8/src/Engine/MoveGen/MoveGen.cpp#L13
-    // Ordering is [Left/Queen-side, Right/King-side]
-    const CASTLE_EMPTY_MASKS: [[BitMask; 2]; 2] = [
-        [ // White
-            bm_from_coord("B1") | bm_from_coord("C1") | bm_from_coord("D1"),
-            bm_from_coord("F1") | bm_from_coord("G1")
-        ],
-
-        [ // Black
-            bm_from_coord("B8") | bm_from_coord("C8") | bm_from_coord("D8"),
-            bm_from_coord("F8") | bm_from_coord("G8")
-        ]
-    ];
-
-    // These squares cannot be in attack from the enemy in order to castle
-    const CASTLE_SAFETY_MASKS: [[BitMask; 2]; 2] = [
-        [ // White
-            bm_from_coord("C1") | bm_from_coord("D1"),
-            bm_from_coord("F1") | bm_from_coord("G1"),
-        ],
-
-        [ // Black
-            bm_from_coord("C8") | bm_from_coord("D8"), // Far
-            bm_from_coord("F8") | bm_from_coord("G8"), // Near
-        ]
-    ];
-
-    if !board.castle_rights[team_idx][side] { return false; }
+    let rook = board.castle_rook_mask(team_idx, side);
+    if rook == 0 { return false; }
     if is_in_check { return false; }
-    if (board.combined_occupancy() & CASTLE_EMPTY_MASKS[team_idx][side]) != 0 { return false; }
-    if (board.attacks[1 - team_idx] & CASTLE_SAFETY_MASKS[team_idx][side]) != 0 { return false; }
+
+    let king = board.pieces[team_idx][PIECE_KING];
+    let back_rank_y: i64 = [0, 7][team_idx];
+
+    // The king always ends up on the C or G file, and the rook on the D or F file
+    let king_to = bm_from_xy([2, 6][side], back_rank_y);
+    let rook_to = bm_from_xy([3, 5][side], back_rank_y);
+
+    let king_path = lookup_gen::get_between_mask_inclusive(bm_to_idx(king), bm_to_idx(king_to));
+    let rook_path = lookup_gen::get_between_mask_inclusive(bm_to_idx(rook), bm_to_idx(rook_to));
+
+    // Everything the king and rook travel over must be empty, other than the king and rook themselves
+    let occ_others = board.combined_occupancy() & !king & !rook;
+    if (occ_others & (king_path | rook_path)) != 0 { return false; }
+
+    // The king cannot travel through or land on an attacked square
+    if (board.attacks[1 - team_idx] & king_path) != 0 { return false; }
+
+    // The rook we are castling with might have been shielding the king's destination from a slider on the back rank
+    let opp_rank_sliders = board.pieces[1 - team_idx][PIECE_ROOK] | board.pieces[1 - team_idx][PIECE_QUEEN];
+    let king_to_rank_attacks =
+        lookup_gen::get_piece_tos(PIECE_ROOK, king_to, bm_to_idx(king_to), occ_others | rook_to) & bm_make_row(back_rank_y);
+    if (king_to_rank_attacks & opp_rank_sliders) != 0 { return false; }
 
     true
 }
 
-pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
+// Which moves _generate_moves() should output
+const GEN_ALL: u8 = 0;
+const GEN_CAPTURES: u8 = 1; // Captures and promotions (everything that isn't Move::is_quiet())
+const GEN_QUIETS: u8 = 2; // Everything else, including castling
+const GEN_EVASIONS: u8 = 3; // Every move that gets out of check, only used when we're in check
+
+fn _generate_moves<const GEN_TYPE: u8>(board: &Board, out_move_set: &mut MoveBuffer) {
     let occ_team = board.occupancy[board.turn_idx];
     let occ_opp = board.occupancy[1 - board.turn_idx];
     let occ_combined = occ_team | occ_opp;
@@ -190,13 +193,22 @@ pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
 
     let pawn_advance_dy = if board.turn_idx == 0 { 1 } else { -1 };
 
+    const PROMOTE_MASK: [BitMask; 2] = [bm_make_row(7), bm_make_row(0)];
+    let promote_mask = PROMOTE_MASK[board.turn_idx];
+
     for piece_idx in 0..NUM_PIECES {
         if (num_checkers > 1) && (piece_idx != PIECE_KING) {
             // Multiple checks, king must move
             continue;
         }
 
-        for from in bm_iter_bits(board.pieces[board.turn_idx][piece_idx]) {
+        let mut froms = board.pieces[board.turn_idx][piece_idx];
+        if GEN_TYPE == GEN_EVASIONS && piece_idx != PIECE_KING {
+            // Pinned pieces can only move along the pin, which never crosses the line between the checker and the king
+            froms &= !board.pinned[board.turn_idx];
+        }
+
+        for from in bm_iter_bits(froms) {
             let idx = bm_to_idx(from);
             let mut tos: BitMask;
             if piece_idx == PIECE_PAWN {
@@ -236,15 +248,17 @@ pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
                 // King cannot move into attacked areas
                 tos &= !board.attacks[1 - board.turn_idx];
 
-                for castle_side in 0..2 {
-                    if can_castle(castle_side, board, board.turn_idx, num_checkers != 0) {
-                        out_move_set.push(Move {
-                            from: king,
-                            to: if castle_side == 0 { bm_shift(king, -2, 0) } else { bm_shift(king, 2, 0) },
-                            from_piece_idx: PIECE_KING,
-                            to_piece_idx: PIECE_KING,
-                            flags: Move::FLAG_CASTLE
-                        });
+                if GEN_TYPE != GEN_CAPTURES && GEN_TYPE != GEN_EVASIONS { // Castling is quiet, and can't get out of check
+                    for castle_side in 0..2 {
+                        if can_castle(castle_side, board, board.turn_idx, num_checkers != 0) {
+                            out_move_set.push(Move {
+                                from: king,
+                                to: board.castle_rook_mask(board.turn_idx, castle_side), // King-takes-rook
+                                from_piece_idx: PIECE_KING,
+                                to_piece_idx: PIECE_KING,
+                                flags: Move::FLAG_CASTLE
+                            });
+                        }
                     }
                 }
 
@@ -267,11 +281,18 @@ pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
                 }
             }
 
+            // Only keep the type of moves we were asked for
+            let loud_tos = if piece_idx == PIECE_PAWN { occ_opp | board.en_passant_mask | promote_mask } else { occ_opp };
+            if GEN_TYPE == GEN_CAPTURES {
+                tos &= loud_tos;
+            } else if GEN_TYPE == GEN_QUIETS {
+                tos &= !loud_tos;
+            }
+
             for to in bm_iter_bits(tos) {
                 let mut flags: u8 = 0;
                 if piece_idx == PIECE_PAWN {
-                    const PROMOTE_MASK: [BitMask; 2] = [bm_make_row(7), bm_make_row(0)];
-                    if (to & PROMOTE_MASK[board.turn_idx]) != 0 {
+                    if (to & promote_mask) != 0 {
                         // Promotion
                         for to_piece_idx in 1..NUM_PIECES {
                             if to_piece_idx == PIECE_KING {
@@ -315,4 +336,28 @@ pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Generates all legal moves
+pub fn generate_moves(board: &Board, out_move_set: &mut MoveBuffer) {
+    _generate_moves::<GEN_ALL>(board, out_move_set);
+}
+
+// Generates all legal captures and promotions (the moves that aren't Move::is_quiet())
+pub fn generate_captures(board: &Board, out_move_set: &mut MoveBuffer) {
+    _generate_moves::<GEN_CAPTURES>(board, out_move_set);
+}
+
+// Generates all legal quiet moves, including castling
+// Together with generate_captures(), this gives every legal move
+pub fn generate_quiets(board: &Board, out_move_set: &mut MoveBuffer) {
+    _generate_moves::<GEN_QUIETS>(board, out_move_set);
+}
+
+// Generates all legal moves that get out of check: king moves, and captures or blocks of a single checker
+// Generates nothing if we aren't in check
+pub fn generate_evasions(board: &Board, out_move_set: &mut MoveBuffer) {
+    if board.checkers != 0 {
+        _generate_moves::<GEN_EVASIONS>(board, out_move_set);
+    }
+}
@@ -0,0 +1,380 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use crate::board::*;
+use crate::epd::EpdEntry;
+use crate::fen;
+use crate::move_gen;
+use crate::zobrist::Hash;
+
+// Extended perft for tracking down move generation bugs
+// See search::perft() for the simple version
+// Ref: https://www.chessprogramming.org/Perft_Results
+
+#[derive(Debug, Clone)]
+pub struct PerftConfig {
+    pub bulk_counting: bool, // Count the moves at depth 1 instead of making each one
+    pub hash_size_mbs: usize, // Size of each thread's perft hash table, 0 to disable it
+    pub num_threads: usize, // Root moves are split between threads
+    pub collect_stats: bool // Count each type of move at every depth, which disables bulk counting and hashing
+}
+
+impl PerftConfig {
+    pub fn new() -> PerftConfig {
+        PerftConfig {
+            bulk_counting: true,
+            hash_size_mbs: 0,
+            num_threads: 1,
+            collect_stats: false
+        }
+    }
+}
+
+// Counts of each type of move made at one depth, in the same format as the chessprogramming wiki tables
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: usize,
+    pub captures: usize, // Includes en passant
+    pub en_passants: usize,
+    pub castles: usize,
+    pub promotions: usize,
+    pub checks: usize,
+    pub discovered_checks: usize, // Checks where the piece that moved isn't giving check
+    pub double_checks: usize,
+    pub checkmates: usize
+}
+
+impl PerftStats {
+    pub fn add_move(&mut self, mv: &Move, next_board: &Board) {
+        self.nodes += 1;
+        self.captures += mv.has_flag(Move::FLAG_CAPTURE) as usize;
+        self.en_passants += mv.has_flag(Move::FLAG_EN_PASSANT) as usize;
+        self.castles += mv.has_flag(Move::FLAG_CASTLE) as usize;
+        self.promotions += mv.has_flag(Move::FLAG_PROMOTION) as usize;
+
+        if next_board.checkers != 0 {
+            self.checks += 1;
+
+            // When castling, only the rook can give check
+            let moved_piece_mask = if mv.has_flag(Move::FLAG_CASTLE) { mv.castle_rook_to() } else { mv.to };
+            self.discovered_checks += (next_board.checkers & moved_piece_mask == 0) as usize;
+            self.double_checks += (next_board.checkers.count_ones() > 1) as usize;
+
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_evasions(next_board, &mut moves);
+            self.checkmates += moves.is_empty() as usize;
+        }
+    }
+
+    pub fn add(&mut self, other: &PerftStats) {
+        self.nodes += other.nodes;
+        self.captures += other.captures;
+        self.en_passants += other.en_passants;
+        self.castles += other.castles;
+        self.promotions += other.promotions;
+        self.checks += other.checks;
+        self.discovered_checks += other.discovered_checks;
+        self.double_checks += other.double_checks;
+        self.checkmates += other.checkmates;
+    }
+}
+
+pub struct PerftResult {
+    pub nodes: usize,
+    pub divide: Vec<(Move, usize)>, // Nodes after each root move, in move generation order
+    pub depth_stats: Vec<PerftStats>, // Only set with PerftConfig::collect_stats, starts at depth 1
+    pub elapsed_time: f64
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone)]
+struct PerftEntry {
+    hash: Hash,
+    depth: u8, // 0 is an empty entry
+    nodes: usize
+}
+
+// Always replaces entries, as perft never revisits positions enough to need anything smarter
+struct PerftTable {
+    entries: Vec<PerftEntry>
+}
+
+impl PerftTable {
+    fn new(size_mbs: usize) -> PerftTable {
+        let num_entries = ((size_mbs * 1_000_000) / size_of::<PerftEntry>()).max(1);
+        PerftTable {
+            entries: vec![PerftEntry { hash: 0, depth: 0, nodes: 0 }; num_entries]
+        }
+    }
+
+    fn get(&self, hash: Hash, depth: u8) -> Option<usize> {
+        let entry = &self.entries[(hash % self.entries.len() as u64) as usize];
+        if entry.hash == hash && entry.depth == depth { Some(entry.nodes) } else { None }
+    }
+
+    fn set(&mut self, hash: Hash, depth: u8, nodes: usize) {
+        let num_entries = self.entries.len() as u64;
+        self.entries[(hash % num_entries) as usize] = PerftEntry { hash, depth, nodes };
+    }
+}
+
+fn _count_nodes(board: &Board, depth: u8, bulk_counting: bool, perft_table: &mut Option<PerftTable>) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+
+    if let Some(perft_table) = perft_table {
+        if let Some(nodes) = perft_table.get(board.hash, depth) {
+            return nodes;
+        }
+    }
+
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+
+    let nodes = if depth == 1 && bulk_counting {
+        moves.len()
+    } else {
+        let mut total = 0;
+        for mv in moves.iter() {
+            let mut next_board = *board;
+            next_board.do_move(mv);
+            total += _count_nodes(&next_board, depth - 1, bulk_counting, perft_table);
+        }
+        total
+    };
+
+    if let Some(perft_table) = perft_table {
+        perft_table.set(board.hash, depth, nodes);
+    }
+
+    nodes
+}
+
+// Adds each move to the stats for its depth, where depth_stats[0] is for the moves from this board
+fn _collect_stats(board: &Board, depth: u8, depth_stats: &mut [PerftStats]) -> usize {
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+
+    let mut total = 0;
+    for mv in moves.iter() {
+        let mut next_board = *board;
+        next_board.do_move(mv);
+        depth_stats[0].add_move(mv, &next_board);
+        total += if depth > 1 { _collect_stats(&next_board, depth - 1, &mut depth_stats[1..]) } else { 1 };
+    }
+    total
+}
+
+pub fn run(board: &Board, depth: u8, config: &PerftConfig) -> PerftResult {
+    let start_time = std::time::Instant::now();
+
+    if depth == 0 {
+        return PerftResult { nodes: 1, divide: Vec::new(), depth_stats: Vec::new(), elapsed_time: 0.0 };
+    }
+
+    let mut root_moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut root_moves);
+    let root_moves: Vec<Move> = root_moves.iter().copied().collect();
+
+    let mut divide: Vec<(Move, usize)> = root_moves.iter().map(|mv| (*mv, 0)).collect();
+    let mut depth_stats = vec![PerftStats::default(); if config.collect_stats { depth as usize } else { 0 }];
+
+    // Each thread takes the next root move until there are none left
+    let next_move_idx = AtomicUsize::new(0);
+    let results = Mutex::new((&mut divide, &mut depth_stats));
+    let num_threads = config.num_threads.clamp(1, root_moves.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| {
+                let mut perft_table = if config.hash_size_mbs > 0 && !config.collect_stats {
+                    Some(PerftTable::new(config.hash_size_mbs))
+                } else {
+                    None
+                };
+                let mut thread_stats = vec![PerftStats::default(); if config.collect_stats { depth as usize } else { 0 }];
+
+                loop {
+                    let move_idx = next_move_idx.fetch_add(1, Ordering::Relaxed);
+                    if move_idx >= root_moves.len() {
+                        break;
+                    }
+
+                    let mv = &root_moves[move_idx];
+                    let mut next_board = *board;
+                    next_board.do_move(mv);
+
+                    let nodes = if config.collect_stats {
+                        thread_stats[0].add_move(mv, &next_board);
+                        if depth > 1 { _collect_stats(&next_board, depth - 1, &mut thread_stats[1..]) } else { 1 }
+                    } else {
+                        _count_nodes(&next_board, depth - 1, config.bulk_counting, &mut perft_table)
+                    };
+
+                    results.lock().unwrap().0[move_idx].1 = nodes;
+                }
+
+                let mut results = results.lock().unwrap();
+                for (stats, thread_stats) in results.1.iter_mut().zip(thread_stats.iter()) {
+                    stats.add(thread_stats);
+                }
+            });
+        }
+    });
+
+    PerftResult {
+        nodes: divide.iter().map(|(_, nodes)| nodes).sum(),
+        divide,
+        depth_stats,
+        elapsed_time: start_time.elapsed().as_secs_f64()
+    }
+}
+
+// Single threaded version of run() without stats, which keeps the hash table between calls
+fn _run_with_table(board: &Board, depth: u8, perft_table: &mut Option<PerftTable>) -> PerftResult {
+    let start_time = std::time::Instant::now();
+
+    if depth == 0 {
+        return PerftResult { nodes: 1, divide: Vec::new(), depth_stats: Vec::new(), elapsed_time: 0.0 };
+    }
+
+    let mut root_moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut root_moves);
+    let divide: Vec<(Move, usize)> = root_moves.iter().map(|mv| {
+        let mut next_board = *board;
+        next_board.do_move(mv);
+        (*mv, _count_nodes(&next_board, depth - 1, true, perft_table))
+    }).collect();
+
+    PerftResult {
+        nodes: divide.iter().map(|(_, nodes)| nodes).sum(),
+        divide,
+        depth_stats: Vec::new(),
+        elapsed_time: start_time.elapsed().as_secs_f64()
+    }
+}
+
+pub fn print_result(result: &PerftResult, chess960: bool) {
+    for (mv, nodes) in &result.divide {
+        if chess960 {
+            println!("{:#}: {}", mv, nodes);
+        } else {
+            println!("{}: {}", mv, nodes);
+        }
+    }
+
+    if !result.depth_stats.is_empty() {
+        println!(
+            "\n{:>5} {:>14} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Depth", "Nodes", "Captures", "E.p.", "Castles", "Promos", "Checks", "Disc.", "Double", "Mates"
+        );
+        for (i, stats) in result.depth_stats.iter().enumerate() {
+            println!(
+                "{:>5} {:>14} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                i + 1, stats.nodes, stats.captures, stats.en_passants, stats.castles, stats.promotions,
+                stats.checks, stats.discovered_checks, stats.double_checks, stats.checkmates
+            );
+        }
+    }
+
+    let nodes_per_sec = (result.nodes as f64 / result.elapsed_time.max(0.001)) as u64;
+    println!("\nNodes Searched: {}", result.nodes);
+    println!("Time: {:.3}s ({} nps)", result.elapsed_time, nodes_per_sec);
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Clone)]
+pub struct PerftSuiteFailure {
+    pub entry_idx: usize,
+    pub fen: String,
+    pub depth: u8,
+    pub expected_nodes: usize,
+    pub nodes: usize,
+    pub divide: Vec<(Move, usize)>
+}
+
+#[derive(Debug, Clone)]
+pub struct PerftSuiteResults {
+    pub num_passed: usize,
+    pub num_failed: usize,
+    pub total_nodes: usize,
+    pub failures: Vec<PerftSuiteFailure> // Sorted by entry index
+}
+
+// Checks the "D1", "D2", etc. node counts of each entry (like in the standard perftsuite.epd), up to max_depth
+// Entries are split between threads, and each entry stops at its first mismatch
+// Each thread keeps one hash table for all of its entries
+pub fn run_test_suite(entries: &[EpdEntry], max_depth: u8, num_threads: usize, print: bool) -> PerftSuiteResults {
+    let next_entry_idx = AtomicUsize::new(0);
+    let results = Mutex::new(PerftSuiteResults {
+        num_passed: 0,
+        num_failed: 0,
+        total_nodes: 0,
+        failures: Vec::new()
+    });
+
+    std::thread::scope(|scope| {
+        for _ in 0..num_threads.max(1) {
+            scope.spawn(|| {
+                let mut perft_table = Some(PerftTable::new(16));
+
+                loop {
+                    let entry_idx = next_entry_idx.fetch_add(1, Ordering::Relaxed);
+                    if entry_idx >= entries.len() {
+                        break;
+                    }
+
+                    let entry = &entries[entry_idx];
+                    let mut perft_counts = entry.perft_counts.clone();
+                    perft_counts.retain(|(depth, _)| *depth <= max_depth);
+                    perft_counts.sort();
+
+                    let mut failure = None;
+                    let mut entry_nodes = 0;
+                    for (depth, expected_nodes) in perft_counts.iter().copied() {
+                        let result = _run_with_table(&entry.board, depth, &mut perft_table);
+                        entry_nodes += result.nodes;
+                        if result.nodes != expected_nodes {
+                            failure = Some(PerftSuiteFailure {
+                                entry_idx,
+                                fen: fen::make_fen(&entry.board),
+                                depth,
+                                expected_nodes,
+                                nodes: result.nodes,
+                                divide: result.divide
+                            });
+                            break;
+                        }
+                    }
+
+                    let mut results = results.lock().unwrap();
+                    results.total_nodes += entry_nodes;
+                    if let Some(failure) = failure {
+                        if print {
+                            println!(
+                                "#{} FAILED at depth {} (got: {}, expected: {}), fen: \"{}\"",
+                                entry_idx + 1, failure.depth, failure.nodes, failure.expected_nodes, failure.fen
+                            );
+                            for (mv, nodes) in &failure.divide {
+                                println!("    {}: {}", mv, nodes);
+                            }
+                        }
+                        results.num_failed += 1;
+                        results.failures.push(failure);
+                    } else {
+                        if print {
+                            let max_tested_depth = perft_counts.last().map(|(depth, _)| *depth).unwrap_or(0);
+                            println!("#{} passed up to depth {}", entry_idx + 1, max_tested_depth);
+                        }
+                        results.num_passed += 1;
+                    }
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.failures.sort_by_key(|failure| failure.entry_idx);
+    results
+}
@@ -107,6 +107,122 @@ pub fn move_to_algebraic_str(board: &Board, mv: &Move) -> Result<String> {
     Ok(move_str)
 }
 
+// Parses a move in standard algebraic notation (e.g. "Nbxd7+", "exd8=Q", "O-O-O") into the matching legal move
+// Check/mate markers and annotations like "!?" are allowed and ignored
+pub fn parse_algebraic(board: &Board, move_str: &str) -> Result<Move> {
+    let throw_err = |msg: &str| -> Result<Move> {
+        Err(PgnError(format!("Invalid move \"{}\" for position \"{}\", {}", move_str, fen::make_fen(board), msg)))
+    };
+
+    let mut move_buffer = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut move_buffer);
+
+    // Strip check/mate markers and annotations
+    let stripped_str = move_str.trim().trim_end_matches(['+', '#', '!', '?']);
+    if stripped_str.is_empty() || !stripped_str.is_ascii() {
+        return throw_err("bad format");
+    }
+
+    // Castling can also be written with zeros
+    let castle_str = stripped_str.replace('0', "O");
+    if castle_str == "O-O" || castle_str == "O-O-O" {
+        let is_king_side = castle_str == "O-O";
+        for mv in move_buffer.iter() {
+            if mv.has_flag(Move::FLAG_CASTLE) && (mv.to > mv.from) == is_king_side {
+                return Ok(*mv);
+            }
+        }
+
+        return throw_err("castling is not legal");
+    }
+
+    let mut chars: Vec<char> = stripped_str.chars().collect();
+
+    // Read the piece type (pawn moves don't have one)
+    let mut piece_idx = PIECE_PAWN;
+    if let Some(found_idx) = PIECE_CHARS.iter().position(|&c| c == chars[0]) {
+        if found_idx == PIECE_PAWN {
+            return throw_err("pawn moves don't have a piece letter");
+        }
+        piece_idx = found_idx;
+        chars.remove(0);
+    }
+
+    // Read the promotion piece, with or without the '='
+    let mut promotion_piece_idx: Option<usize> = None;
+    if let Some(last_char) = chars.last() {
+        if let Some(found_idx) = PIECE_CHARS.iter().position(|c| c == &last_char.to_ascii_uppercase()) {
+            if !(PIECE_KNIGHT..=PIECE_QUEEN).contains(&found_idx) || piece_idx != PIECE_PAWN {
+                return throw_err("bad promotion piece");
+            }
+            promotion_piece_idx = Some(found_idx);
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    // Read the destination square
+    if chars.len() < 2 {
+        return throw_err("missing destination square");
+    }
+    let to_file = chars[chars.len() - 2];
+    let to_rank = chars[chars.len() - 1];
+    if !('a'..='h').contains(&to_file) || !('1'..='8').contains(&to_rank) {
+        return throw_err("bad destination square");
+    }
+    let to = bm_from_coord(format!("{to_file}{to_rank}").as_str());
+    chars.truncate(chars.len() - 2);
+
+    // Whatever is left is the capture marker and the disambiguation
+    if chars.last() == Some(&'x') || chars.last() == Some(&':') {
+        chars.pop();
+    }
+
+    let mut from_file: Option<i64> = None;
+    let mut from_rank: Option<i64> = None;
+    for ch in chars {
+        if ('a'..='h').contains(&ch) && from_file.is_none() {
+            from_file = Some((ch as i64) - ('a' as i64));
+        } else if ('1'..='8').contains(&ch) && from_rank.is_none() {
+            from_rank = Some((ch as i64) - ('1' as i64));
+        } else {
+            return throw_err(format!("unexpected character \'{ch}\'").as_str());
+        }
+    }
+
+    let mut result: Option<Move> = None;
+    for mv in move_buffer.iter() {
+        if mv.from_piece_idx != piece_idx || mv.to != to || mv.has_flag(Move::FLAG_CASTLE) {
+            continue;
+        }
+
+        if mv.has_flag(Move::FLAG_PROMOTION) && Some(mv.to_piece_idx) != promotion_piece_idx {
+            continue;
+        }
+
+        if !mv.has_flag(Move::FLAG_PROMOTION) && promotion_piece_idx.is_some() {
+            continue;
+        }
+
+        let (x, y) = bm_to_xy(mv.from);
+        if from_file.is_some_and(|file| file != x) || from_rank.is_some_and(|rank| rank != y) {
+            continue;
+        }
+
+        if result.is_some() {
+            return throw_err("ambiguous move");
+        }
+        result = Some(*mv);
+    }
+
+    match result {
+        Some(mv) => Ok(mv),
+        None => throw_err("no matching legal move")
+    }
+}
+
 pub fn make_pgn(start_board: &Board, moves: &Vec<Move>) -> Result<String> {
     let mut stream: String = String::new();
     let start_fen = fen::make_fen(&start_board);
@@ -123,7 +239,7 @@ pub fn make_pgn(start_board: &Board, moves: &Vec<Move>) -> Result<String> {
     }
 
     let mut cur_board = start_board.clone();
-    let mut cur_move_number = 1;
+    let mut cur_move_number = start_board.full_move_counter;
 
     if cur_board.turn_idx == 1 {
         // We start with black's turn, so we need to add the appropriate prefix
@@ -148,4 +264,598 @@ pub fn make_pgn(start_board: &Board, moves: &Vec<Move>) -> Result<String> {
     }
 
     Ok(stream.trim().to_string())
-}
\ No newline at end of file
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub const RESULT_STRS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// A single move in a PGN game, along with its annotations and alternatives
+#[derive(Debug, Clone)]
+pub struct PgnNode {
+    pub mv: Move,
+    pub comment: Option<String>,
+    pub nags: Vec<u8>,
+
+    // Alternatives to this move, each starting from the position before it
+    pub variations: Vec<Vec<PgnNode>>
+}
+
+impl PgnNode {
+    pub fn new(mv: Move) -> PgnNode {
+        PgnNode {
+            mv,
+            comment: None,
+            nags: Vec::new(),
+            variations: Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start_board: Board,
+
+    // Comment before the first move
+    pub comment: Option<String>,
+
+    // Just the mainline moves
+    pub moves: Vec<Move>,
+
+    // The full mainline, including comments, NAGs and variations
+    pub nodes: Vec<PgnNode>,
+
+    // One of RESULT_STRS
+    pub result: String
+}
+
+impl PgnGame {
+    pub fn new(start_board: &Board) -> PgnGame {
+        PgnGame {
+            tags: Vec::new(),
+            start_board: *start_board,
+            comment: None,
+            moves: Vec::new(),
+            nodes: Vec::new(),
+            result: "*".to_string()
+        }
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str())
+    }
+
+    // Adds a move to the end of the mainline
+    pub fn add_move(&mut self, mv: Move, comment: Option<String>) {
+        let mut node = PgnNode::new(mv);
+        node.comment = comment;
+        self.moves.push(mv);
+        self.nodes.push(node);
+    }
+
+    // Sets a tag, replacing the old value if it already exists
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    pub fn make_game(&self) -> crate::game::Game {
+        let mut game = crate::game::Game::new(&self.start_board);
+        for mv in &self.moves {
+            game.do_move(mv);
+        }
+        game
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PgnToken {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+    Move(String)
+}
+
+// Characters that can make up a move, move number, or result
+fn _is_symbol_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || "_+#=:-/!?.".contains(ch)
+}
+
+// Annotation suffixes like "!?" are the same as their NAG
+fn _suffix_to_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+fn _tokenize(text: &str) -> Result<Vec<PgnToken>> {
+    let throw_err = |msg: &str| -> Result<Vec<PgnToken>> {
+        Err(PgnError(format!("Failed to read PGN, {}", msg)))
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+
+        if ch.is_whitespace() {
+            i += 1;
+        } else if ch == '%' && (i == 0 || chars[i - 1] == '\n') {
+            // Escaped line, ignore
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if ch == '[' {
+            // Tag pair, the name is a symbol and the value is a quoted string
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() { i += 1; }
+            let name_start = i;
+            while i < chars.len() && _is_symbol_char(chars[i]) { i += 1; }
+            let name: String = chars[name_start..i].iter().collect();
+            while i < chars.len() && chars[i].is_whitespace() { i += 1; }
+
+            if name.is_empty() || i >= chars.len() || chars[i] != '"' {
+                return throw_err("bad tag pair");
+            }
+            i += 1;
+
+            let mut value = String::new();
+            loop {
+                if i >= chars.len() || chars[i] == '\n' {
+                    return throw_err(format!("unterminated value for tag \"{name}\"").as_str());
+                }
+
+                if chars[i] == '\\' && i + 1 < chars.len() && (chars[i + 1] == '"' || chars[i + 1] == '\\') {
+                    value.push(chars[i + 1]);
+                    i += 2;
+                } else if chars[i] == '"' {
+                    i += 1;
+                    break;
+                } else {
+                    value.push(chars[i]);
+                    i += 1;
+                }
+            }
+
+            while i < chars.len() && chars[i].is_whitespace() { i += 1; }
+            if i >= chars.len() || chars[i] != ']' {
+                return throw_err(format!("unterminated tag \"{name}\"").as_str());
+            }
+            i += 1;
+
+            tokens.push(PgnToken::Tag(name, value));
+        } else if ch == '{' {
+            let comment_start = i + 1;
+            while i < chars.len() && chars[i] != '}' { i += 1; }
+            if i >= chars.len() {
+                return throw_err("unterminated comment");
+            }
+            let comment: String = chars[comment_start..i].iter().collect();
+            tokens.push(PgnToken::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+            i += 1;
+        } else if ch == ';' {
+            let comment_start = i + 1;
+            while i < chars.len() && chars[i] != '\n' { i += 1; }
+            let comment: String = chars[comment_start..i].iter().collect();
+            tokens.push(PgnToken::Comment(comment.trim().to_string()));
+        } else if ch == '$' {
+            i += 1;
+            let nag_start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+            let nag_str: String = chars[nag_start..i].iter().collect();
+            match nag_str.parse::<u8>() {
+                Ok(nag) => tokens.push(PgnToken::Nag(nag)),
+                Err(_) => return throw_err(format!("bad NAG \"${nag_str}\"").as_str())
+            }
+        } else if ch == '(' {
+            tokens.push(PgnToken::VariationStart);
+            i += 1;
+        } else if ch == ')' {
+            tokens.push(PgnToken::VariationEnd);
+            i += 1;
+        } else if ch == '*' {
+            tokens.push(PgnToken::Result("*".to_string()));
+            i += 1;
+        } else if _is_symbol_char(ch) {
+            let symbol_start = i;
+            while i < chars.len() && _is_symbol_char(chars[i]) { i += 1; }
+            let symbol: String = chars[symbol_start..i].iter().collect();
+
+            if RESULT_STRS.contains(&symbol.as_str()) {
+                tokens.push(PgnToken::Result(symbol));
+            } else if ch.is_ascii_digit() && symbol.contains('.') {
+                // Move number, possibly stuck to the move (e.g. "12.Nf3" or "12...Nf6")
+                let move_str = symbol.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start_matches('.');
+                if !move_str.is_empty() {
+                    tokens.push(PgnToken::Move(move_str.to_string()));
+                }
+            } else if symbol.chars().all(|c| c.is_ascii_digit()) {
+                // Move number without the period
+            } else if let Some(nag) = _suffix_to_nag(&symbol) {
+                // Annotation separated from the move
+                tokens.push(PgnToken::Nag(nag));
+            } else {
+                tokens.push(PgnToken::Move(symbol));
+            }
+        } else {
+            return throw_err(format!("unexpected character \'{ch}\'").as_str());
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn _append_comment(target: &mut Option<String>, comment: String) {
+    if let Some(existing) = target {
+        existing.push(' ');
+        existing.push_str(&comment);
+    } else {
+        *target = Some(comment);
+    }
+}
+
+// Reads moves starting at "board" until the line ends, recursing into variations
+// Returns the line and the result token, if one was found
+fn _read_line(tokens: &[PgnToken], token_idx: &mut usize, board: &Board,
+              is_variation: bool, start_comment: &mut Option<String>) -> Result<(Vec<PgnNode>, Option<String>)> {
+    let mut nodes: Vec<PgnNode> = Vec::new();
+    let mut cur_board = *board;
+    let mut prev_board = *board;
+
+    while *token_idx < tokens.len() {
+        let token = &tokens[*token_idx];
+        *token_idx += 1;
+
+        match token {
+            PgnToken::Tag(name, _) => {
+                return Err(PgnError(format!("Failed to read PGN, tag \"{name}\" found within the moves")));
+            },
+            PgnToken::Comment(comment) => {
+                match nodes.last_mut() {
+                    Some(node) => _append_comment(&mut node.comment, comment.clone()),
+                    None => _append_comment(start_comment, comment.clone())
+                }
+            },
+            PgnToken::Nag(nag) => {
+                match nodes.last_mut() {
+                    Some(node) => node.nags.push(*nag),
+                    None => return Err(PgnError("Failed to read PGN, NAG found before any move".to_string()))
+                }
+            },
+            PgnToken::VariationStart => {
+                if nodes.is_empty() {
+                    return Err(PgnError("Failed to read PGN, variation found before any move".to_string()));
+                }
+
+                // Variation comments before the first move are kept with the variation's first move
+                let mut variation_comment = None;
+                let (mut variation, _) = _read_line(tokens, token_idx, &prev_board, true, &mut variation_comment)?;
+                if let (Some(first_node), Some(comment)) = (variation.first_mut(), variation_comment) {
+                    first_node.comment = Some(match first_node.comment.take() {
+                        Some(old_comment) => format!("{comment} {old_comment}"),
+                        None => comment
+                    });
+                }
+
+                if !variation.is_empty() {
+                    nodes.last_mut().unwrap().variations.push(variation);
+                }
+            },
+            PgnToken::VariationEnd => {
+                if !is_variation {
+                    return Err(PgnError("Failed to read PGN, unmatched ')'".to_string()));
+                }
+                return Ok((nodes, None));
+            },
+            PgnToken::Result(result) => {
+                if is_variation {
+                    // Some programs put results at the end of variations, just ignore them
+                    continue;
+                }
+                return Ok((nodes, Some(result.clone())));
+            },
+            PgnToken::Move(move_str) => {
+                let mv = parse_algebraic(&cur_board, move_str)?;
+                let mut node = PgnNode::new(mv);
+
+                // Keep annotation suffixes as NAGs
+                let suffix = move_str.trim_start_matches(|c: char| c != '!' && c != '?');
+                if let Some(nag) = _suffix_to_nag(suffix) {
+                    node.nags.push(nag);
+                }
+
+                prev_board = cur_board;
+                cur_board.do_move(&mv);
+                nodes.push(node);
+            }
+        }
+    }
+
+    if is_variation {
+        return Err(PgnError("Failed to read PGN, unterminated variation".to_string()));
+    }
+
+    Ok((nodes, None))
+}
+
+// Reads a single game from PGN text
+pub fn load_pgn(pgn_str: &str) -> Result<PgnGame> {
+    let tokens = _tokenize(pgn_str)?;
+
+    let mut tags = Vec::new();
+    let mut token_idx = 0;
+    while token_idx < tokens.len() {
+        if let PgnToken::Tag(name, value) = &tokens[token_idx] {
+            tags.push((name.clone(), value.clone()));
+            token_idx += 1;
+        } else {
+            break;
+        }
+    }
+
+    let start_board = match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen_str)) => {
+            fen::load_fen(fen_str).map_err(|e| PgnError(format!("Failed to read PGN, bad FEN tag: {e}")))?
+        },
+        None => Board::start_pos()
+    };
+
+    let mut game = PgnGame::new(&start_board);
+    game.tags = tags;
+
+    let (nodes, result) = _read_line(&tokens, &mut token_idx, &start_board, false, &mut game.comment)?;
+    if token_idx < tokens.len() {
+        return Err(PgnError("Failed to read PGN, found more moves after the result".to_string()));
+    }
+
+    game.moves = nodes.iter().map(|node| node.mv).collect();
+    game.nodes = nodes;
+    game.result = match result {
+        Some(result) => result,
+        None => game.get_tag("Result").filter(|result| RESULT_STRS.contains(result)).unwrap_or("*").to_string()
+    };
+
+    Ok(game)
+}
+
+// Streams games from a PGN file (or anything else readable) one at a time
+// A malformed game is returned as an error, and reading continues with the next game
+pub struct PgnReader<R: std::io::BufRead> {
+    reader: R,
+    pending_line: Option<String>,
+    finished: bool
+}
+
+impl<R: std::io::BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> PgnReader<R> {
+        PgnReader {
+            reader,
+            pending_line: None,
+            finished: false
+        }
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        if let Some(line) = self.pending_line.take() {
+            return Ok(Some(line));
+        }
+
+        let mut bytes = Vec::new();
+        if self.reader.read_until(b'\n', &mut bytes)? == 0 {
+            return Ok(None);
+        }
+
+        // Old PGN files are often not UTF-8
+        Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    // Reads the text of the next game, which ends when the tags of the next one start
+    fn read_game_text(&mut self) -> std::io::Result<Option<String>> {
+        let mut text = String::new();
+        let mut has_moves = false;
+        let mut in_comment = false;
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim_start();
+
+            if !in_comment && has_moves && trimmed.starts_with('[') {
+                self.pending_line = Some(line);
+                break;
+            }
+
+            // Track brace comments so that lines inside them can't start a new game
+            let is_tag_line = !in_comment && (trimmed.starts_with('[') || trimmed.starts_with('%'));
+            if !is_tag_line {
+                for ch in line.chars() {
+                    if in_comment {
+                        in_comment = ch != '}';
+                    } else if ch == '{' {
+                        in_comment = true;
+                    } else if ch == ';' {
+                        break;
+                    } else if !ch.is_whitespace() {
+                        has_moves = true;
+                    }
+                }
+            }
+
+            text += &line;
+        }
+
+        if text.trim().is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(text))
+        }
+    }
+}
+
+impl<R: std::io::BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        match self.read_game_text() {
+            Ok(Some(text)) => Some(load_pgn(&text)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            },
+            Err(e) => {
+                self.finished = true;
+                Some(Err(PgnError(format!("Failed to read PGN, IO error: {e}"))))
+            }
+        }
+    }
+}
+
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const SEVEN_TAG_ROSTER_DEFAULTS: [&str; 7] = ["?", "?", "????.??.??", "?", "?", "?", "*"];
+
+// Export format wants movetext lines no longer than this
+pub const MAX_LINE_LENGTH: usize = 80;
+
+fn _escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Comments are split into words so that they can be wrapped
+fn _write_comment_tokens(tokens: &mut Vec<String>, comment: &str) {
+    // Comments can't contain their own terminator
+    let comment = comment.replace('}', "");
+    let words: Vec<&str> = comment.split_whitespace().collect();
+    if words.is_empty() {
+        tokens.push("{}".to_string());
+        return;
+    }
+
+    for (i, word) in words.iter().enumerate() {
+        let mut token = word.to_string();
+        if i == 0 {
+            token.insert(0, '{');
+        }
+        if i == words.len() - 1 {
+            token.push('}');
+        }
+        tokens.push(token);
+    }
+}
+
+// Writes the movetext of a line (and its variations) as separate tokens, so that they can be wrapped later
+fn _write_line_tokens(tokens: &mut Vec<String>, board: &Board, nodes: &[PgnNode], start_ply: usize) -> Result<()> {
+    let mut cur_board = *board;
+    let mut needs_move_number = true;
+    for (i, node) in nodes.iter().enumerate() {
+        let ply = start_ply + i;
+        let move_number = ply / 2 + 1;
+        if cur_board.turn_idx == 0 {
+            tokens.push(format!("{}.", move_number));
+        } else if needs_move_number {
+            // Black moves need their number after anything that interrupts the movetext
+            tokens.push(format!("{}...", move_number));
+        }
+        needs_move_number = false;
+
+        tokens.push(move_to_algebraic_str(&cur_board, &node.mv)?);
+
+        for nag in &node.nags {
+            tokens.push(format!("${}", nag));
+        }
+
+        if let Some(comment) = &node.comment {
+            _write_comment_tokens(tokens, comment);
+            needs_move_number = true;
+        }
+
+        for variation in &node.variations {
+            if variation.is_empty() {
+                continue;
+            }
+
+            let variation_start = tokens.len();
+            _write_line_tokens(tokens, &cur_board, variation, ply)?;
+            tokens[variation_start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+            needs_move_number = true;
+        }
+
+        cur_board.do_move(&node.mv);
+    }
+
+    Ok(())
+}
+
+// Writes a full game in PGN export format
+// The seven tag roster always comes first (with "?" for missing values), followed by any other tags
+pub fn write_pgn(game: &PgnGame) -> Result<String> {
+    let mut stream: String = String::new();
+
+    for (name, default_value) in SEVEN_TAG_ROSTER.iter().zip(SEVEN_TAG_ROSTER_DEFAULTS) {
+        let value = if *name == "Result" {
+            game.result.as_str()
+        } else {
+            game.get_tag(name).unwrap_or(default_value)
+        };
+        writeln!(stream, "[{} \"{}\"]", name, _escape_tag_value(value)).unwrap();
+    }
+
+    let start_fen = fen::make_fen(&game.start_board);
+    let mut extra_tags: Vec<(String, String)> = game.tags.iter()
+        .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()))
+        .cloned()
+        .collect();
+    if start_fen != fen::FEN_START_POS && game.get_tag("FEN").is_none() {
+        extra_tags.push(("SetUp".to_string(), "1".to_string()));
+        extra_tags.push(("FEN".to_string(), start_fen));
+    }
+    for (name, value) in &extra_tags {
+        writeln!(stream, "[{} \"{}\"]", name, _escape_tag_value(value)).unwrap();
+    }
+    writeln!(stream).unwrap();
+
+    let mut tokens: Vec<String> = Vec::new();
+    if let Some(comment) = &game.comment {
+        _write_comment_tokens(&mut tokens, comment);
+    }
+
+    // Move numbers carry on from the starting position
+    let start_ply = (game.start_board.full_move_counter as usize - 1) * 2 + game.start_board.turn_idx;
+    _write_line_tokens(&mut tokens, &game.start_board, &game.nodes, start_ply)?;
+    tokens.push(game.result.clone());
+
+    // Wrap the movetext
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+            writeln!(stream, "{}", line).unwrap();
+            line.clear();
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &token;
+    }
+    writeln!(stream, "{}", line).unwrap();
+
+    Ok(stream)
+}
@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::bitmask::*;
 use crate::board::*;
 use crate::eval::*;
@@ -6,16 +7,26 @@ use crate::move_gen;
 use crate::zobrist::Hash;
 use crate::transpos;
 use crate::thread_flag::ThreadFlag;
+use crate::syzygy;
+use crate::tablebase;
 
-fn _perft(board: &Board, depth: u8, depth_elapsed: usize, print: bool) -> usize {
+// MAKE_UNMAKE decides whether we use make_move()/unmake_move() on one board or copy the board for every move
+fn _perft<const MAKE_UNMAKE: bool>(board: &mut Board, depth: u8, depth_elapsed: usize, print: bool) -> usize {
     let mut moves = move_gen::MoveBuffer::new();
-    move_gen::generate_moves(&board, &mut moves);
+    move_gen::generate_moves(board, &mut moves);
     if depth > 1 {
         let mut total: usize = 0;
         for mv in moves.iter() {
-            let mut next_board: Board = *board;
-            next_board.do_move(&mv);
-            let sub_total = _perft(&next_board, depth - 1, depth_elapsed + 1, print);
+            let sub_total;
+            if MAKE_UNMAKE {
+                let undo_info = board.make_move(mv);
+                sub_total = _perft::<MAKE_UNMAKE>(board, depth - 1, depth_elapsed + 1, print);
+                board.unmake_move(mv, &undo_info);
+            } else {
+                let mut next_board: Board = *board;
+                next_board.do_move(mv);
+                sub_total = _perft::<MAKE_UNMAKE>(&mut next_board, depth - 1, depth_elapsed + 1, print);
+            }
             if depth_elapsed == 0 && print {
                 println!("{}: {}", mv, sub_total);
             }
@@ -41,39 +52,103 @@ fn _perft(board: &Board, depth: u8, depth_elapsed: usize, print: bool) -> usize
     }
 }
 
-pub fn perft(board: &Board, depth: u8, print: bool) -> usize { _perft(board, depth, 0, print) }
+pub fn perft(board: &Board, depth: u8, print: bool) -> usize { _perft::<false>(&mut board.clone(), depth, 0, print) }
+
+pub fn perft_make_unmake(board: &Board, depth: u8, print: bool) -> usize { _perft::<true>(&mut board.clone(), depth, 0, print) }
 
 //////////////////////////////////////////////////////////////////////////
 
-fn get_no_moves_eval(board: &Board) -> Value {
-    if board.checkers != 0 { -VALUE_CHECKMATE } else { 0.0 }
+fn get_no_moves_eval(board: &Board, ply: usize) -> Value {
+    if board.checkers != 0 { value_mated_in(ply as u32) } else { 0 }
 }
 
 pub struct SearchInfo {
     pub total_nodes: usize,
-    pub depth_hashes: [Hash; 256], // For repetition detection
+
+    // Hashes of the positions leading up to the current node, for repetition detection
+    // Starts with the game history from before the root (see Game::prev_hashes())
+    pub hash_history: Vec<Hash>,
+    pub root_history_len: usize,
 
     // See https://www.chessprogramming.org/History_Heuristic
-    pub history_values: [[[Value; 64]; NUM_PIECES]; 2],
-    pub root_best_move: Option<u8>
+    pub history_values: [[[f32; 64]; NUM_PIECES]; 2],
+    pub root_best_move: Option<Move>,
+
+    // Triangular PV table, see https://www.chessprogramming.org/Triangular_PV-Table
+    // Entry i holds the best line found from the node at ply i, so entry 0 is the root PV
+    pub pv_table: Vec<Vec<Move>>,
+
+    pub tb_hits: usize, // Number of successful tablebase probes
+
+    shared_nodes_added: usize // How many of total_nodes have been added to SearchConfig::shared_nodes
 }
 
 impl SearchInfo {
     pub fn new() -> SearchInfo {
         SearchInfo {
             total_nodes: 0,
-            depth_hashes: [0; 256],
+            hash_history: Vec::with_capacity(256),
+            root_history_len: 0,
             history_values: [[[0.0; 64]; NUM_PIECES]; 2],
-            root_best_move: None
+            root_best_move: None,
+            pv_table: Vec::new(),
+            tb_hits: 0,
+
+            shared_nodes_added: 0
         }
     }
+
+    // Adds our new nodes to the shared node count, and returns the updated count
+    fn _share_nodes(&mut self, shared_nodes: &AtomicUsize) -> usize {
+        let new_nodes = self.total_nodes - self.shared_nodes_added;
+        self.shared_nodes_added = self.total_nodes;
+        shared_nodes.fetch_add(new_nodes, Ordering::Relaxed) + new_nodes
+    }
+
+    // The principal variation from the last completed root search
+    pub fn get_pv(&self) -> &[Move] {
+        match self.pv_table.first() {
+            Some(pv) => pv.as_slice(),
+            None => &[]
+        }
+    }
+
+    // Clears the PV at this ply, called when we start searching a node
+    fn _clear_pv(&mut self, ply: usize) {
+        if self.pv_table.len() <= ply + 1 {
+            self.pv_table.resize_with(ply + 2, Vec::new);
+        }
+        self.pv_table[ply].clear();
+    }
+
+    // Sets the PV at this ply to the move followed by the PV of the child node
+    fn _update_pv(&mut self, ply: usize, mv: Move) {
+        let (cur_pvs, next_pvs) = self.pv_table.split_at_mut(ply + 1);
+        let pv = &mut cur_pvs[ply];
+        pv.clear();
+        pv.push(mv);
+        pv.extend_from_slice(&next_pvs[0]);
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct SearchConfig {
     pub null_move_pruning: bool,
     pub futility_pruning: bool,
-    pub late_move_reduction_factor: f32
+    pub late_move_reduction_factor: f32,
+    pub principal_variation_search: bool, // Search moves after the first one with a null window, and only re-search them if they beat the best move
+    pub make_unmake: bool, // Use make_move()/unmake_move() on a single board instead of copying the board for each move
+
+    pub tablebase: Option<Arc<syzygy::Tablebase>>,
+    pub dtm_tablebase: Option<Arc<tablebase::DtmTablebase>>,
+    pub root_moves: Vec<Move>, // Only these moves are searched at the root, unless empty
+    pub multi_pv: usize, // Number of best root moves to find in search_multi_pv()
+    pub max_nodes: Option<usize>, // Abort the search after this many nodes
+    pub shared_nodes: Option<Arc<AtomicUsize>>, // Nodes of every search that uses this count, which max_nodes applies to instead if set (see async_engine::do_search_thread())
+    pub mate_moves: Option<u8>, // Stop iterative deepening once we find a mate in this many moves (see async_engine::do_search_thread())
+
+    // 0 for the main search thread, Lazy SMP helper threads use this to order their moves differently (see async_engine::do_smp_search())
+    pub helper_idx: usize
 }
 
 impl SearchConfig {
@@ -81,31 +156,63 @@ impl SearchConfig {
         SearchConfig {
             null_move_pruning: true,
             futility_pruning: true,
-            late_move_reduction_factor: 1.0
+            late_move_reduction_factor: 1.0,
+            principal_variation_search: true,
+            make_unmake: true,
+
+            tablebase: None,
+            dtm_tablebase: None,
+            root_moves: Vec::new(),
+            multi_pv: 1,
+            max_nodes: None,
+            shared_nodes: None,
+            mate_moves: None,
+
+            helper_idx: 0
         }
     }
 }
 
+// A small pseudo-random bonus for a move, which is different for each helper thread
+// This is just enough to reorder moves that are rated about the same, so that helpers search different parts of the tree first
+fn _get_helper_move_noise(board: &Board, mv: &Move, helper_idx: usize) -> f32 {
+    let seed = board.hash ^ (PackedMove::from_move(mv).0 as u64) ^ (helper_idx as u64).wrapping_mul(0x9E3779B97F4A7C15);
+    let noise_bits = seed.wrapping_mul(0x2545F4914F6CDD1D) >> 54; // 10 bits
+    noise_bits as f32 * 0.0002
+}
+
 fn _search(
-    board: &Board, table: &mut transpos::Table, config: &SearchConfig, search_info: &mut SearchInfo,
+    board: &mut Board, table: &transpos::Table, config: &SearchConfig, search_info: &mut SearchInfo,
     mut lower_bound: Value, upper_bound: Value,
     depth_remaining: u8, depth_elapsed: i64,
-    stop_flag: Option<&ThreadFlag>, stop_time: Option<std::time::Instant>) -> Value {
+    stop_flag: Option<&ThreadFlag>, stop_time: Option<std::time::Instant>) -> Option<Value> {
 
     search_info.total_nodes += 1;
 
+    // Any early return leaves this node without a PV
+    let ply = depth_elapsed as usize;
+    search_info._clear_pv(ply);
+
     let in_extension = depth_remaining == 0;
 
+    // PV nodes are the ones searched with an open window, everything else is only searched to prove that it's worse (or better) than a bound
+    let is_pv_node = upper_bound - lower_bound > 1;
+    let start_lower_bound = lower_bound; // The lower bound is raised as we go, but the table entry type depends on the one we started with
+
     // Check draw by repetition
-    for i in (4..12).step_by(2) {
-        if (depth_elapsed >= i) && search_info.depth_hashes[(depth_elapsed - i) as usize] == board.hash {
-            // Loop detected
-            return 0.0;
-        } else {
-            break;
+    let history_idx = search_info.root_history_len + depth_elapsed as usize;
+    search_info.hash_history.truncate(history_idx);
+    if depth_elapsed > 0 {
+        // Positions from before the last capture or pawn move can't repeat
+        let max_lookback = usize::min(board.half_move_counter as usize, history_idx);
+        for i in (4..=max_lookback).step_by(2) {
+            if search_info.hash_history[history_idx - i] == board.hash {
+                // Loop detected
+                return Some(0);
+            }
         }
     }
-    search_info.depth_hashes[depth_elapsed as usize] = board.hash;
+    search_info.hash_history.push(board.hash);
 
     if depth_remaining >= 3 { // No point in checking at a super low depth
         let mut stop = false;
@@ -118,8 +225,17 @@ fn _search(
             }
         }
 
+        let searched_nodes = match &config.shared_nodes {
+            Some(shared_nodes) => search_info._share_nodes(shared_nodes),
+            None => search_info.total_nodes
+        };
+        if config.max_nodes.is_some_and(|max_nodes| searched_nodes >= max_nodes) {
+            stop = true;
+        }
+
         if stop {
-            return VALUE_INF;
+            // Aborted, the results of this node can't be used
+            return None;
         }
     }
 
@@ -130,7 +246,7 @@ fn _search(
         best_eval = cur_eval;
 
         if best_eval >= upper_bound {
-            return best_eval;
+            return Some(best_eval);
         } else if best_eval > lower_bound {
             lower_bound = best_eval;
         }
@@ -146,26 +262,31 @@ fn _search(
     }
 
     // Table lookup
-    let mut table_best_move: Option<u8> = None;
+    let mut table_best_move = PackedMove::NULL;
     if table_entry.is_valid() {
+        let table_eval = value_from_table(table_entry.eval, ply);
+
         // NOTE: In extensions, this depth check won't work because depth_remaining is stuck at 0
         if table_entry.depth_remaining >= depth_remaining && !in_extension {
             match table_entry.entry_type {
                 transpos::EntryType::FailLow => {
                     // Exceeds our lower bound, do a cutoff
-                    if table_entry.eval <= lower_bound {
-                        return table_entry.eval;
+                    if table_eval <= lower_bound {
+                        return Some(table_eval);
                     }
                 },
                 transpos::EntryType::FailHigh => {
-                    if table_entry.eval >= upper_bound {
+                    if table_eval >= upper_bound {
                         // Exceeds our upper bound, do a cutoff
-                        return table_entry.eval;
+                        return Some(table_eval);
                     }
                 },
                 transpos::EntryType::Exact => {
                     // Exact node, no further searching is needed
-                    return table_entry.eval;
+                    // Unless the eval is inside our window, in which case this node is part of the PV and we search it to collect the PV
+                    if table_eval <= lower_bound || table_eval >= upper_bound {
+                        return Some(table_eval);
+                    }
                 },
                 _ => {
                     panic!("Invalid or unsupported entry type: {}", table_entry.entry_type as usize);
@@ -176,7 +297,53 @@ fn _search(
         }
 
         // If we didn't hit a quick return, we can still use the best move from this entry
-        table_best_move = Some(table_entry.best_move_idx);
+        table_best_move = table_entry.best_move;
+    }
+
+    // Generated tablebase probe, which gives the exact distance to mate
+    if let Some(dtm_tablebase) = &config.dtm_tablebase {
+        if depth_elapsed > 0 && dtm_tablebase.can_probe(board) {
+            if let Some(result) = dtm_tablebase.probe(board) {
+                search_info.tb_hits += 1;
+                return Some(match result {
+                    tablebase::DtmResult::Win(plies) => value_mate_in(ply as u32 + plies),
+                    tablebase::DtmResult::Loss(plies) => value_mated_in(ply as u32 + plies),
+                    tablebase::DtmResult::Draw => 0
+                });
+            }
+        }
+    }
+
+    // Tablebase probe
+    // Only done right after a capture or pawn move, as that's when we first enter a new table
+    if let Some(tablebase) = &config.tablebase {
+        if depth_elapsed > 0 && !in_extension && board.half_move_counter == 0 && tablebase.can_probe(board) {
+            if let Some(wdl) = tablebase.probe_wdl(board) {
+                search_info.tb_hits += 1;
+
+                // Cursed wins and blessed losses are draws by the 50 move rule
+                // Wins that are reached sooner are better
+                let (tb_eval, entry_type) = if wdl > syzygy::WDL_CURSED_WIN {
+                    (VALUE_TABLEBASE_WIN - depth_elapsed as Value, transpos::EntryType::FailHigh)
+                } else if wdl < syzygy::WDL_BLESSED_LOSS {
+                    (-VALUE_TABLEBASE_WIN + depth_elapsed as Value, transpos::EntryType::FailLow)
+                } else {
+                    (0, transpos::EntryType::Exact)
+                };
+
+                // A win is only a lower bound on the eval (we could find a mate), and a loss is an upper bound
+                let is_cutoff = match entry_type {
+                    transpos::EntryType::FailHigh => tb_eval >= upper_bound,
+                    transpos::EntryType::FailLow => tb_eval <= lower_bound,
+                    _ => true
+                };
+
+                if is_cutoff {
+                    table.set(board.hash, value_to_table(tb_eval, ply), PackedMove::NULL, depth_remaining, entry_type);
+                    return Some(tb_eval);
+                }
+            }
+        }
     }
 
     // Null move pruning
@@ -190,48 +357,58 @@ fn _search(
         let is_king_and_pawn = board.occupancy[board.turn_idx] == king_and_pawn;
         if !is_king_and_pawn {
 
-            let mut next_board = board.clone();
-            next_board.do_null_move();
+            let mut board_copy: Board;
+            let mut null_undo_info = None;
+            let next_board: &mut Board = if config.make_unmake {
+                null_undo_info = Some(board.make_null_move());
+                &mut *board
+            } else {
+                board_copy = *board;
+                board_copy.do_null_move();
+                &mut board_copy
+            };
 
+            // Null window, we only need to know if we still fail high
             let next_depth = depth_remaining / 2;
             let next_result = _search(
-                &next_board, table, config, search_info,
-                -upper_bound, -upper_bound + 0.01,
+                next_board, table, config, search_info,
+                -upper_bound, -upper_bound + 1,
                 next_depth, depth_elapsed + 1,
                 stop_flag, stop_time
             );
 
-            let next_eval = decay_eval(-next_result);
+            if let Some(undo_info) = null_undo_info {
+                board.unmake_null_move(&undo_info);
+            }
+
+            let next_eval = -next_result?;
             if next_eval >= upper_bound {
-                return next_eval;
+                return Some(next_eval);
             }
         }
     }
 
     let mut moves = move_gen::MoveBuffer::new();
-    move_gen::generate_moves(&board, &mut moves);
-    if moves.is_empty() {
-        return get_no_moves_eval(board);
+    if in_extension && board.checkers == 0 {
+        // Only loud moves are allowed in extensions, so don't bother generating the quiet ones
+        // NOTE: This means stalemates aren't detected in extensions
+        move_gen::generate_captures(board, &mut moves);
+    } else {
+        if board.checkers != 0 {
+            move_gen::generate_evasions(board, &mut moves);
+        } else {
+            move_gen::generate_moves(board, &mut moves);
+        }
+
+        if moves.is_empty() {
+            return Some(get_no_moves_eval(board, ply));
+        }
     }
 
     #[derive(Copy, Clone)]
     struct RatedMove {
         idx: usize,
-        eval: Value
-    }
-
-    let table_best_move_idx;
-    if table_best_move.is_some() {
-        if (table_best_move.unwrap() as usize) < moves.len() {
-            table_best_move_idx = table_best_move.unwrap() as usize;
-        } else {
-            // Hash collision
-            // Very rare so we'll just ignore it
-            debug_assert!(false);
-            table_best_move_idx = usize::MAX;
-        }
-    } else {
-        table_best_move_idx = usize::MAX;
+        eval: f32
     }
 
     let mut rated_moves: Vec<RatedMove> = Vec::with_capacity(moves.len());
@@ -240,7 +417,11 @@ fn _search(
         let is_quiet = mv.is_quiet();
 
         if in_extension && is_quiet {
-            continue // Only loud moves allowed in extensions
+            continue // Only loud moves allowed in extensions (we can still get quiet evasions here)
+        }
+
+        if depth_elapsed == 0 && !config.root_moves.is_empty() && !config.root_moves.contains(&mv) {
+            continue
         }
 
         let mut move_eval = eval_move(board, &mv);
@@ -248,10 +429,15 @@ fn _search(
         if is_quiet {
             let history_value = search_info.history_values[board.turn_idx][mv.from_piece_idx][bm_to_idx(mv.to)];
             move_eval += history_value * 0.02;
+
+            if config.helper_idx > 0 {
+                move_eval += _get_helper_move_noise(board, &mv, config.helper_idx);
+            }
         }
 
-        if i == table_best_move_idx {
-            move_eval = Value::MAX;
+        if !table_best_move.is_null() && PackedMove::from_move(&mv) == table_best_move {
+            // If the table move came from a hash collision, it just won't match anything here
+            move_eval = f32::MAX;
         }
 
         rated_moves.push(
@@ -283,7 +469,7 @@ fn _search(
 
     if rated_moves.is_empty() {
         if in_extension {
-            return cur_eval;
+            return Some(cur_eval);
         } else {
             panic!("No rated moves in non-extension search (this should never happen)")
         }
@@ -295,8 +481,16 @@ fn _search(
         let move_eval = rated_moves[i].eval;
         let mv = &moves[move_idx];
 
-        let mut next_board: Board = board.clone();
-        next_board.do_move(mv);
+        let mut board_copy: Board;
+        let mut undo_info = None;
+        let next_board: &mut Board = if config.make_unmake {
+            undo_info = Some(board.make_move(mv));
+            &mut *board
+        } else {
+            board_copy = *board;
+            board_copy.do_move(mv);
+            &mut board_copy
+        };
 
         let gives_check = next_board.checkers != 0;
         let mut depth_reduction_f: f32 = 1.0;
@@ -313,10 +507,14 @@ fn _search(
             }
 
             // Futility pruning
-            if config.futility_pruning && i >= 1 && depth_remaining <= 4 {
-                let max_end_eval = cur_eval + 1.5 * depth_remaining as Value;
+            // Skipped once we've found a mate, as other moves could still find a shorter one
+            if config.futility_pruning && i >= 1 && depth_remaining <= 4 && lower_bound < VALUE_CHECKMATE_MIN {
+                let max_end_eval = cur_eval + 150 * depth_remaining as Value;
                 if max_end_eval < lower_bound {
                     // Prune the rest of the branch
+                    if let Some(undo_info) = undo_info {
+                        board.unmake_move(mv, &undo_info);
+                    }
                     break;
                 }
             }
@@ -324,42 +522,56 @@ fn _search(
 
         let mut depth_reduction = depth_reduction_f.clamp(0.0, depth_remaining as f32).round() as u8;
 
-        let mut next_eval: Value;
+        // Principal variation search
+        // The first move is expected to be the best one, so the rest are searched with a null window just to check that they're worse
+        let mut null_window = config.principal_variation_search && i >= 1;
+
+        let mut next_result: Option<Value>;
         loop {
-            next_eval = _search(
-                &next_board, table, config, search_info,
-                -upper_bound, -lower_bound,
+            let next_upper_bound = if null_window { lower_bound + 1 } else { upper_bound };
+            next_result = _search(
+                next_board, table, config, search_info,
+                -next_upper_bound, -lower_bound,
                 depth_remaining - depth_reduction, depth_elapsed + 1,
                 stop_flag, stop_time
             );
 
-            if next_eval.is_infinite() {
-                return VALUE_INF;
-            }
-
-            next_eval = decay_eval(-next_eval);
+            if let Some(next_eval) = next_result.map(|next_eval| -next_eval) {
+                if depth_reduction > 1 && next_eval > lower_bound {
+                    // Exceeded lower bound, we need to do a full depth search
+                    depth_reduction = 1;
+                    continue
+                }
 
-            if depth_reduction > 1 && next_eval > lower_bound {
-                // Exceeded lower bound, we need to do a full search
-                depth_reduction = 1;
-                continue
+                if null_window && is_pv_node && next_eval > lower_bound && next_eval < upper_bound {
+                    // Better than the best move so far, so we need its exact eval from a full window search
+                    null_window = false;
+                    continue
+                }
             }
 
             break;
         }
 
+        if let Some(undo_info) = undo_info {
+            board.unmake_move(mv, &undo_info);
+        }
+
+        let next_eval = -next_result?;
+
         if next_eval > best_eval {
             best_eval = next_eval;
             best_move_idx = move_idx;
             if next_eval > lower_bound {
                 lower_bound = next_eval;
+                search_info._update_pv(ply, *mv);
             }
 
             if next_eval >= upper_bound {
                 // Failed high, beta cut-off
                 if mv.is_quiet() {
                     // Higher depth means better search and thus better quality info on how good this move is
-                    let history_weight = 1.0 / (depth_elapsed as Value);
+                    let history_weight = 1.0 / (depth_elapsed as f32);
                     let history = &mut search_info.history_values[board.turn_idx][mv.from_piece_idx][bm_to_idx(mv.to)];
                     *history += history_weight;
 
@@ -368,7 +580,7 @@ fn _search(
                         let omv = &moves[rated_moves[j].idx];
                         if omv.is_quiet() {
                             let other_history = &mut search_info.history_values[board.turn_idx][omv.from_piece_idx][bm_to_idx(omv.to)];
-                            *other_history -= history_weight / (i as Value);
+                            *other_history -= history_weight / (i as f32);
                         }
                     }
                 }
@@ -378,18 +590,18 @@ fn _search(
     }
 
     table.set(
-        board.hash, best_eval,
+        board.hash, value_to_table(best_eval, ply),
         // Only update best move if we exceeded the lower bound
         if best_eval >= lower_bound {
-            best_move_idx as u8
+            PackedMove::from_move(&moves[best_move_idx])
         } else {
-            table_entry.best_move_idx
+            table_entry.best_move
         },
         depth_remaining,
         {
             if best_eval >= upper_bound {
                 transpos::EntryType::FailHigh
-            } else if best_eval < lower_bound {
+            } else if best_eval <= start_lower_bound {
                 transpos::EntryType::FailLow
             } else {
                 transpos::EntryType::Exact
@@ -398,97 +610,120 @@ fn _search(
     );
 
     if depth_elapsed == 0 {
-        search_info.root_best_move = Some(best_move_idx as u8);
+        search_info.root_best_move = Some(moves[best_move_idx]);
     }
 
-    best_eval
+    Some(best_eval)
 }
 
+// prev_hashes are the hashes of the game's positions before this board, so that we can detect repetitions through them
+// The eval is None if the search was aborted
 pub fn search(
-    board: &Board, table: &mut transpos::Table, config: &SearchConfig, depth: u8,
+    board: &Board, prev_hashes: &[Hash], table: &transpos::Table, config: &SearchConfig, depth: u8,
     guessed_eval: Option<Value>,
-    stop_flag: Option<&ThreadFlag>, stop_time: Option<std::time::Instant>) -> (Value, SearchInfo) {
+    stop_flag: Option<&ThreadFlag>, stop_time: Option<std::time::Instant>) -> (Option<Value>, SearchInfo) {
 
     let mut search_info = SearchInfo::new();
+    search_info.hash_history.extend_from_slice(prev_hashes);
+    search_info.root_history_len = prev_hashes.len();
+    let mut board = *board;
+    let board = &mut board;
+
+    let search_result = 'search: {
+        if depth >= 4 {
+            // Use an aspiration window
+            const WINDOW_RANGE_GUESS: Value = 30; // Range of the window if there is a guessed eval
+            const WINDOW_RANGE_NO_GUESS: Value = 100; // Range of the window if there isn't guessed eval
+            let window_start_center = if guessed_eval.is_some() { guessed_eval.unwrap() } else { eval_board(board) };
+
+            let mut window_min = window_start_center;
+            let mut window_max = window_start_center;
+
+            if guessed_eval.is_some() {
+                window_min -= WINDOW_RANGE_GUESS / 2;
+                window_max += WINDOW_RANGE_GUESS / 2;
+            } else {
+                window_min -= WINDOW_RANGE_NO_GUESS / 2;
+                window_max += WINDOW_RANGE_NO_GUESS / 2;
+            }
 
-    if depth >= 4 {
-        // Use an aspiration window
-        const WINDOW_RANGE_GUESS: Value = 0.3; // Range of the window if there is a guessed eval
-        const WINDOW_RANGE_NO_GUESS: Value = 1.0; // Range of the window if there isn't guessed eval
-        let window_start_center = if guessed_eval.is_some() { guessed_eval.unwrap() } else { eval_board(board) };
-
-        let mut window_min = window_start_center;
-        let mut window_max = window_start_center;
+            let search_result = _search(
+                board, table, config, &mut search_info, window_min, window_max, depth, 0, stop_flag, stop_time
+            );
 
-        if guessed_eval.is_some() {
-            window_min -= WINDOW_RANGE_GUESS / 2.0;
-            window_max += WINDOW_RANGE_GUESS / 2.0;
-        } else {
-            window_min -= WINDOW_RANGE_NO_GUESS / 2.0;
-            window_max += WINDOW_RANGE_NO_GUESS / 2.0;
+            match search_result {
+                // Window was sufficient (a result on either edge is only a bound)
+                Some(eval) if eval > window_min && eval < window_max => break 'search search_result,
+                None => break 'search None,
+                _ => {}
+            }
         }
 
-        let eval = _search(
-            board, table, config, &mut search_info, window_min, window_max, depth, 0, stop_flag, stop_time
-        );
+        _search(
+            board, table, config, &mut search_info, -VALUE_CHECKMATE, VALUE_CHECKMATE, depth, 0, stop_flag, stop_time
+        )
+    };
 
-        if eval >= window_min && eval < window_max {
-            // Window was sufficient
-            return (eval, search_info);
-        }
+    // Nodes since the last node limit check haven't been shared yet
+    if let Some(shared_nodes) = &config.shared_nodes {
+        search_info._share_nodes(shared_nodes);
     }
 
-    let search_result = _search(
-        board, table, config, &mut search_info, -VALUE_CHECKMATE, VALUE_CHECKMATE, depth, 0, stop_flag, stop_time
-    );
-
     (search_result, search_info)
 }
 
-pub fn determine_pv(mut board: Board, table: &transpos::Table) -> Vec<Move> {
-    let mut result = Vec::new();
-    let mut found_hashes = HashSet::<Hash>::new();
+// Finds the best config.multi_pv root moves, by searching again with the moves we already found excluded
+// Returns a result for each PV in ranked order, with node and tablebase hit counts that include the previous PVs
+// Returns None if the search is aborted
+pub fn search_multi_pv(
+    board: &Board, prev_hashes: &[Hash], table: &transpos::Table, config: &SearchConfig, depth: u8,
+    guessed_evals: &[Value],
+    stop_flag: Option<&ThreadFlag>, stop_time: Option<std::time::Instant>) -> Option<Vec<(Value, SearchInfo)>> {
 
-    loop {
-
-        let entry = table.get_fast(board.hash);
-
-        let valid;
-        if result.is_empty() {
-            // No PV yet, allow a locked entry
-            valid = entry.is_set();
-        } else {
-            // Require full validity
-            valid = entry.is_valid();
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+    let mut remaining_moves: Vec<Move> = moves.iter().copied().filter(|mv| config.root_moves.is_empty() || config.root_moves.contains(mv)).collect();
+
+    let mut pv_config = config.clone();
+    let mut results: Vec<(Value, SearchInfo)> = Vec::with_capacity(config.multi_pv);
+    // Always search at least once, so that positions without any moves still get an eval
+    while results.is_empty() || (results.len() < config.multi_pv && !remaining_moves.is_empty()) {
+        pv_config.root_moves.clone_from(&remaining_moves);
+
+        // The node limit is shared between all of the PVs (which a shared node count already does)
+        if let (Some(max_nodes), None, Some((_, prev_search_info))) = (config.max_nodes, &config.shared_nodes, results.last()) {
+            pv_config.max_nodes = Some(max_nodes.saturating_sub(prev_search_info.total_nodes));
         }
+        let (search_result, mut search_info) = search(
+            board, prev_hashes, table, &pv_config, depth,
+            guessed_evals.get(results.len()).copied(),
+            stop_flag, stop_time
+        );
+        let eval = search_result?;
 
-        if valid {
-            if found_hashes.contains(&board.hash) {
-                // Looped position
-                break;
-            } else {
-                found_hashes.insert(board.hash);
-            }
+        if let Some((_, prev_search_info)) = results.last() {
+            search_info.total_nodes += prev_search_info.total_nodes;
+            search_info.tb_hits += prev_search_info.tb_hits;
+        }
 
-            let mut moves = move_gen::MoveBuffer::new();
-            move_gen::generate_moves(&board, &mut moves);
+        let best_move = search_info.root_best_move;
+        results.push((eval, search_info));
 
-            let best_move_idx = entry.best_move_idx as usize;
-            if best_move_idx >= moves.len() {
-                panic!("Failed to generate PV, bad move count (hash collision?)");
-            }
-
-            let best_move = moves[best_move_idx];
-            result.push(best_move);
-            board.do_move(&best_move);
-        } else {
-            break;
+        match best_move {
+            Some(best_move) => remaining_moves.retain(|mv| *mv != best_move),
+            None => break
         }
     }
 
-    if result.is_empty() {
-        panic!("Failed to generate PV, first table entry never found");
+    // Each PV's counts include the PVs before it, so every PV gets the counts for the whole search
+    let (total_nodes, tb_hits) = results.last().map(|(_, search_info)| (search_info.total_nodes, search_info.tb_hits)).unwrap();
+    for (_, search_info) in results.iter_mut() {
+        search_info.total_nodes = total_nodes;
+        search_info.tb_hits = tb_hits;
     }
 
-    result
-}
\ No newline at end of file
+    // Search instability can give a later PV a better eval, and the best move always goes first
+    results.sort_by_key(|(eval, _)| std::cmp::Reverse(*eval));
+
+    Some(results)
+}
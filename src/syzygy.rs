@@ -0,0 +1,1224 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use crate::bitmask::*;
+use crate::board::*;
+use crate::move_gen;
+use crate::zobrist::Hash;
+
+// Syzygy endgame tablebase probing
+// Files are decoded the same way as Ronald de Man's original probing code
+// Refs:
+// - https://www.chessprogramming.org/Syzygy_Bases
+// - https://github.com/jdart1/Fathom
+
+pub type WdlScore = i32;
+pub const WDL_LOSS: WdlScore = -2;
+pub const WDL_BLESSED_LOSS: WdlScore = -1; // Loss, but drawn by the 50 move rule
+pub const WDL_DRAW: WdlScore = 0;
+pub const WDL_CURSED_WIN: WdlScore = 1; // Win, but drawn by the 50 move rule
+pub const WDL_WIN: WdlScore = 2;
+
+// Max number of pieces any Syzygy table can have
+pub const MAX_TB_PIECES: usize = 7;
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Table flags, all but SINGLE_VALUE only apply to DTZ tables
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Lookup tables for converting positions to table indices
+struct IndexTables {
+    map_pawns: [usize; 64],
+    map_b1h1h7: [usize; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; MAX_TB_PIECES],
+    lead_pawn_idx: [[u64; 64]; MAX_TB_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_TB_PIECES]
+}
+
+fn _off_a1h8(sq: usize) -> i64 {
+    (sq / 8) as i64 - (sq % 8) as i64
+}
+
+fn _flip_file(sq: usize) -> usize { sq ^ 7 }
+
+fn _flip_rank(sq: usize) -> usize { sq ^ 56 }
+
+fn _make_index_tables() -> IndexTables {
+    let mut tables = IndexTables {
+        map_pawns: [0; 64],
+        map_b1h1h7: [0; 64],
+        map_a1d1d4: [0; 64],
+        map_kk: [[0; 64]; 10],
+        binomial: [[0; 64]; MAX_TB_PIECES],
+        lead_pawn_idx: [[0; 64]; MAX_TB_PIECES],
+        lead_pawns_size: [[0; 4]; MAX_TB_PIECES]
+    };
+
+    // Squares below the a1-h8 diagonal
+    let mut code = 0;
+    for sq in 0..64 {
+        if _off_a1h8(sq) < 0 {
+            tables.map_b1h1h7[sq] = code;
+            code += 1;
+        }
+    }
+
+    // Squares in the a1-d1-d4 triangle, with the diagonal squares last
+    let mut diagonal = Vec::new();
+    code = 0;
+    for sq in 0..=27 {
+        if _off_a1h8(sq) < 0 && sq % 8 <= 3 {
+            tables.map_a1d1d4[sq] = code;
+            code += 1;
+        } else if _off_a1h8(sq) == 0 && sq % 8 <= 3 {
+            diagonal.push(sq);
+        }
+    }
+    for sq in diagonal {
+        tables.map_a1d1d4[sq] = code;
+        code += 1;
+    }
+
+    // All 462 legal placements of two kings where the first is in the a1-d1-d4 triangle
+    // If the first king is on the diagonal, the second can't be above it
+    let mut both_on_diagonal = Vec::new();
+    code = 0;
+    for idx in 0..10 {
+        for sq1 in 0..=27 {
+            // b1 is also mapped to 0, like all the squares outside of the triangle
+            if tables.map_a1d1d4[sq1] != idx || (idx == 0 && sq1 != 1) {
+                continue;
+            }
+
+            let (x1, y1) = (sq1 as i64 % 8, sq1 as i64 / 8);
+            for sq2 in 0..64 {
+                let (x2, y2) = (sq2 as i64 % 8, sq2 as i64 / 8);
+                if (x1 - x2).abs() <= 1 && (y1 - y2).abs() <= 1 {
+                    continue; // Kings are touching
+                } else if _off_a1h8(sq1) == 0 && _off_a1h8(sq2) > 0 {
+                    continue; // First king on the diagonal, second above it
+                } else if _off_a1h8(sq1) == 0 && _off_a1h8(sq2) == 0 {
+                    both_on_diagonal.push((idx, sq2));
+                } else {
+                    tables.map_kk[idx][sq2] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (idx, sq2) in both_on_diagonal {
+        tables.map_kk[idx][sq2] = code;
+        code += 1;
+    }
+
+    // binomial[k][n] is the number of ways to choose k things from n things
+    tables.binomial[0][0] = 1;
+    for n in 1..64 {
+        for k in 0..MAX_TB_PIECES.min(n + 1) {
+            tables.binomial[k][n] =
+                if k > 0 { tables.binomial[k - 1][n - 1] } else { 0 } +
+                if k < n { tables.binomial[k][n - 1] } else { 0 };
+        }
+    }
+
+    // map_pawns encodes a2-h7 so that the leading pawn (closest to the edge, then lowest rank) has the highest value
+    let mut available_squares = 47;
+    for lead_pawns_count in 1..=5 {
+        for file in 0..4 {
+            let mut idx = 0;
+            for rank in 1..7 {
+                let sq = file + rank * 8;
+                if lead_pawns_count == 1 {
+                    tables.map_pawns[sq] = available_squares;
+                    tables.map_pawns[_flip_file(sq)] = available_squares - 1;
+                    available_squares -= 2;
+                }
+
+                tables.lead_pawn_idx[lead_pawns_count][sq] = idx;
+                idx += tables.binomial[lead_pawns_count - 1][tables.map_pawns[sq]];
+            }
+            tables.lead_pawns_size[lead_pawns_count][file] = idx;
+        }
+    }
+
+    tables
+}
+
+fn _index_tables() -> &'static IndexTables {
+    static INDEX_TABLES: OnceLock<IndexTables> = OnceLock::new();
+    INDEX_TABLES.get_or_init(_make_index_tables)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+fn _read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn _read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn _read_u32_be(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn _read_u64_be(data: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// Material key, made of the number of each piece per team
+// Unlike the board hash, this doesn't depend on where the pieces are
+type MaterialKey = u64;
+
+fn _material_key(counts: &[[u8; NUM_PIECES]; 2]) -> MaterialKey {
+    let mut key = 0;
+    for (team_idx, team_counts) in counts.iter().enumerate() {
+        for (piece_idx, count) in team_counts.iter().enumerate() {
+            key |= (*count as u64) << ((team_idx * NUM_PIECES + piece_idx) * 4);
+        }
+    }
+    key
+}
+
+fn _board_piece_counts(board: &Board) -> [[u8; NUM_PIECES]; 2] {
+    let mut counts = [[0; NUM_PIECES]; 2];
+    for (team_counts, team_pieces) in counts.iter_mut().zip(board.pieces.iter()) {
+        for (count, piece_mask) in team_counts.iter_mut().zip(team_pieces.iter()) {
+            *count = piece_mask.count_ones() as u8;
+        }
+    }
+    counts
+}
+
+// Pieces in the files are 1-6 for white's pawn-king, and 9-14 for black's
+fn _board_tb_piece_at(board: &Board, sq: usize) -> u8 {
+    let mask = bm_from_idx(sq);
+    for team_idx in 0..2 {
+        for piece_idx in 0..NUM_PIECES {
+            if board.pieces[team_idx][piece_idx] & mask != 0 {
+                return (piece_idx as u8 + 1) | ((team_idx as u8) << 3);
+            }
+        }
+    }
+    panic!("No piece at square {}", sq);
+}
+
+// Symbol of a Huffman code, expanded by recursive pairing
+type Sym = usize;
+
+// Low-level info for decompressing values from a table
+// Every table has one of these for each side to move (if stored) and leading pawn file (if it has pawns)
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    min_sym_len: u8, // Also the value for single value tables
+    block_size: usize,
+    span: usize, // Number of values between each sparse index entry
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+
+    // Offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    sparse_index: usize,
+    data: usize,
+
+    base64: Vec<u64>,
+    sym_len: Vec<u16>, // Number of values (minus one) each symbol expands to
+
+    pieces: [u8; MAX_TB_PIECES], // The order that pieces are encoded in
+    group_idx: [u64; MAX_TB_PIECES + 1],
+    group_len: [usize; MAX_TB_PIECES + 1], // Zero-terminated
+    map_idx: [usize; 4] // DTZ value maps for a win, loss, cursed win and blessed loss
+}
+
+impl PairsData {
+    fn btree_left(&self, data: &[u8], sym: Sym) -> Sym {
+        let offset = self.btree + sym * 3;
+        (((data[offset + 1] & 0xF) as usize) << 8) | data[offset] as usize
+    }
+
+    fn btree_right(&self, data: &[u8], sym: Sym) -> Sym {
+        let offset = self.btree + sym * 3;
+        ((data[offset + 2] as usize) << 4) | (data[offset + 1] >> 4) as usize
+    }
+
+    fn lowest_sym(&self, data: &[u8], len: usize) -> Sym {
+        _read_u16(data, self.lowest_sym + len * 2) as Sym
+    }
+
+    fn tb_size(&self) -> u64 {
+        let num_groups = self.group_len.iter().position(|len| *len == 0).unwrap();
+        self.group_idx[num_groups]
+    }
+}
+
+// Info about a table from its name
+#[derive(Debug, Clone)]
+struct TableInfo {
+    name: String,
+    key: MaterialKey, // With the first side of the name as white
+    key2: MaterialKey, // With the first side of the name as black
+    num_pieces: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool, // Whether any non-king piece is alone
+    pawn_count: [usize; 2] // Leading pawn team first
+}
+
+impl TableInfo {
+    // Name is something like "KQvKR"
+    fn from_name(name: &str) -> Option<TableInfo> {
+        let sides: Vec<&str> = name.split('v').collect();
+        if sides.len() != 2 {
+            return None;
+        }
+
+        let mut counts = [[0u8; NUM_PIECES]; 2];
+        for (team_idx, side) in sides.iter().enumerate() {
+            if !side.starts_with('K') {
+                return None;
+            }
+
+            for ch in side.chars() {
+                let piece_idx = PIECE_CHARS.iter().position(|piece_ch| *piece_ch == ch)?;
+                counts[team_idx][piece_idx] += 1;
+            }
+
+            if counts[team_idx][PIECE_KING] != 1 {
+                return None;
+            }
+        }
+
+        let num_pieces = counts.iter().flatten().map(|count| *count as usize).sum();
+        if num_pieces > MAX_TB_PIECES {
+            return None;
+        }
+
+        let has_unique_pieces = (0..2).any(|team_idx| {
+            (0..NUM_PIECES_NO_KING).any(|piece_idx| counts[team_idx][piece_idx] == 1)
+        });
+
+        // The leading team has the fewest pawns (but at least one), which compresses better
+        let pawns = [counts[0][PIECE_PAWN] as usize, counts[1][PIECE_PAWN] as usize];
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        Some(TableInfo {
+            name: name.to_string(),
+            key: _material_key(&counts),
+            key2: _material_key(&[counts[1], counts[0]]),
+            num_pieces,
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces,
+            pawn_count: if white_leads { pawns } else { [pawns[1], pawns[0]] }
+        })
+    }
+}
+
+// Contents of a loaded table file
+struct TableData {
+    bytes: Vec<u8>,
+    pairs: [[PairsData; 4]; 2], // [side to move][leading pawn file]
+    dtz_map: usize // Offset of the DTZ value maps
+}
+
+// A WDL or DTZ table file, which is only loaded when first probed
+struct Table {
+    path: PathBuf,
+    is_dtz: bool,
+    data: OnceLock<Option<TableData>>
+}
+
+impl Table {
+    fn new(path: PathBuf, is_dtz: bool) -> Table {
+        Table {
+            path,
+            is_dtz,
+            data: OnceLock::new()
+        }
+    }
+
+    fn get_data(&self, info: &TableInfo) -> Option<&TableData> {
+        self.data.get_or_init(|| {
+            let bytes = std::fs::read(&self.path).ok()?;
+            _parse_table(bytes, info, self.is_dtz)
+        }).as_ref()
+    }
+}
+
+fn _set_groups(info: &TableInfo, d: &mut PairsData, order: [usize; 2], file: usize) {
+    let tables = _index_tables();
+
+    // Pieces of the same type and team are encoded together
+    // The first group is the leading pawns, or the first 2-3 pieces if there are no pawns
+    let mut n = 0;
+    let mut first_len: i64 = if info.has_pawns { 0 } else if info.has_unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+    for i in 1..info.num_pieces {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    // Groups are combined into one index, in an order that is stored per table
+    let both_pawns = info.has_pawns && info.pawn_count[1] > 0;
+    let mut next = if both_pawns { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if both_pawns { d.group_len[1] } else { 0 };
+    let mut idx: u64 = 1;
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            // Leading pawns or pieces
+            d.group_idx[0] = idx;
+            idx *= if info.has_pawns {
+                tables.lead_pawns_size[d.group_len[0]][file]
+            } else if info.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            // Remaining pawns
+            d.group_idx[1] = idx;
+            idx *= tables.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            // Remaining pieces
+            d.group_idx[next] = idx;
+            idx *= tables.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+    d.group_idx[n] = idx;
+}
+
+// Finds how many values each symbol expands to
+fn _set_sym_len(d: &mut PairsData, data: &[u8], sym: Sym, visited: &mut [bool]) -> u16 {
+    visited[sym] = true;
+    let right = d.btree_right(data, sym);
+    if right == 0xFFF {
+        return 0; // Leaf
+    }
+
+    let left = d.btree_left(data, sym);
+    if !visited[left] {
+        d.sym_len[left] = _set_sym_len(d, data, left, visited);
+    }
+    if !visited[right] {
+        d.sym_len[right] = _set_sym_len(d, data, right, visited);
+    }
+
+    d.sym_len[left] + d.sym_len[right] + 1
+}
+
+// Reads the Huffman code info, and returns the offset after it
+fn _set_sizes(d: &mut PairsData, data: &[u8], mut offset: usize) -> Option<usize> {
+    d.flags = *data.get(offset)?;
+    offset += 1;
+
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = *data.get(offset)?;
+        return Some(offset + 1);
+    }
+
+    let tb_size = d.tb_size();
+    d.block_size = 1 << data.get(offset)?;
+    d.span = 1 << data.get(offset + 1)?;
+    d.sparse_index_size = tb_size.div_ceil(d.span as u64) as usize;
+    let padding = *data.get(offset + 2)? as usize;
+    d.num_blocks = _read_u32(data, offset + 3) as usize;
+    d.block_length_size = d.num_blocks + padding; // Padded so the sparse index never points out of range
+    let max_sym_len = *data.get(offset + 7)?;
+    d.min_sym_len = *data.get(offset + 8)?;
+    offset += 9;
+    if max_sym_len < d.min_sym_len || d.min_sym_len == 0 {
+        return None;
+    }
+
+    // Canonical Huffman code, where longer symbols have lower values
+    // base64[i] is the lowest symbol of length (min_sym_len + i), left-aligned in 64 bits
+    d.lowest_sym = offset;
+    let num_lens = (max_sym_len - d.min_sym_len + 1) as usize;
+    d.base64 = vec![0; num_lens];
+    for i in (0..num_lens - 1).rev() {
+        d.base64[i] = d.base64[i + 1]
+            .wrapping_add(d.lowest_sym(data, i) as u64)
+            .wrapping_sub(d.lowest_sym(data, i + 1) as u64) / 2;
+    }
+    for i in 0..num_lens {
+        d.base64[i] <<= 64 - i - d.min_sym_len as usize;
+    }
+    offset += num_lens * 2;
+
+    let num_syms = _read_u16(data, offset) as usize;
+    offset += 2;
+    d.btree = offset;
+    if d.btree + num_syms * 3 > data.len() {
+        return None;
+    }
+
+    d.sym_len = vec![0; num_syms];
+    let mut visited = vec![false; num_syms];
+    for sym in 0..num_syms {
+        if !visited[sym] {
+            d.sym_len[sym] = _set_sym_len(d, data, sym, &mut visited);
+        }
+    }
+
+    Some(offset + num_syms * 3 + (num_syms & 1))
+}
+
+fn _parse_table(bytes: Vec<u8>, info: &TableInfo, is_dtz: bool) -> Option<TableData> {
+    const FILE_FLAG_SPLIT: u8 = 1;
+    const FILE_FLAG_HAS_PAWNS: u8 = 2;
+
+    let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+    if bytes.len() < 5 || bytes[0..4] != magic {
+        return None;
+    }
+
+    let file_flags = bytes[4];
+    let is_split = info.key != info.key2;
+    if (file_flags & FILE_FLAG_HAS_PAWNS != 0) != info.has_pawns || (!is_dtz && (file_flags & FILE_FLAG_SPLIT != 0) != is_split) {
+        return None;
+    }
+
+    let mut table_data = TableData {
+        bytes: Vec::new(),
+        pairs: Default::default(),
+        dtz_map: 0
+    };
+
+    let data = &bytes;
+    let num_sides = if !is_dtz && is_split { 2 } else { 1 };
+    let num_files = if info.has_pawns { 4 } else { 1 };
+    let both_pawns = info.has_pawns && info.pawn_count[1] > 0;
+
+    // Piece order and group order
+    let mut offset = 5;
+    for file in 0..num_files {
+        let order_byte_2 = if both_pawns { *data.get(offset + 1)? } else { 0xFF };
+        let orders = [
+            [(data.get(offset)? & 0xF) as usize, (order_byte_2 & 0xF) as usize],
+            [(data.get(offset)? >> 4) as usize, (order_byte_2 >> 4) as usize]
+        ];
+        offset += 1 + both_pawns as usize;
+
+        for k in 0..info.num_pieces {
+            let pieces_byte = *data.get(offset)?;
+            for (side, pairs) in table_data.pairs.iter_mut().enumerate().take(num_sides) {
+                pairs[file].pieces[k] = if side == 0 { pieces_byte & 0xF } else { pieces_byte >> 4 };
+            }
+            offset += 1;
+        }
+
+        for (pairs, order) in table_data.pairs.iter_mut().zip(orders).take(num_sides) {
+            _set_groups(info, &mut pairs[file], order, file);
+        }
+    }
+    offset += offset & 1;
+
+    for file in 0..num_files {
+        for side in 0..num_sides {
+            offset = _set_sizes(&mut table_data.pairs[side][file], data, offset)?;
+        }
+    }
+
+    if is_dtz {
+        table_data.dtz_map = offset;
+        for file in 0..num_files {
+            let d = &mut table_data.pairs[0][file];
+            if d.flags & FLAG_MAPPED == 0 {
+                continue;
+            }
+
+            if d.flags & FLAG_WIDE != 0 {
+                offset += offset & 1;
+                for i in 0..4 {
+                    d.map_idx[i] = (offset - table_data.dtz_map) / 2 + 1;
+                    offset += 2 * _read_u16(data, offset) as usize + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    d.map_idx[i] = offset - table_data.dtz_map + 1;
+                    offset += *data.get(offset)? as usize + 1;
+                }
+            }
+        }
+        offset += offset & 1;
+    }
+
+    for file in 0..num_files {
+        for side in 0..num_sides {
+            let d = &mut table_data.pairs[side][file];
+            d.sparse_index = offset;
+            offset += d.sparse_index_size * 6;
+        }
+    }
+
+    for file in 0..num_files {
+        for side in 0..num_sides {
+            let d = &mut table_data.pairs[side][file];
+            d.block_length = offset;
+            offset += d.block_length_size * 2;
+        }
+    }
+
+    for file in 0..num_files {
+        for side in 0..num_sides {
+            let d = &mut table_data.pairs[side][file];
+            offset = (offset + 0x3F) & !0x3F; // 64 byte alignment
+            d.data = offset;
+            offset += d.num_blocks * d.block_size;
+        }
+    }
+
+    if offset > bytes.len() {
+        return None; // Truncated file
+    }
+
+    table_data.bytes = bytes;
+    Some(table_data)
+}
+
+// Finds the value stored at an index of a table
+fn _decompress_pairs(d: &PairsData, data: &[u8], idx: u64) -> usize {
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        return d.min_sym_len as usize;
+    }
+
+    // The sparse index stores the block and offset of every span-th value (from the middle of the span)
+    let k = (idx / d.span as u64) as usize;
+    let mut block = _read_u32(data, d.sparse_index + k * 6) as usize;
+    let mut offset = _read_u16(data, d.sparse_index + k * 6 + 4) as i64;
+    offset += (idx % d.span as u64) as i64 - (d.span / 2) as i64;
+
+    // Walk to the block that actually has our value
+    let block_length = |block: usize| _read_u16(data, d.block_length + block * 2) as i64;
+    while offset < 0 {
+        block -= 1;
+        offset += block_length(block) + 1;
+    }
+    while offset > block_length(block) {
+        offset -= block_length(block) + 1;
+        block += 1;
+    }
+
+    // Read symbols until we reach the one that contains our value
+    let mut ptr = d.data + block * d.block_size;
+    let mut buf64 = _read_u64_be(data, ptr);
+    ptr += 8;
+    let mut buf64_size = 64;
+    let min_sym_len = d.min_sym_len as usize;
+    let mut sym: Sym;
+    loop {
+        let mut len = 0;
+        while buf64 < d.base64[len] {
+            len += 1;
+        }
+
+        sym = ((buf64 - d.base64[len]) >> (64 - len - min_sym_len)) as Sym;
+        sym += d.lowest_sym(data, len);
+
+        if offset < d.sym_len[sym] as i64 + 1 {
+            break;
+        }
+
+        offset -= d.sym_len[sym] as i64 + 1;
+        len += min_sym_len;
+        buf64 <<= len;
+        buf64_size -= len;
+
+        if buf64_size <= 32 {
+            // Refill
+            buf64_size += 32;
+            buf64 |= (_read_u32_be(data, ptr) as u64) << (64 - buf64_size);
+            ptr += 4;
+        }
+    }
+
+    // Expand the symbol until we reach the single value
+    while d.sym_len[sym] != 0 {
+        let left = d.btree_left(data, sym);
+        if offset < d.sym_len[left] as i64 + 1 {
+            sym = left;
+        } else {
+            offset -= d.sym_len[left] as i64 + 1;
+            sym = d.btree_right(data, sym);
+        }
+    }
+
+    d.btree_left(data, sym)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ProbeState {
+    Ok,
+    Fail,
+    ChangeStm, // DTZ table only stores the other side to move
+    ZeroingBestMove // Best move is a capture or pawn move
+}
+
+// DTZ of the move before a zeroing move, which the tables don't store
+fn _dtz_before_zeroing(wdl: WdlScore) -> i32 {
+    match wdl {
+        WDL_WIN => 1,
+        WDL_CURSED_WIN => 101,
+        WDL_BLESSED_LOSS => -101,
+        WDL_LOSS => -1,
+        _ => 0
+    }
+}
+
+fn _is_zeroing_move(mv: &Move) -> bool {
+    mv.has_flag(Move::FLAG_CAPTURE) || mv.from_piece_idx == PIECE_PAWN
+}
+
+fn _is_checkmate(board: &Board) -> bool {
+    if board.checkers == 0 {
+        return false;
+    }
+
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+    moves.is_empty()
+}
+
+// A set of Syzygy tables loaded from one or more directories
+pub struct Tablebase {
+    infos: Vec<TableInfo>,
+    wdl_tables: Vec<Table>,
+    dtz_tables: Vec<Option<Table>>,
+    key_to_idx: HashMap<MaterialKey, usize>,
+    max_pieces: usize
+}
+
+impl std::fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tablebase({} WDL, {} DTZ, max {} pieces)", self.num_wdl_tables(), self.num_dtz_tables(), self.max_pieces)
+    }
+}
+
+impl Tablebase {
+    // Paths are separated like the PATH environment variable (";" on Windows, ":" elsewhere)
+    // Missing directories and unrecognized files are ignored
+    pub fn new(paths_str: &str) -> Tablebase {
+        let mut result = Tablebase {
+            infos: Vec::new(),
+            wdl_tables: Vec::new(),
+            dtz_tables: Vec::new(),
+            key_to_idx: HashMap::new(),
+            max_pieces: 0
+        };
+
+        let separator = if cfg!(windows) { ';' } else { ':' };
+        let mut wdl_paths: Vec<(String, PathBuf)> = Vec::new();
+        let mut dtz_paths: HashMap<String, PathBuf> = HashMap::new();
+        for dir in paths_str.split(separator).filter(|dir| !dir.trim().is_empty()) {
+            let Ok(dir_entries) = std::fs::read_dir(Path::new(dir.trim())) else {
+                continue;
+            };
+
+            for dir_entry in dir_entries.flatten() {
+                let path = dir_entry.path();
+                let (Some(name), Some(ext)) = (path.file_stem(), path.extension()) else {
+                    continue;
+                };
+
+                let name = name.to_string_lossy().to_string();
+                match ext.to_string_lossy().as_ref() {
+                    "rtbw" => wdl_paths.push((name, path)),
+                    "rtbz" => { dtz_paths.insert(name, path); },
+                    _ => {}
+                }
+            }
+        }
+
+        wdl_paths.sort();
+        for (name, path) in wdl_paths {
+            let Some(info) = TableInfo::from_name(&name) else {
+                continue;
+            };
+
+            if result.key_to_idx.contains_key(&info.key) {
+                continue; // Same table in another directory
+            }
+
+            let idx = result.infos.len();
+            result.key_to_idx.insert(info.key, idx);
+            result.key_to_idx.insert(info.key2, idx);
+            result.max_pieces = result.max_pieces.max(info.num_pieces);
+            result.wdl_tables.push(Table::new(path, false));
+            result.dtz_tables.push(dtz_paths.remove(&name).map(|path| Table::new(path, true)));
+            result.infos.push(info);
+        }
+
+        result
+    }
+
+    pub fn num_wdl_tables(&self) -> usize {
+        self.wdl_tables.len()
+    }
+
+    pub fn num_dtz_tables(&self) -> usize {
+        self.dtz_tables.iter().filter(|table| table.is_some()).count()
+    }
+
+    // Number of pieces (including kings) in the biggest table, or 0 if there are none
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Names of the tables, like "KQvKR"
+    pub fn table_names(&self) -> Vec<String> {
+        self.infos.iter().map(|info| info.name.clone()).collect()
+    }
+
+    // Tables have no castling positions, and we only have the ones we found
+    pub fn can_probe(&self, board: &Board) -> bool {
+        let has_castle_rights = board.castle_rights.iter().flatten().any(|right| right.is_some());
+        !has_castle_rights && (board.combined_occupancy().count_ones() as usize) <= self.max_pieces
+    }
+
+    fn _probe_table(&self, board: &Board, is_dtz: bool, wdl: WdlScore, state: &mut ProbeState) -> i32 {
+        let counts = _board_piece_counts(board);
+        if board.combined_occupancy().count_ones() == 2 {
+            return WDL_DRAW; // King vs king
+        }
+
+        let key = _material_key(&counts);
+        let Some(&table_idx) = self.key_to_idx.get(&key) else {
+            *state = ProbeState::Fail;
+            return 0;
+        };
+
+        let info = &self.infos[table_idx];
+        let table = if is_dtz { self.dtz_tables[table_idx].as_ref() } else { Some(&self.wdl_tables[table_idx]) };
+        let Some(table_data) = table.and_then(|table| table.get_data(info)) else {
+            *state = ProbeState::Fail;
+            return 0;
+        };
+
+        self._do_probe_table(board, info, table_data, is_dtz, key, wdl, state)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn _do_probe_table(&self, board: &Board, info: &TableInfo, table_data: &TableData, is_dtz: bool,
+                       key: MaterialKey, wdl: WdlScore, state: &mut ProbeState) -> i32 {
+        let tables = _index_tables();
+        let data = &table_data.bytes;
+
+        // Tables are stored with the first side of the name as white
+        // If that's actually black, or if both sides are the same and black is to move, flip the board
+        let symmetric_black_to_move = info.key == info.key2 && board.turn_idx == 1;
+        let black_stronger = key != info.key;
+        let flip = symmetric_black_to_move || black_stronger;
+        let flip_team: u8 = if flip { 8 } else { 0 };
+        let flip_squares: usize = if flip { 56 } else { 0 };
+        let stm = (flip as usize) ^ board.turn_idx;
+
+        let mut squares = [0usize; MAX_TB_PIECES];
+        let mut pieces = [0u8; MAX_TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_count = 0;
+        let mut lead_pawns: BitMask = 0;
+        let mut tb_file = 0;
+
+        // Pawn tables are split by the file of the leading pawn
+        if info.has_pawns {
+            let lead_piece = table_data.pairs[0][0].pieces[0] ^ flip_team;
+            let lead_team_idx = (lead_piece >> 3) as usize;
+            lead_pawns = board.pieces[lead_team_idx][PIECE_PAWN];
+            for sq in bm_iter_bits(lead_pawns) {
+                squares[size] = bm_to_idx(sq) ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+
+            let mut lead_idx = 0;
+            for i in 1..lead_pawns_count {
+                if tables.map_pawns[squares[i]] > tables.map_pawns[squares[lead_idx]] {
+                    lead_idx = i;
+                }
+            }
+            squares.swap(0, lead_idx);
+
+            let file = squares[0] % 8;
+            tb_file = file.min(7 - file);
+        }
+
+        let num_sides = if !is_dtz && info.key != info.key2 { 2 } else { 1 };
+        let d = &table_data.pairs[stm % num_sides][tb_file];
+
+        // DTZ tables only store one side to move, unless the sides are the same
+        let has_both_stms = info.key == info.key2 && !info.has_pawns;
+        if is_dtz && (d.flags & FLAG_STM) as usize != stm && !has_both_stms {
+            *state = ProbeState::ChangeStm;
+            return 0;
+        }
+
+        for sq in bm_iter_bits(board.combined_occupancy() ^ lead_pawns) {
+            let sq_idx = bm_to_idx(sq);
+            squares[size] = sq_idx ^ flip_squares;
+            pieces[size] = _board_tb_piece_at(board, sq_idx) ^ flip_team;
+            size += 1;
+        }
+
+        // Put the pieces in the same order as the table
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            for j in (i + 1)..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so that the leading piece is on files a-d
+        if squares[0] % 8 > 3 {
+            for sq in squares.iter_mut().take(size) {
+                *sq = _flip_file(*sq);
+            }
+        }
+
+        let mut idx: u64;
+        if info.has_pawns {
+            idx = tables.lead_pawn_idx[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|sq| tables.map_pawns[*sq]);
+            for (i, sq) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += tables.binomial[i][tables.map_pawns[*sq]];
+            }
+        } else {
+            // Mirror so that the leading piece is on ranks 1-4
+            if squares[0] / 8 > 3 {
+                for sq in squares.iter_mut().take(size) {
+                    *sq = _flip_rank(*sq);
+                }
+            }
+
+            // Mirror along the a1-h8 diagonal so that the first leading piece off of it is below it
+            for i in 0..d.group_len[0] {
+                let off = _off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+
+                if off > 0 {
+                    for sq in squares.iter_mut().take(size).skip(i) {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if info.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let ranks = [(squares[0] / 8) as u64, (squares[1] / 8) as u64, (squares[2] / 8) as u64];
+
+                idx = if _off_a1h8(squares[0]) != 0 {
+                    (tables.map_a1d1d4[squares[0]] as u64 * 63 + (squares[1] as u64 - adjust1)) * 62
+                        + squares[2] as u64 - adjust2
+                } else if _off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + ranks[0] * 28 + tables.map_b1h1h7[squares[1]] as u64) * 62
+                        + squares[2] as u64 - adjust2
+                } else if _off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62
+                        + ranks[0] * 7 * 28
+                        + (ranks[1] - adjust1) * 28
+                        + tables.map_b1h1h7[squares[2]] as u64
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28
+                        + ranks[0] * 7 * 6
+                        + (ranks[1] - adjust1) * 6
+                        + (ranks[2] - adjust2)
+                };
+            } else {
+                idx = tables.map_kk[tables.map_a1d1d4[squares[0]]][squares[1]] as u64;
+            }
+        }
+
+        // Encode the remaining groups, each with its squares in ascending order
+        idx *= d.group_idx[0];
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = info.has_pawns && info.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_len = d.group_len[next];
+            squares[group_start..group_start + group_len].sort();
+
+            let mut n: u64 = 0;
+            for i in 0..group_len {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|other_sq| sq > **other_sq).count();
+                let pawn_adjust = if remaining_pawns { 8 } else { 0 };
+                n += tables.binomial[i + 1][sq - adjust - pawn_adjust];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += group_len;
+            next += 1;
+        }
+
+        let value = _decompress_pairs(d, data, idx) as i32;
+        if !is_dtz {
+            return value - 2;
+        }
+
+        // DTZ values are mapped to be sorted by frequency for each WDL result
+        let d = &table_data.pairs[0][tb_file];
+        let mut value = value;
+        if d.flags & FLAG_MAPPED != 0 {
+            const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+            let map_idx = d.map_idx[WDL_MAP[(wdl + 2) as usize]] + value as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                _read_u16(data, table_data.dtz_map + map_idx * 2) as i32
+            } else {
+                data[table_data.dtz_map + map_idx] as i32
+            };
+        }
+
+        // Values can be stored in moves instead of plies
+        if (wdl == WDL_WIN && d.flags & FLAG_WIN_PLIES == 0) ||
+            (wdl == WDL_LOSS && d.flags & FLAG_LOSS_PLIES == 0) ||
+            wdl == WDL_CURSED_WIN || wdl == WDL_BLESSED_LOSS {
+            value *= 2;
+        }
+
+        value + 1
+    }
+
+    // Tables don't store the result if it's reached with a capture, so we have to search the captures ourselves
+    // If check_zeroing_moves is set, pawn moves are searched as well, as DTZ tables don't store those either
+    fn _search(&self, board: &Board, check_zeroing_moves: bool, state: &mut ProbeState) -> WdlScore {
+        let mut best_value = WDL_LOSS;
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+
+        let mut move_count = 0;
+        for mv in moves.iter() {
+            let is_capture = mv.has_flag(Move::FLAG_CAPTURE);
+            if !is_capture && (!check_zeroing_moves || mv.from_piece_idx != PIECE_PAWN) {
+                continue;
+            }
+
+            move_count += 1;
+
+            let mut next_board = *board;
+            next_board.do_move(mv);
+            let value = -self._search(&next_board, false, state);
+
+            if *state == ProbeState::Fail {
+                return WDL_DRAW;
+            }
+
+            if value > best_value {
+                best_value = value;
+                if value >= WDL_WIN {
+                    *state = ProbeState::ZeroingBestMove;
+                    return value;
+                }
+            }
+        }
+
+        // If every move was searched, the stored value can't be trusted (it might be an en passant position)
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best_value
+        } else {
+            let value = self._probe_table(board, false, WDL_DRAW, state);
+            if *state == ProbeState::Fail {
+                return WDL_DRAW;
+            }
+            value
+        };
+
+        if best_value >= value {
+            *state = if best_value > WDL_DRAW || no_more_moves { ProbeState::ZeroingBestMove } else { ProbeState::Ok };
+            return best_value;
+        }
+
+        *state = ProbeState::Ok;
+        value
+    }
+
+    // Win/draw/loss for the side to move
+    // Castle rights are ignored, check can_probe() first
+    pub fn probe_wdl(&self, board: &Board) -> Option<WdlScore> {
+        let mut state = ProbeState::Ok;
+        let wdl = self._search(board, false, &mut state);
+        if state == ProbeState::Fail { None } else { Some(wdl) }
+    }
+
+    fn _probe_dtz(&self, board: &Board, state: &mut ProbeState) -> i32 {
+        *state = ProbeState::Ok;
+        let wdl = self._search(board, true, state);
+        if *state == ProbeState::Fail || wdl == WDL_DRAW {
+            return 0; // Draws aren't stored
+        }
+
+        if *state == ProbeState::ZeroingBestMove {
+            return _dtz_before_zeroing(wdl);
+        }
+
+        let dtz = self._probe_table(board, true, wdl, state);
+        if *state == ProbeState::Fail {
+            return 0;
+        }
+
+        if *state != ProbeState::ChangeStm {
+            let cursed_adjust = if wdl == WDL_BLESSED_LOSS || wdl == WDL_CURSED_WIN { 100 } else { 0 };
+            return (dtz + cursed_adjust) * wdl.signum();
+        }
+
+        // The table only stores the other side to move, so find the best DTZ of our moves
+        let mut min_dtz = i32::MAX;
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+        for mv in moves.iter() {
+            let is_zeroing = _is_zeroing_move(mv);
+
+            let mut next_board = *board;
+            next_board.do_move(mv);
+
+            // For zeroing moves, we want the DTZ from before the move
+            let mut dtz = if is_zeroing {
+                -_dtz_before_zeroing(self._search(&next_board, false, state))
+            } else {
+                -self._probe_dtz(&next_board, state)
+            };
+
+            if dtz == 1 && _is_checkmate(&next_board) {
+                min_dtz = 1;
+            }
+
+            if !is_zeroing {
+                dtz += dtz.signum();
+            }
+
+            if dtz < min_dtz && dtz.signum() == wdl.signum() {
+                min_dtz = dtz;
+            }
+
+            if *state == ProbeState::Fail {
+                return 0;
+            }
+        }
+
+        // No legal moves means we're checkmated
+        if min_dtz == i32::MAX { -1 } else { min_dtz }
+    }
+
+    // Distance to zeroing (a capture or pawn move) in plies, assuming optimal play
+    // Positive is a win, negative is a loss, and 0 is a draw
+    // Values past 100 are wins/losses that are drawn by the 50 move rule
+    pub fn probe_dtz(&self, board: &Board) -> Option<i32> {
+        let mut state = ProbeState::Ok;
+        let dtz = self._probe_dtz(board, &mut state);
+        if state == ProbeState::Fail { None } else { Some(dtz) }
+    }
+
+    // Ranks each root move by its DTZ, keeping the 50 move rule in mind
+    // Higher ranks are better, with certain wins ranked equally
+    // prev_hashes are the hashes of the game's positions before this board, for repetition detection
+    pub fn rank_root_moves(&self, board: &Board, prev_hashes: &[Hash]) -> Option<Vec<(Move, i32)>> {
+        let half_move_counter = board.half_move_counter as i32;
+
+        // Whether a position has repeated since the last zeroing move
+        let lookback = (board.half_move_counter as usize).min(prev_hashes.len());
+        let recent_hashes = &prev_hashes[prev_hashes.len() - lookback..];
+        let has_repeated = recent_hashes.contains(&board.hash) ||
+            recent_hashes.iter().enumerate().any(|(i, hash)| recent_hashes[i + 1..].contains(hash));
+
+        let mut result = Vec::new();
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+        for mv in moves.iter() {
+            let mut next_board = *board;
+            next_board.do_move(mv);
+
+            let mut state = ProbeState::Ok;
+            let mut dtz = if next_board.half_move_counter == 0 {
+                let wdl = -self._search(&next_board, false, &mut state);
+                _dtz_before_zeroing(wdl)
+            } else {
+                let dtz = -self._probe_dtz(&next_board, &mut state);
+                dtz + dtz.signum()
+            };
+
+            if dtz == 2 && _is_checkmate(&next_board) {
+                dtz = 1;
+            }
+
+            if state == ProbeState::Fail {
+                return None;
+            }
+
+            let rank = if dtz > 0 {
+                if dtz + half_move_counter <= 99 && !has_repeated { 1000 } else { 1000 - (dtz + half_move_counter) }
+            } else if dtz < 0 {
+                if -dtz * 2 + half_move_counter < 100 { -1000 } else { -1000 + (-dtz + half_move_counter) }
+            } else {
+                0
+            };
+            result.push((*mv, rank));
+        }
+
+        Some(result)
+    }
+
+    // Fallback for rank_root_moves() if DTZ tables are missing, which can keep a win but may not make progress
+    pub fn rank_root_moves_wdl(&self, board: &Board) -> Option<Vec<(Move, i32)>> {
+        const WDL_TO_RANK: [i32; 5] = [-1000, -899, 0, 899, 1000];
+
+        let mut result = Vec::new();
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+        for mv in moves.iter() {
+            let mut next_board = *board;
+            next_board.do_move(mv);
+
+            let wdl = -self.probe_wdl(&next_board)?;
+            result.push((*mv, WDL_TO_RANK[(wdl + 2) as usize]));
+        }
+
+        Some(result)
+    }
+
+    // The root moves that keep the best tablebase result, or None if the position can't be probed
+    pub fn filter_root_moves(&self, board: &Board, prev_hashes: &[Hash]) -> Option<Vec<Move>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let ranked_moves = self.rank_root_moves(board, prev_hashes)
+            .or_else(|| self.rank_root_moves_wdl(board))?;
+
+        let best_rank = ranked_moves.iter().map(|(_, rank)| *rank).max()?;
+        Some(ranked_moves.into_iter().filter(|(_, rank)| *rank == best_rank).map(|(mv, _)| mv).collect())
+    }
+}
@@ -0,0 +1,793 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::bitmask::*;
+use crate::board::*;
+use crate::lookup_gen;
+use crate::move_gen;
+
+// Distance-to-mate tablebases for small endings, generated by retrograde analysis
+// The 50 move rule is ignored
+// Ref: https://www.chessprogramming.org/Retrograde_Analysis
+
+type Result<T> = std::result::Result<T, TablebaseError>;
+
+#[derive(Debug, Clone)]
+pub struct TablebaseError(String);
+
+impl std::fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "TablebaseError: {}", self.0)
+    }
+}
+
+// Tables are indexed by every square for every piece other than the white king, so this can't go much higher
+pub const MAX_TABLE_PIECES: usize = 4;
+
+pub const FILE_EXTENSION: &str = "dtm";
+const FILE_MAGIC: [u8; 4] = *b"BCTB";
+const FILE_VERSION: u8 = 2;
+
+// Endings generated when none are specified (along with the tables they depend on)
+pub const DEFAULT_TABLE_NAMES: [&str; 10] = [
+    "KQvK", "KRvK", "KPvK", "KBNvK", "KBBvK", "KQvKR", "KQvKB", "KQvKN", "KRvKB", "KRvKN"
+];
+
+// Order that pieces are listed in table names
+const NAME_PIECE_ORDER: [usize; NUM_PIECES] = [PIECE_KING, PIECE_QUEEN, PIECE_ROOK, PIECE_BISHOP, PIECE_KNIGHT, PIECE_PAWN];
+
+// Result for the side to move, with the number of plies until checkmate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DtmResult {
+    Win(u32),
+    Loss(u32),
+    Draw
+}
+
+impl DtmResult {
+    // Higher is better for the side to move
+    fn rank(&self) -> i64 {
+        match self {
+            DtmResult::Win(plies) => 1000 - *plies as i64,
+            DtmResult::Loss(plies) => -1000 + *plies as i64,
+            DtmResult::Draw => 0
+        }
+    }
+
+    // The result for the side that moved into this position
+    fn for_prev_ply(&self) -> DtmResult {
+        match self {
+            DtmResult::Win(plies) => DtmResult::Loss(plies + 1),
+            DtmResult::Loss(plies) => DtmResult::Win(plies + 1),
+            DtmResult::Draw => DtmResult::Draw
+        }
+    }
+}
+
+// Packs an optional result into a u16: 0 is none, 1 is a draw, and otherwise it's the number of plies until checkmate plus 2
+// Like table values, an odd number of plies is a win for the side to move and an even number is a loss
+fn _pack_result(result: Option<DtmResult>) -> u16 {
+    match result {
+        None => 0,
+        Some(DtmResult::Draw) => 1,
+        Some(DtmResult::Win(plies)) | Some(DtmResult::Loss(plies)) => plies as u16 + 2
+    }
+}
+
+fn _unpack_result(packed: u16) -> Option<DtmResult> {
+    match packed {
+        0 => None,
+        1 => Some(DtmResult::Draw),
+        _ => {
+            let plies = (packed - 2) as u32;
+            Some(if !plies.is_multiple_of(2) { DtmResult::Win(plies) } else { DtmResult::Loss(plies) })
+        }
+    }
+}
+
+// The 8 symmetries of the board: bit 0 mirrors the files, bit 1 mirrors the ranks, and bit 2 mirrors along the a1-h8 diagonal
+// Only the first 2 apply with pawns, which can't change ranks
+fn _transform_square(sq: usize, transform: usize) -> usize {
+    let mut sq = sq;
+    if transform & 4 != 0 {
+        sq = ((sq >> 3) | (sq << 3)) & 63;
+    }
+    if transform & 2 != 0 {
+        sq ^= 56;
+    }
+    if transform & 1 != 0 {
+        sq ^= 7;
+    }
+    sq
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+type MaterialCounts = [[u8; NUM_PIECES]; 2];
+
+fn _material_key(counts: &MaterialCounts) -> u64 {
+    let mut key = 0;
+    for (team_idx, team_counts) in counts.iter().enumerate() {
+        for (piece_idx, count) in team_counts.iter().enumerate() {
+            key |= (*count as u64) << ((team_idx * NUM_PIECES + piece_idx) * 4);
+        }
+    }
+    key
+}
+
+fn _board_material(board: &Board) -> MaterialCounts {
+    let mut counts = [[0; NUM_PIECES]; 2];
+    for (team_counts, team_pieces) in counts.iter_mut().zip(board.pieces.iter()) {
+        for (count, piece_mask) in team_counts.iter_mut().zip(team_pieces.iter()) {
+            *count = piece_mask.count_ones() as u8;
+        }
+    }
+    counts
+}
+
+fn _material_name(counts: &MaterialCounts) -> String {
+    let mut name = String::new();
+    for (team_idx, team_counts) in counts.iter().enumerate() {
+        if team_idx == 1 {
+            name.push('v');
+        }
+
+        for piece_idx in NAME_PIECE_ORDER {
+            for _ in 0..team_counts[piece_idx] {
+                name.push(PIECE_CHARS[piece_idx]);
+            }
+        }
+    }
+    name
+}
+
+// Names are like "KQvK", where the first side is white
+fn _parse_material_name(name: &str) -> Result<MaterialCounts> {
+    let throw_err = |msg: &str| -> Result<MaterialCounts> {
+        Err(TablebaseError(format!("Invalid table name \"{}\", {}", name, msg)))
+    };
+
+    let sides: Vec<&str> = name.split('v').collect();
+    if sides.len() != 2 {
+        return throw_err("expected two sides separated by 'v'");
+    }
+
+    let mut counts = [[0u8; NUM_PIECES]; 2];
+    for (team_idx, side) in sides.iter().enumerate() {
+        for ch in side.chars() {
+            match PIECE_CHARS.iter().position(|piece_ch| *piece_ch == ch) {
+                Some(piece_idx) => counts[team_idx][piece_idx] += 1,
+                None => return throw_err(format!("unknown piece '{}'", ch).as_str())
+            }
+        }
+
+        if counts[team_idx][PIECE_KING] != 1 {
+            return throw_err("each side needs exactly one king");
+        }
+    }
+
+    let num_pieces: usize = counts.iter().flatten().map(|count| *count as usize).sum();
+    if num_pieces > MAX_TABLE_PIECES {
+        return throw_err(format!("tables can have at most {} pieces", MAX_TABLE_PIECES).as_str());
+    }
+
+    Ok(counts)
+}
+
+// Whether neither side could ever checkmate, so there's no need for a table
+fn _is_trivial_draw(counts: &MaterialCounts) -> bool {
+    let minor_pieces: u8 = counts.iter().map(|team_counts| team_counts[PIECE_BISHOP] + team_counts[PIECE_KNIGHT]).sum();
+    let other_pieces: u8 = counts.iter().map(|team_counts| {
+        team_counts[PIECE_PAWN] + team_counts[PIECE_ROOK] + team_counts[PIECE_QUEEN]
+    }).sum();
+
+    other_pieces == 0 && minor_pieces <= 1
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+pub struct DtmTable {
+    pub name: String,
+
+    // Team and piece type of each piece, in the order they are indexed, starting with the white king
+    // Identical pieces are next to each other, and are indexed with their squares in ascending order
+    pieces: Vec<(usize, usize)>,
+    num_transforms: usize, // Symmetries of the board that keep the same position (see _transform_square())
+
+    // Positions are mirrored so that the white king is on one of these squares, which is a1-d1-d4 without pawns and files a-d with pawns
+    king_squares: Vec<usize>,
+    king_square_idxs: [usize; 64], // Index into king_squares for each square, or usize::MAX
+
+    // Indexed by [square of each piece other than the white king][white king square][turn] (see _encode())
+    // 0 is a draw (or an illegal position), otherwise it's the number of plies until checkmate plus one
+    // An odd number of plies is a win for the side to move, an even number is a loss
+    values: Vec<u8>
+}
+
+impl DtmTable {
+    fn new(counts: &MaterialCounts) -> DtmTable {
+        let mut pieces = Vec::new();
+        for (team_idx, team_counts) in counts.iter().enumerate() {
+            for piece_idx in NAME_PIECE_ORDER {
+                for _ in 0..team_counts[piece_idx] {
+                    pieces.push((team_idx, piece_idx));
+                }
+            }
+        }
+
+        let has_pawns = counts.iter().any(|team_counts| team_counts[PIECE_PAWN] > 0);
+        let king_squares: Vec<usize> = (0..64).filter(|sq| {
+            let (x, y) = (sq % 8, sq / 8);
+            if has_pawns { x <= 3 } else { x <= 3 && y <= x }
+        }).collect();
+
+        let mut king_square_idxs = [usize::MAX; 64];
+        for (i, sq) in king_squares.iter().enumerate() {
+            king_square_idxs[*sq] = i;
+        }
+
+        let num_positions = (2 * king_squares.len()) << (6 * (pieces.len() - 1));
+        DtmTable {
+            name: _material_name(counts),
+            pieces,
+            num_transforms: if has_pawns { 2 } else { 8 },
+            king_squares,
+            king_square_idxs,
+            values: vec![0; num_positions]
+        }
+    }
+
+    pub fn num_pieces(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn num_positions(&self) -> usize {
+        self.values.len()
+    }
+
+    fn material(&self) -> MaterialCounts {
+        let mut counts = [[0; NUM_PIECES]; 2];
+        for (team_idx, piece_idx) in &self.pieces {
+            counts[*team_idx][*piece_idx] += 1;
+        }
+        counts
+    }
+
+    // Every mirror image of a position has the same index
+    // Usually only one symmetry puts the white king on one of king_squares, but if the king is on the a1-h8 diagonal we use the lowest index
+    fn _encode(&self, squares: &[usize], turn_idx: usize) -> usize {
+        let num_pieces = self.pieces.len();
+        let mut best_idx = usize::MAX;
+        for transform in 0..self.num_transforms {
+            let king_square_idx = self.king_square_idxs[_transform_square(squares[0], transform)];
+            if king_square_idx == usize::MAX {
+                continue;
+            }
+
+            let mut transformed_squares = [0usize; MAX_TABLE_PIECES];
+            for (transformed_sq, sq) in transformed_squares.iter_mut().zip(squares) {
+                *transformed_sq = _transform_square(*sq, transform);
+            }
+            self._sort_identical(&mut transformed_squares[..num_pieces]);
+
+            let mut idx = 0;
+            for sq in transformed_squares[1..num_pieces].iter().rev() {
+                idx = (idx << 6) | sq;
+            }
+            idx = (idx * self.king_squares.len() + king_square_idx) * 2 + turn_idx;
+            best_idx = best_idx.min(idx);
+        }
+        best_idx
+    }
+
+    // Returns the turn
+    fn _decode(&self, idx: usize, squares: &mut [usize; MAX_TABLE_PIECES]) -> usize {
+        let mut rest = idx / 2;
+        squares[0] = self.king_squares[rest % self.king_squares.len()];
+        rest /= self.king_squares.len();
+        for sq in squares.iter_mut().take(self.pieces.len()).skip(1) {
+            *sq = rest & 63;
+            rest >>= 6;
+        }
+        idx % 2
+    }
+
+    // Puts the squares of identical pieces in ascending order
+    fn _sort_identical(&self, squares: &mut [usize]) {
+        for i in 0..self.pieces.len() {
+            for j in (i + 1)..self.pieces.len() {
+                if self.pieces[i] == self.pieces[j] && squares[i] > squares[j] {
+                    squares.swap(i, j);
+                }
+            }
+        }
+    }
+
+    // Checks for overlapping pieces and pawns on the back ranks
+    fn _is_valid_placement(&self, squares: &[usize]) -> bool {
+        let mut occupancy: BitMask = 0;
+        for (i, (_, piece_idx)) in self.pieces.iter().enumerate() {
+            let mask = bm_from_idx(squares[i]);
+            if occupancy & mask != 0 {
+                return false;
+            }
+            occupancy |= mask;
+
+            if *piece_idx == PIECE_PAWN && (squares[i] < 8 || squares[i] >= 56) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn _make_board(&self, squares: &[usize], turn_idx: usize) -> Board {
+        let mut board = Board::new();
+        for (i, (team_idx, piece_idx)) in self.pieces.iter().enumerate() {
+            board.pieces[*team_idx][*piece_idx] |= bm_from_idx(squares[i]);
+        }
+        board.turn_idx = turn_idx;
+        board.full_update();
+        board
+    }
+
+    // Index of a board with the same material as this table, or the opposite material if flipped
+    // Flipping swaps the teams and mirrors the board vertically
+    fn _board_idx(&self, board: &Board, flipped: bool) -> usize {
+        let flip_team = flipped as usize;
+        let flip_squares = if flipped { 56 } else { 0 };
+
+        let mut squares = [0usize; MAX_TABLE_PIECES];
+        let mut i = 0;
+        while i < self.pieces.len() {
+            let (team_idx, piece_idx) = self.pieces[i];
+            for piece_mask in bm_iter_bits(board.pieces[team_idx ^ flip_team][piece_idx]) {
+                squares[i] = bm_to_idx(piece_mask) ^ flip_squares;
+                i += 1;
+            }
+        }
+
+        self._encode(&squares[..self.pieces.len()], board.turn_idx ^ flip_team)
+    }
+
+    fn _value_to_result(value: u8) -> DtmResult {
+        if value == 0 {
+            DtmResult::Draw
+        } else {
+            let plies = (value - 1) as u32;
+            if !plies.is_multiple_of(2) { DtmResult::Win(plies) } else { DtmResult::Loss(plies) }
+        }
+    }
+
+    // The board must have the same material as this table, or the opposite material if flipped
+    pub fn probe(&self, board: &Board, flipped: bool) -> DtmResult {
+        DtmTable::_value_to_result(self.values[self._board_idx(board, flipped)])
+    }
+
+    // Most plies until checkmate from any position
+    pub fn get_longest_mate(&self) -> u32 {
+        self.values.iter().map(|value| value.saturating_sub(1) as u32).max().unwrap_or(0)
+    }
+
+    // Finds the positions that can reach a position with a non-capture, non-promotion move
+    // Each position is only listed once, even if it has more than one move to this position (or its mirror images)
+    fn _get_predecessors(&self, idx: usize, out_predecessors: &mut Vec<usize>) {
+        out_predecessors.clear();
+
+        let mut squares = [0usize; MAX_TABLE_PIECES];
+        let turn_idx = self._decode(idx, &mut squares);
+        let prev_turn_idx = 1 - turn_idx;
+        let num_pieces = self.pieces.len();
+
+        let mut occupancy: BitMask = 0;
+        for sq in &squares[..num_pieces] {
+            occupancy |= bm_from_idx(*sq);
+        }
+
+        for i in 0..num_pieces {
+            let (team_idx, piece_idx) = self.pieces[i];
+            if team_idx != prev_turn_idx {
+                continue;
+            }
+
+            let sq = squares[i];
+            let from_mask: BitMask = if piece_idx == PIECE_PAWN {
+                // Pawns move backwards one square, or two from their double move
+                let (single_from, double_from, double_rank) = if team_idx == 0 {
+                    (sq as i64 - 8, sq as i64 - 16, 3)
+                } else {
+                    (sq as i64 + 8, sq as i64 + 16, 4)
+                };
+
+                let mut mask = 0;
+                if (8..56).contains(&single_from) && occupancy & bm_from_idx(single_from as usize) == 0 {
+                    mask |= bm_from_idx(single_from as usize);
+                    if sq / 8 == double_rank && occupancy & bm_from_idx(double_from as usize) == 0 {
+                        mask |= bm_from_idx(double_from as usize);
+                    }
+                }
+                mask
+            } else {
+                lookup_gen::get_piece_tos(piece_idx, bm_from_idx(sq), sq, occupancy) & !occupancy
+            };
+
+            for from in bm_iter_bits(from_mask) {
+                let mut prev_squares = squares;
+                prev_squares[i] = bm_to_idx(from);
+                out_predecessors.push(self._encode(&prev_squares[..num_pieces], prev_turn_idx));
+            }
+        }
+
+        out_predecessors.sort_unstable();
+        out_predecessors.dedup();
+    }
+
+    // Subtables are needed for every ending reachable by a capture or promotion
+    pub fn generate(name: &str, subtables: &DtmTablebase, print: bool) -> Result<DtmTable> {
+        const ILLEGAL: u8 = u8::MAX;
+
+        let counts = _parse_material_name(name)?;
+        let mut table = DtmTable::new(&counts);
+        let num_positions = table.num_positions();
+        let num_pieces = table.num_pieces();
+        let start_time = std::time::Instant::now();
+
+        // Number of positions that our moves reach in this table, which haven't been found to be a win for the opponent yet
+        // Moves to mirror images of the same position only count once, since they share an index
+        let mut move_counts = vec![ILLEGAL; num_positions];
+
+        // Best result from captures and promotions, which leave this table (see _pack_result())
+        let mut exit_results: Vec<u16> = vec![0; num_positions];
+
+        // Positions to resolve, by number of plies until checkmate
+        let mut pending: Vec<Vec<u32>> = Vec::new();
+        let add_pending = |pending: &mut Vec<Vec<u32>>, idx: usize, plies: u32| {
+            if pending.len() <= plies as usize {
+                pending.resize(plies as usize + 1, Vec::new());
+            }
+            pending[plies as usize].push(idx as u32);
+        };
+
+        let mut squares = [0usize; MAX_TABLE_PIECES];
+        let mut moves = move_gen::MoveBuffer::new();
+        let mut next_idxs = Vec::new();
+        for idx in 0..num_positions {
+            let turn_idx = table._decode(idx, &mut squares);
+            if !table._is_valid_placement(&squares[..num_pieces]) || table._encode(&squares[..num_pieces], turn_idx) != idx {
+                continue; // Only the lowest index of each position's mirror images is used
+            }
+
+            let board = table._make_board(&squares[..num_pieces], turn_idx);
+            if board.attacks[turn_idx] & board.pieces[1 - turn_idx][PIECE_KING] != 0 {
+                continue; // Can capture the king
+            }
+
+            moves.clear();
+            move_gen::generate_moves(&board, &mut moves);
+            if moves.is_empty() {
+                move_counts[idx] = 0;
+                if board.checkers != 0 {
+                    add_pending(&mut pending, idx, 0);
+                }
+                continue;
+            }
+
+            next_idxs.clear();
+            let mut best_exit_result: Option<DtmResult> = None;
+            for mv in moves.iter() {
+                let mut next_board = board;
+                next_board.do_move(mv);
+
+                if !mv.has_flag(Move::FLAG_CAPTURE) && !mv.has_flag(Move::FLAG_PROMOTION) {
+                    next_idxs.push(table._board_idx(&next_board, false));
+                    continue;
+                }
+
+                let next_result = match subtables.probe(&next_board) {
+                    Some(next_result) => next_result,
+                    None => return Err(TablebaseError(format!(
+                        "Can't generate {} without a table for {}", table.name, _material_name(&_board_material(&next_board))
+                    )))
+                };
+
+                let exit_result = next_result.for_prev_ply();
+                if best_exit_result.is_none_or(|best| exit_result.rank() > best.rank()) {
+                    best_exit_result = Some(exit_result);
+                }
+            }
+
+            next_idxs.sort_unstable();
+            next_idxs.dedup();
+            move_counts[idx] = next_idxs.len() as u8;
+            exit_results[idx] = _pack_result(best_exit_result);
+            match best_exit_result {
+                Some(DtmResult::Win(plies)) => add_pending(&mut pending, idx, plies),
+                Some(DtmResult::Loss(plies)) if next_idxs.is_empty() => add_pending(&mut pending, idx, plies),
+                _ => {}
+            }
+        }
+
+        // Resolve positions in order of distance to mate, starting from the checkmates
+        // A loss makes every position leading to it a win
+        // A win makes every position leading to it a loss, once all of that position's moves lead to wins
+        let mut predecessors = Vec::new();
+        let mut plies: u32 = 0;
+        while (plies as usize) < pending.len() {
+            let cur_pending = std::mem::take(&mut pending[plies as usize]);
+            for idx in cur_pending {
+                let idx = idx as usize;
+                if table.values[idx] != 0 {
+                    continue; // Already resolved with a shorter mate
+                }
+
+                if plies >= (u8::MAX - 1) as u32 {
+                    return Err(TablebaseError(format!("Mate in {} plies is too long to store in {}", plies, table.name)));
+                }
+                table.values[idx] = plies as u8 + 1;
+
+                let is_loss = plies.is_multiple_of(2);
+                table._get_predecessors(idx, &mut predecessors);
+                for prev_idx in predecessors.iter().copied() {
+                    if move_counts[prev_idx] == ILLEGAL || table.values[prev_idx] != 0 {
+                        continue;
+                    }
+
+                    if is_loss {
+                        add_pending(&mut pending, prev_idx, plies + 1);
+                    } else {
+                        move_counts[prev_idx] -= 1;
+                        if move_counts[prev_idx] == 0 {
+                            match _unpack_result(exit_results[prev_idx]) {
+                                None => add_pending(&mut pending, prev_idx, plies + 1),
+                                Some(DtmResult::Loss(exit_plies)) => add_pending(&mut pending, prev_idx, exit_plies.max(plies + 1)),
+                                _ => {} // Can escape to a draw or win
+                            }
+                        }
+                    }
+                }
+            }
+            plies += 1;
+        }
+
+        if print {
+            let num_legal = move_counts.iter().filter(|count| **count != ILLEGAL).count();
+            let num_decisive = table.values.iter().filter(|value| **value != 0).count();
+            println!(
+                "Generated {} in {:.1}s: {} legal positions, {} decisive, longest mate is {} plies",
+                table.name, start_time.elapsed().as_secs_f64(), num_legal, num_decisive, table.get_longest_mate()
+            );
+        }
+
+        Ok(table)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.values.len() + 16);
+        bytes.extend_from_slice(&FILE_MAGIC);
+        bytes.push(FILE_VERSION);
+        bytes.push(self.pieces.len() as u8);
+        for (team_idx, piece_idx) in &self.pieces {
+            bytes.push(*team_idx as u8);
+            bytes.push(*piece_idx as u8);
+        }
+        bytes.extend_from_slice(&self.values);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<DtmTable> {
+        let throw_err = |msg: &str| -> Result<DtmTable> {
+            Err(TablebaseError(format!("Invalid table data, {}", msg)))
+        };
+
+        if bytes.len() < 6 || bytes[0..4] != FILE_MAGIC {
+            return throw_err("bad header");
+        }
+
+        if bytes[4] != FILE_VERSION {
+            return throw_err(format!("unsupported version {}", bytes[4]).as_str());
+        }
+
+        let num_pieces = bytes[5] as usize;
+        if !(2..=MAX_TABLE_PIECES).contains(&num_pieces) || bytes.len() < 6 + num_pieces * 2 {
+            return throw_err(format!("bad number of pieces {}", num_pieces).as_str());
+        }
+
+        let mut counts = [[0u8; NUM_PIECES]; 2];
+        for i in 0..num_pieces {
+            let team_idx = bytes[6 + i * 2] as usize;
+            let piece_idx = bytes[6 + i * 2 + 1] as usize;
+            if team_idx >= 2 || piece_idx >= NUM_PIECES {
+                return throw_err("bad piece");
+            }
+            counts[team_idx][piece_idx] += 1;
+        }
+
+        let mut table = DtmTable::new(&counts);
+        let values = &bytes[6 + num_pieces * 2..];
+        if values.len() != table.values.len() {
+            return throw_err(format!("expected {} values, found {}", table.values.len(), values.len()).as_str());
+        }
+
+        table.values.copy_from_slice(values);
+        Ok(table)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| TablebaseError(format!("Failed to write table file \"{}\": {}", path.display(), e)))
+    }
+
+    pub fn load(path: &Path) -> Result<DtmTable> {
+        match std::fs::read(path) {
+            Ok(bytes) => DtmTable::from_bytes(&bytes),
+            Err(e) => Err(TablebaseError(format!("Failed to read table file \"{}\": {}", path.display(), e)))
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// A set of generated tables
+pub struct DtmTablebase {
+    tables: Vec<DtmTable>,
+    key_to_idx: HashMap<u64, (usize, bool)>, // Table index and whether the teams are flipped
+    max_pieces: usize
+}
+
+impl std::fmt::Debug for DtmTablebase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DtmTablebase({:?})", self.table_names())
+    }
+}
+
+impl DtmTablebase {
+    pub fn new() -> DtmTablebase {
+        DtmTablebase {
+            tables: Vec::new(),
+            key_to_idx: HashMap::new(),
+            max_pieces: 0
+        }
+    }
+
+    // Replaces any table with the same material
+    pub fn add_table(&mut self, table: DtmTable) {
+        let counts = table.material();
+        let key = _material_key(&counts);
+        let flipped_key = _material_key(&[counts[1], counts[0]]);
+
+        let table_idx = match self.key_to_idx.get(&key) {
+            Some((table_idx, _)) => {
+                self.tables[*table_idx] = table;
+                *table_idx
+            },
+            None => {
+                self.max_pieces = self.max_pieces.max(table.num_pieces());
+                self.tables.push(table);
+                self.tables.len() - 1
+            }
+        };
+
+        self.key_to_idx.insert(flipped_key, (table_idx, true));
+        self.key_to_idx.insert(key, (table_idx, false)); // For symmetric endings, the same key isn't flipped
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn table_names(&self) -> Vec<String> {
+        self.tables.iter().map(|table| table.name.clone()).collect()
+    }
+
+    pub fn get_table(&self, name: &str) -> Option<&DtmTable> {
+        let counts = _parse_material_name(name).ok()?;
+        match self.key_to_idx.get(&_material_key(&counts)) {
+            Some((table_idx, false)) => Some(&self.tables[*table_idx]),
+            _ => None
+        }
+    }
+
+    // Tables have no castling positions
+    pub fn can_probe(&self, board: &Board) -> bool {
+        let has_castle_rights = board.castle_rights.iter().flatten().any(|right| right.is_some());
+        !has_castle_rights && (board.combined_occupancy().count_ones() as usize) <= self.max_pieces
+    }
+
+    // Returns None if we don't have a table for the position's material
+    // En passant and castle rights are ignored, check can_probe() first
+    pub fn probe(&self, board: &Board) -> Option<DtmResult> {
+        let counts = _board_material(board);
+        if _is_trivial_draw(&counts) {
+            return Some(DtmResult::Draw);
+        }
+
+        let (table_idx, flipped) = self.key_to_idx.get(&_material_key(&counts))?;
+        Some(self.tables[*table_idx].probe(board, *flipped))
+    }
+
+    // Picks the move that mates the fastest, or delays being mated the longest
+    pub fn get_best_move(&self, board: &Board) -> Option<Move> {
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(board, &mut moves);
+
+        let mut best: Option<(Move, i64)> = None;
+        for mv in moves.iter() {
+            let mut next_board = *board;
+            next_board.do_move(mv);
+            let rank = self.probe(&next_board)?.for_prev_ply().rank();
+            if best.is_none_or(|(_, best_rank)| rank > best_rank) {
+                best = Some((*mv, rank));
+            }
+        }
+
+        best.map(|(mv, _)| mv)
+    }
+
+    // Generates a table, first generating any missing tables it depends on
+    // Does nothing if we already have the table
+    pub fn generate(&mut self, name: &str, print: bool) -> Result<()> {
+        let counts = _parse_material_name(name)?;
+        if _is_trivial_draw(&counts) || self.key_to_idx.contains_key(&_material_key(&counts)) {
+            return Ok(());
+        }
+
+        // Endings reachable with one capture or promotion
+        for team_idx in 0..2 {
+            for piece_idx in 0..NUM_PIECES_NO_KING {
+                if counts[team_idx][piece_idx] == 0 {
+                    continue;
+                }
+
+                let mut captured_counts = counts;
+                captured_counts[team_idx][piece_idx] -= 1;
+                self.generate(&_material_name(&captured_counts), print)?;
+
+                if piece_idx == PIECE_PAWN {
+                    for promotion_piece_idx in [PIECE_KNIGHT, PIECE_BISHOP, PIECE_ROOK, PIECE_QUEEN] {
+                        let mut promoted_counts = captured_counts;
+                        promoted_counts[team_idx][promotion_piece_idx] += 1;
+                        self.generate(&_material_name(&promoted_counts), print)?;
+                    }
+                }
+            }
+        }
+
+        let table = DtmTable::generate(name, self, print)?;
+        self.add_table(table);
+        Ok(())
+    }
+
+    // Loads every table file in a directory
+    pub fn load_dir(dir: &str) -> Result<DtmTablebase> {
+        let dir_entries = std::fs::read_dir(dir)
+            .map_err(|e| TablebaseError(format!("Failed to read table directory \"{}\": {}", dir, e)))?;
+
+        let mut paths: Vec<_> = dir_entries.flatten()
+            .map(|dir_entry| dir_entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == FILE_EXTENSION))
+            .collect();
+        paths.sort();
+
+        let mut result = DtmTablebase::new();
+        for path in paths {
+            result.add_table(DtmTable::load(&path)?);
+        }
+
+        Ok(result)
+    }
+
+    // Writes each table to "<dir>/<name>.dtm"
+    pub fn save_dir(&self, dir: &str) -> Result<()> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| TablebaseError(format!("Failed to create table directory \"{}\": {}", dir, e)))?;
+
+        for table in &self.tables {
+            table.save(&Path::new(dir).join(format!("{}.{}", table.name, FILE_EXTENSION)))?;
+        }
+
+        Ok(())
+    }
+}
@@ -57,7 +57,7 @@ pub fn get_max_time_to_use(board: &Board, time_state: &TimeState) -> Option<f64>
 }
 
 // Determines whether we should stop searching early
-pub fn should_exit_early(time_given_to_use: f64, time_used: f64, best_moves: &Vec<u8>) -> bool {
+pub fn should_exit_early(time_given_to_use: f64, time_used: f64, best_moves: &Vec<Move>) -> bool {
     let last_depth = best_moves.len();
 
     if last_depth < 5 {
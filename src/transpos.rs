@@ -1,4 +1,6 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
 use crate::zobrist::*;
+use crate::board::PackedMove;
 use crate::eval::Value;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -9,76 +11,96 @@ pub enum EntryType {
     FailHigh
 }
 
+impl EntryType {
+    fn from_bits(bits: u64) -> EntryType {
+        match bits {
+            1 => EntryType::Exact,
+            2 => EntryType::FailLow,
+            3 => EntryType::FailHigh,
+            _ => EntryType::Invalid
+        }
+    }
+}
+
+// Generations wrap around, since there are only 6 bits left to store them in
+const GENERATION_MASK: u8 = 0x3F;
+
 #[derive(Debug, Copy, Clone)]
 pub struct Entry {
     pub hash: Hash,
     pub eval: Value,
-    pub best_move_idx: u8,
+    pub best_move: PackedMove,
     pub depth_remaining: u8,
     pub entry_type: EntryType,
-    pub age_count: u64,
-    pub checksum: u64
+    pub generation: u8 // The search that stored this entry (see Table::new_search())
 }
 
 impl Entry {
     pub fn new() -> Entry {
         Entry {
             hash: 0,
-            eval: 0.0,
-            best_move_idx: 0,
+            eval: 0,
+            best_move: PackedMove::NULL,
             depth_remaining: 0,
             entry_type: EntryType::Invalid,
-            age_count: 0,
-            checksum: 0
+            generation: 0
         }
     }
 
-    pub fn update_checksum(&mut self) {
-        self.checksum = self.calc_checksum();
+    pub fn is_valid(&self) -> bool {
+        self.entry_type != EntryType::Invalid
     }
 
-    fn calc_checksum(&self) -> u64 {
-        let mut cur_checksum = 0;
+    // Layout: eval (32 bits), best move (16 bits), depth remaining (8 bits), entry type (2 bits), generation (6 bits)
+    fn pack_data(&self) -> u64 {
+        (self.eval as u32 as u64)
+            | ((self.best_move.0 as u64) << 32)
+            | ((self.depth_remaining as u64) << 48)
+            | ((self.entry_type as u64) << 56)
+            | (((self.generation & GENERATION_MASK) as u64) << 58)
+    }
 
-        cur_checksum += self.hash;
-        unsafe {
-            cur_checksum += (std::mem::transmute::<Value, i32>(self.eval) as u64) ^ cur_checksum;
+    fn unpack_data(hash: Hash, data: u64) -> Entry {
+        Entry {
+            hash,
+            eval: data as u32 as Value,
+            best_move: PackedMove((data >> 32) as u16),
+            depth_remaining: (data >> 48) as u8,
+            entry_type: EntryType::from_bits((data >> 56) & 3),
+            generation: (data >> 58) as u8
         }
-        cur_checksum += self.best_move_idx as u64 ^ cur_checksum;
-        cur_checksum += self.depth_remaining as u64 ^ cur_checksum;
-        cur_checksum += self.entry_type as u64 ^ cur_checksum;
-
-        // NOTE: We don't care about the age count, it's not that important
-
-        cur_checksum
     }
+}
 
-    pub fn is_set(&self) -> bool {
-        self.entry_type != EntryType::Invalid
-    }
+///////////////////////////////////////////
 
-    pub fn is_valid(&self) -> bool {
-        if self.entry_type == EntryType::Invalid {
-            return false;
-        }
+// An entry stored as atomics, so that the table can be shared between search threads without locking
+// The key is the hash XORed with the data, so if another thread writes between our reads of the key and the data, the key won't match
+// See https://www.chessprogramming.org/Shared_Hash_Table#Lock-less
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64
+}
 
-        self.checksum == self.calc_checksum()
+impl Slot {
+    fn new() -> Slot {
+        Slot {
+            key: AtomicU64::new(0),
+            data: AtomicU64::new(0)
+        }
     }
 }
 
-///////////////////////////////////////////
-
 const ENTRIES_PER_BUCKET: usize = 4;
 
-#[derive(Debug, Copy, Clone)]
 struct Bucket {
-    entries: [Entry; ENTRIES_PER_BUCKET],
+    slots: [Slot; ENTRIES_PER_BUCKET],
 }
 
 impl Bucket {
-    pub fn new() -> Bucket {
+    fn new() -> Bucket {
         Bucket {
-            entries: [Entry::new(); ENTRIES_PER_BUCKET],
+            slots: std::array::from_fn(|_| Slot::new()),
         }
     }
 }
@@ -87,18 +109,17 @@ impl Bucket {
 
 pub struct Table {
     buckets: Vec<Bucket>,
-    age_count: u64,
+    generation: AtomicU8,
     size_mbs: usize
 }
 
 impl Table {
     pub fn new(size_mbs: usize) -> Table {
-        let num_buckets = (size_mbs * 1_000_000) / size_of::<Bucket>();
-        let mut buckets = Vec::with_capacity(num_buckets);
-        buckets.resize(num_buckets, Bucket::new());
+        let num_buckets = usize::max((size_mbs * 1_000_000) / size_of::<Bucket>(), 1);
+        let buckets = (0..num_buckets).map(|_| Bucket::new()).collect();
         Table {
             buckets,
-            age_count: 0,
+            generation: AtomicU8::new(0),
             size_mbs
         }
     }
@@ -107,92 +128,72 @@ impl Table {
         self.size_mbs
     }
 
-    pub fn is_any_entry_locked(&self) -> bool{
-        for bucket in &self.buckets {
-            for entry in &bucket.entries {
-                if entry.is_set() && !entry.is_valid() {
-                    return true;
-                }
-            }
-        }
+    pub fn get_num_buckets(&self) -> usize {
+        self.buckets.len()
+    }
 
-        false
+    // Called once at the start of each search, so that entries from older searches are replaced first
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
     }
 
     fn get_bucket_idx(&self, hash: Hash) -> usize {
         (hash as usize) % self.buckets.len()
     }
 
-    // If the entry is locked, just returns an empty entry
+    // Returns an invalid entry if the hash isn't found
     pub fn get_fast(&self, hash: Hash) -> Entry {
         let bucket = &self.buckets[self.get_bucket_idx(hash)];
-        for i in 0..ENTRIES_PER_BUCKET {
-            if bucket.entries[i].hash == hash {
-                return bucket.entries[i];
+        for slot in &bucket.slots {
+            let data = slot.data.load(Ordering::Relaxed);
+            let key = slot.key.load(Ordering::Relaxed);
+            if key ^ data == hash {
+                return Entry::unpack_data(hash, data);
             }
         }
 
         Entry::new()
     }
 
-    // Waits for the entry to be unlocked
-    pub fn get_wait(&self, hash: Hash) -> Entry {
+    pub fn set(&self, hash: Hash, eval: Value, best_move: PackedMove, depth_remaining: u8, entry_type: EntryType) {
         let bucket = &self.buckets[self.get_bucket_idx(hash)];
-        loop {
-            let mut was_locked = false;
-            for i in 0..ENTRIES_PER_BUCKET {
-                if bucket.entries[i].hash == hash {
-                    let result = bucket.entries[i];
-                    if result.is_set() && !result.is_valid() {
-                        was_locked = true;
-                        break;
-                    }
-                    return result;
-                }
-            }
-
-            if was_locked {
-                continue;
-            } else {
-                return Entry::new();
-            }
-        }
-    }
 
-    pub fn set(&mut self, hash: Hash, eval: Value, best_move_idx: u8, depth_remaining: u8, entry_type: EntryType) {
-        let bucket_idx = self.get_bucket_idx(hash);
-        let bucket = &mut self.buckets[bucket_idx];
+        let generation = self.generation.load(Ordering::Relaxed) & GENERATION_MASK;
 
-        // Find the oldest entry to replace
+        // Find the entry to replace, which is the one from the oldest search, and then the one with the lowest depth
         let mut replace_entry_idx = 0;
-        let mut oldest_entry_age = u64::max_value();
+        let mut best_replace_score = (0, 0);
         for i in 0..ENTRIES_PER_BUCKET {
-            let existing_entry = bucket.entries[i];
-            if existing_entry.hash == hash {
+            let existing_slot = &bucket.slots[i];
+            let existing_data = existing_slot.data.load(Ordering::Relaxed);
+            let existing_hash = existing_slot.key.load(Ordering::Relaxed) ^ existing_data;
+            if existing_hash == hash {
                 // We found a matching hash, just use that
                 replace_entry_idx = i;
                 break;
             }
 
-            if existing_entry.age_count < oldest_entry_age {
-                oldest_entry_age = existing_entry.age_count;
+            let existing_entry = Entry::unpack_data(existing_hash, existing_data);
+            let existing_age = if existing_entry.is_valid() { generation.wrapping_sub(existing_entry.generation) & GENERATION_MASK } else { u8::MAX };
+            let replace_score = (existing_age, u8::MAX - existing_entry.depth_remaining);
+            if i == 0 || replace_score > best_replace_score {
+                best_replace_score = replace_score;
                 replace_entry_idx = i;
             }
         }
 
-        self.age_count += 1;
-
-        let mut entry = Entry {
+        let entry = Entry {
             hash,
             eval,
-            best_move_idx,
+            best_move,
             depth_remaining,
             entry_type,
-            age_count: self.age_count,
-            checksum: 0
+            generation
         };
-        entry.update_checksum();
+        let data = entry.pack_data();
 
-        bucket.entries[replace_entry_idx] = entry;
+        let slot = &bucket.slots[replace_entry_idx];
+        slot.data.store(data, Ordering::Relaxed);
+        slot.key.store(hash ^ data, Ordering::Relaxed);
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,20 @@
 use std::cmp::PartialEq;
+use std::sync::Arc;
 use crate::board::*;
 use crate::move_gen;
 use crate::search;
+use crate::perft;
 use crate::eval::*;
 use crate::fen;
+use crate::game::Game;
 use crate::search::SearchInfo;
-use crate::transpos;
+use crate::search::SearchConfig;
 use crate::async_engine::AsyncEngine;
+use crate::book;
+use crate::book::Book;
+use crate::syzygy::Tablebase;
+use crate::tablebase;
+use crate::tablebase::DtmTablebase;
 use crate::time_manager::TimeState;
 // Refs:
 // - https://gist.github.com/DOBRO/2592c6dad754ba67e6dcaec8c90165bf
@@ -14,27 +22,29 @@ use crate::time_manager::TimeState;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum UCIOptionType {
-    Int, Bool, Button
+    Int, Bool, Button, String
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 struct UCIOption {
     option_type: UCIOptionType,
     name: &'static str,
     value: i64,
+    value_str: String, // Only used by string options
     value_min: i64,
     value_max: i64,
     change_callback: Option<fn (&mut UCIState, i64)>
 }
 
 impl UCIOption {
-    const TYPE_NAMES: [&'static str; 3] = ["spin", "check", "button"];
+    const TYPE_NAMES: [&'static str; 4] = ["spin", "check", "button", "string"];
 
     pub fn new_int(name: &'static str, default: i64, value_min: i64, value_max: i64, change_callback: Option<fn (&mut UCIState, i64)>) -> UCIOption {
         UCIOption {
             option_type: UCIOptionType::Int,
             name,
             value: default,
+            value_str: String::new(),
             value_min,
             value_max,
             change_callback
@@ -43,9 +53,10 @@ impl UCIOption {
 
     pub fn new_bool(name: &'static str, default: bool, change_callback: Option<fn (&mut UCIState, i64)>) -> UCIOption {
         UCIOption {
-            option_type: UCIOptionType::Int,
+            option_type: UCIOptionType::Bool,
             name,
             value: default as i64,
+            value_str: String::new(),
             value_min: 0,
             value_max: 1,
             change_callback
@@ -57,16 +68,31 @@ impl UCIOption {
             option_type: UCIOptionType::Button,
             name,
             value: 0,
+            value_str: String::new(),
             value_min: 0,
             value_max: 0,
             change_callback: Some(change_callback)
         }
     }
+
+    // The callback should read the new value with UCIState::get_option_str()
+    pub fn new_string(name: &'static str, default: &str, change_callback: Option<fn (&mut UCIState, i64)>) -> UCIOption {
+        UCIOption {
+            option_type: UCIOptionType::String,
+            name,
+            value: 0,
+            value_str: default.to_string(),
+            value_min: 0,
+            value_max: 0,
+            change_callback
+        }
+    }
 }
 
 pub struct UCIState {
     engine: AsyncEngine,
-    options: Vec<UCIOption>
+    options: Vec<UCIOption>,
+    book: Option<Book>
 }
 
 impl UCIState {
@@ -82,11 +108,74 @@ impl UCIState {
             UCIOption::new_button("Clear Hash", |state: &mut UCIState, _new_value: i64| {
                  state.engine.reset_table();
             }),
+            UCIOption::new_bool("UCI_Chess960", false,
+                Some(|state: &mut UCIState, new_value: i64| {
+                    state.engine.set_chess960(new_value != 0);
+                })
+            ),
+            UCIOption::new_int("MultiPV", 1, 1, 256,
+                Some(|state: &mut UCIState, new_value: i64| {
+                    state.engine.set_multi_pv(new_value as usize);
+                })
+            ),
+            UCIOption::new_bool("OwnBook", false, None),
+            UCIOption::new_string("BookFile", "",
+                Some(|state: &mut UCIState, _new_value: i64| {
+                    let path = state.get_option_str("BookFile").to_string();
+                    state.book = None;
+                    if !path.is_empty() {
+                        match Book::load(&path) {
+                            Ok(book) => {
+                                println!("info string Loaded book with {} entries", book.len());
+                                state.book = Some(book);
+                            },
+                            Err(e) => println!("info string Error: {}", e)
+                        }
+                    }
+                })
+            ),
+            UCIOption::new_int("BookDepth", 20, 0, 1000, None), // Max number of plies to use the book for
+            UCIOption::new_string("SyzygyPath", "",
+                Some(|state: &mut UCIState, _new_value: i64| {
+                    let path = state.get_option_str("SyzygyPath").to_string();
+                    let tablebase = Tablebase::new(&path);
+                    if tablebase.num_wdl_tables() > 0 {
+                        println!(
+                            "info string Found {} WDL and {} DTZ tablebase files, up to {} pieces",
+                            tablebase.num_wdl_tables(), tablebase.num_dtz_tables(), tablebase.max_pieces()
+                        );
+                        state.engine.set_tablebase(Some(Arc::new(tablebase)));
+                    } else {
+                        if !path.is_empty() {
+                            println!("info string No tablebase files found in \"{}\"", path);
+                        }
+                        state.engine.set_tablebase(None);
+                    }
+                })
+            ),
+            UCIOption::new_string("DtmTablePath", "",
+                Some(|state: &mut UCIState, _new_value: i64| {
+                    let path = state.get_option_str("DtmTablePath").to_string();
+                    state.engine.set_dtm_tablebase(None);
+                    if !path.is_empty() {
+                        match DtmTablebase::load_dir(&path) {
+                            Ok(dtm_tablebase) => {
+                                println!("info string Loaded {} generated tables", dtm_tablebase.len());
+                                if !dtm_tablebase.is_empty() {
+                                    state.engine.set_dtm_tablebase(Some(Arc::new(dtm_tablebase)));
+                                }
+                            },
+                            Err(e) => println!("info string Error: {}", e)
+                        }
+                    }
+                })
+            ),
         ];
 
         let mut result = UCIState {
             engine: AsyncEngine::new(DEFAULT_TABLE_SIZE_MBS),
-            options: Vec::new()
+            options: Vec::new(),
+            book: None
         };
 
         for option in options.iter() {
@@ -105,43 +194,68 @@ impl UCIState {
 
         panic!("UCI Option {} not found", name);
     }
+
+    pub fn get_option_str(&self, name: &str) -> &str {
+        for option in &self.options {
+            if option.name == name {
+                return &option.value_str;
+            }
+        }
+
+        panic!("UCI Option {} not found", name);
+    }
 }
 
 //////////////////////////
 
-pub fn print_search_results(board: &Board, table: &transpos::Table, depth: u8, eval: Value, search_info: &SearchInfo, elapsed_time: f64) {
-    let mut moves = move_gen::MoveBuffer::new();
-    move_gen::generate_moves(board, &mut moves);
+// Writes a move in UCI notation
+// In Chess960 mode, castling is written as king-takes-rook
+pub fn move_to_uci_str(mv: &Move, chess960: bool) -> String {
+    if chess960 {
+        format!("{mv:#}")
+    } else {
+        format!("{mv}")
+    }
+}
 
-    let pv_moves = search::determine_pv(*board, table);
+// total_nodes is for the whole search so far, across all threads
+pub fn print_search_results(depth: u8, multipv: usize, eval: Value, search_info: &SearchInfo, total_nodes: usize, elapsed_time: f64, chess960: bool) {
+    let pv_moves = search_info.get_pv();
     let mut pv_str = String::new();
     for i in 0..pv_moves.len() {
         if i > 0 {
             pv_str.push(' ');
         }
 
-        pv_str += format!("{}", &pv_moves[i]).as_str();
+        pv_str += move_to_uci_str(&pv_moves[i], chess960).as_str();
     }
 
-    let eval_str;
-    if eval.abs() >= VALUE_CHECKMATE_MIN {
-        eval_str = eval_to_str(eval).replace("#", "mate ");
-    } else {
-        eval_str = format!("cp {}", (eval * 100.0).round() as i64);
-    }
+    let eval_str = match get_mate_moves(eval) {
+        Some(mate_moves) => format!("mate {}", mate_moves),
+        None => format!("cp {}", eval)
+    };
 
-    let multipv = 1;
-    let total_nodes = search_info.total_nodes;
-    let nodes_per_sec = ((search_info.total_nodes as f64) / elapsed_time).round() as i64;
+    let nodes_per_sec = ((total_nodes as f64) / elapsed_time).round() as i64;
     let elapsed_ms = (elapsed_time * 1000.0).round() as i64;
+    let tb_hits = search_info.tb_hits;
 
     println!(
-        "info depth {depth} multipv {multipv} score {eval_str} nodes {total_nodes} nps {nodes_per_sec} time {elapsed_ms} pv {pv_str}"
+        "info depth {depth} multipv {multipv} score {eval_str} nodes {total_nodes} nps {nodes_per_sec} tbhits {tb_hits} time {elapsed_ms} pv {pv_str}"
     );
 }
 
-pub fn print_best_move(best_move: Move) {
-    println!("bestmove {}", best_move);
+// Finds the legal move written in UCI notation
+fn _parse_uci_move(board: &Board, move_str: &str, chess960: bool) -> Option<Move> {
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+    moves.iter().copied().find(|mv| move_to_uci_str(mv, chess960).eq(move_str))
+}
+
+pub fn print_best_move(best_move: Move, ponder_move: Option<Move>, chess960: bool) {
+    match ponder_move {
+        Some(ponder_move) => println!("bestmove {} ponder {}", move_to_uci_str(&best_move, chess960), move_to_uci_str(&ponder_move, chess960)),
+        None => println!("bestmove {}", move_to_uci_str(&best_move, chess960))
+    }
 }
 
 // Just returns an Option<String> of the error
@@ -165,7 +279,10 @@ fn cmd_uci(_parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
             UCIOptionType::Bool => {
                 print!(" default {}", option.value > 0);
             },
-            UCIOptionType::Button => {}
+            UCIOptionType::Button => {},
+            UCIOptionType::String => {
+                print!(" default {}", if option.value_str.is_empty() { "<empty>" } else { &option.value_str });
+            }
         }
 
         println!();
@@ -216,6 +333,15 @@ fn cmd_setoption(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
                 return cmd_err!("Value missing")
             }
 
+            if option.option_type == UCIOptionType::String {
+                option.value_str = if new_value_str == "<empty>" { String::new() } else { new_value_str.clone() };
+                println!("info string \"{}\" -> {}", option.name, new_value_str);
+                if let Some(change_callback) = option.change_callback {
+                    change_callback(state, 0);
+                }
+                return None;
+            }
+
             let new_value: i64 = match new_value_str.to_lowercase().as_str() {
                 "false" => 0,
                 "true" => 1,
@@ -246,7 +372,7 @@ fn cmd_setoption(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
                         return cmd_err!("Invalid bool value: \"{}\", expected \"false\", \"true\", \"0\", or \"1\"", new_value_str);
                     }
                 },
-                UCIOptionType::Button => {
+                UCIOptionType::Button | UCIOptionType::String => {
                     // Don't care
                 }
             }
@@ -276,7 +402,7 @@ fn cmd_position(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
         cmd_err!("Too few arguments");
     }
 
-    let mut board;
+    let board;
 
     let mut cur_part_idx: usize = 2;
     if parts[1] == "fen" {
@@ -302,25 +428,15 @@ fn cmd_position(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
         return cmd_err!("Unknown position type \"{}\"", parts[1]);
     }
 
+    let chess960 = state.get_option_val("UCI_Chess960") != 0;
+    let mut game = Game::new(&board);
     if cur_part_idx < parts.len() {
         if parts[cur_part_idx] == "moves" {
             for i in (cur_part_idx + 1)..parts.len() {
                 let move_str = &parts[i];
-
-                let mut moves = move_gen::MoveBuffer::new();
-                move_gen::generate_moves(&board, &mut moves);
-
-                let mut move_found = false;
-                for mv in moves.iter() {
-                    if format!("{mv}").eq(move_str) {
-                        board.do_move(mv);
-                        move_found = true;
-                        break;
-                    }
-                }
-
-                if !move_found {
-                    return cmd_err!("Invalid move \"{}\" for position \"{}\"", move_str, fen::make_fen(&board));
+                match _parse_uci_move(game.board(), move_str, chess960) {
+                    Some(mv) => game.do_move(&mv),
+                    None => return cmd_err!("Invalid move \"{}\" for position \"{}\"", move_str, fen::make_fen(game.board()))
                 }
             }
         } else {
@@ -328,7 +444,7 @@ fn cmd_position(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
         }
     }
 
-    state.engine.set_board(&board);
+    state.engine.set_game(&game);
     None
 }
 
@@ -355,6 +471,30 @@ fn cmd_go(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
 
     let mut max_depth: Option<u8> = None;
     let mut time_state: TimeState = TimeState::new();
+    let mut search_config = SearchConfig::new();
+    let mut infinite = false;
+    let mut ponder = false;
+
+    let chess960 = state.engine.is_chess960();
+    let mut in_search_moves = false;
+    for single in singles {
+        match single.as_str() {
+            "infinite" => infinite = true,
+            "ponder" => ponder = true,
+            "searchmoves" => in_search_moves = true,
+            _ => {
+                if !in_search_moves {
+                    println!("info string Ignoring unknown argument \"{}\"", single);
+                    continue;
+                }
+
+                match _parse_uci_move(board, &single, chess960) {
+                    Some(mv) => search_config.root_moves.push(mv),
+                    None => return cmd_err!("Invalid search move \"{}\" for position \"{}\"", single, fen::make_fen(board))
+                }
+            }
+        }
+    }
 
     let remaining_time_str = if board.turn_idx == 0 { "wtime" } else { "btime" };
     let time_inc_str = if board.turn_idx == 0 { "winc" } else { "binc" };
@@ -371,6 +511,12 @@ fn cmd_go(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
             "movestogo" => {
                 time_state.moves_till_time_control = Some(pair.1 as u64);
             }
+            "nodes" => {
+                search_config.max_nodes = Some(pair.1 as usize);
+            }
+            "mate" => {
+                search_config.mate_moves = Some(pair.1 as u8);
+            }
             "perft" => {
                 search::perft(state.engine.get_board(), pair.1 as u8, true);
                 return None;
@@ -386,8 +532,38 @@ fn cmd_go(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
         }
     }
 
+    // Play straight from the book if we can
+    // Not while analyzing, or when we might be told to search a different position on a ponderhit
+    let board = state.engine.get_board();
+    let ply = (board.full_move_counter as usize - 1) * 2 + board.turn_idx;
+    if state.get_option_val("OwnBook") != 0 && ply < state.get_option_val("BookDepth") as usize && !infinite && !ponder {
+        if let Some(book) = &state.book {
+            if let Some(book_move) = book.get_random_move(state.engine.get_board()) {
+                state.engine.stop_search();
+                print_best_move(book_move, None, state.engine.is_chess960());
+                return None;
+            }
+        }
+    }
+
     state.engine.maybe_update_table_size(state.get_option_val("Hash") as usize);
-    state.engine.start_search(max_depth, Some(time_state), state.get_option_val("Threads") as usize);
+    let num_threads = state.get_option_val("Threads") as usize;
+    if ponder {
+        state.engine.start_ponder(max_depth, Some(time_state), num_threads, search_config);
+    } else if infinite {
+        state.engine.start_search(max_depth, None, num_threads, search_config, true);
+    } else {
+        state.engine.start_search(max_depth, Some(time_state), num_threads, search_config, false);
+    }
+    None
+}
+
+fn cmd_ponderhit(_parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
+    if !state.engine.is_pondering() {
+        return cmd_err!("Not pondering");
+    }
+
+    state.engine.ponderhit();
     None
 }
 
@@ -427,7 +603,175 @@ fn cmd_d(_parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
     None
 }
 
-const CMD_FNS: [(fn(&Vec<String>, &mut UCIState) -> Option<String>, &str); 10] = [
+// Runs an EPD test suite, e.g. "epd wac.epd movetime 1000" or "epd sts.epd depth 8"
+fn cmd_epd(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
+    if parts.len() < 2 {
+        return cmd_err!("Missing EPD file path");
+    }
+
+    let mut max_depth: Option<u8> = None;
+    let mut max_time: Option<f64> = None;
+    for pair in parts[2..].chunks(2) {
+        let val = pair.get(1).and_then(|val_str| val_str.parse::<i64>().ok());
+        match (pair[0].as_str(), val) {
+            ("depth", Some(depth)) => max_depth = Some(depth as u8),
+            ("movetime", Some(ms)) => max_time = Some(ms as f64 / 1000.0),
+            _ => return cmd_err!("Bad epd argument \"{}\"", pair[0])
+        }
+    }
+
+    if max_depth.is_none() && max_time.is_none() {
+        return cmd_err!("EPD test suite needs a depth or movetime");
+    }
+
+    let entries = match crate::epd::load_epd_file(&parts[1]) {
+        Ok(entries) => entries,
+        Err(e) => return cmd_err!("{}", e)
+    };
+
+    state.engine.stop_search();
+    let table_size_mbs = state.get_option_val("Hash") as usize;
+    crate::epd::run_test_suite(&entries, max_depth, max_time, table_size_mbs, &search::SearchConfig::new(), true);
+    None
+}
+
+// Builds an opening book from PGN files
+// e.g. "makebook games1.pgn games2.pgn ply 16 mincount 2 bin book.bin fens opening_fens.txt"
+fn cmd_makebook(parts: &Vec<String>, _state: &mut UCIState) -> Option<String> {
+    const KEYWORDS: [&str; 4] = ["ply", "mincount", "bin", "fens"];
+
+    let pgn_paths: Vec<String> = parts[1..].iter().take_while(|part| !KEYWORDS.contains(&part.as_str())).cloned().collect();
+    if pgn_paths.is_empty() {
+        return cmd_err!("Missing PGN file path");
+    }
+
+    let mut max_ply: usize = 16;
+    let mut min_count: u32 = 1;
+    let mut bin_path: Option<&str> = None;
+    let mut fens_path: Option<&str> = None;
+    for pair in parts[1 + pgn_paths.len()..].chunks(2) {
+        let Some(val) = pair.get(1) else {
+            return cmd_err!("Missing value for \"{}\"", pair[0]);
+        };
+
+        match pair[0].as_str() {
+            "ply" | "mincount" => {
+                let Ok(num) = val.parse::<u32>() else {
+                    return cmd_err!("Invalid number value: \"{}\"", val);
+                };
+                if pair[0] == "ply" { max_ply = num as usize } else { min_count = num }
+            },
+            "bin" => bin_path = Some(val),
+            "fens" => fens_path = Some(val),
+            _ => return cmd_err!("Bad makebook argument \"{}\"", pair[0])
+        }
+    }
+
+    if bin_path.is_none() && fens_path.is_none() {
+        return cmd_err!("Nothing to output, give a \"bin\" and/or \"fens\" path");
+    }
+
+    let (builder, num_skipped) = match book::build_from_pgn_files(&pgn_paths, max_ply) {
+        Ok(result) => result,
+        Err(e) => return cmd_err!("{}", e)
+    };
+    println!(
+        "info string Read {} games ({} skipped), {} book positions",
+        builder.num_games, num_skipped, builder.positions.len()
+    );
+
+    if let Some(bin_path) = bin_path {
+        let bytes = builder.make_bin(min_count);
+        if let Err(e) = std::fs::write(bin_path, &bytes) {
+            return cmd_err!("Failed to write \"{}\": {}", bin_path, e);
+        }
+        println!("info string Wrote {} book entries to \"{}\"", bytes.len() / book::ENTRY_SIZE, bin_path);
+    }
+
+    if let Some(fens_path) = fens_path {
+        let fens = builder.make_fen_list(min_count);
+        if let Err(e) = std::fs::write(fens_path, fens.join("\n") + "\n") {
+            return cmd_err!("Failed to write \"{}\": {}", fens_path, e);
+        }
+        println!("info string Wrote {} FENs to \"{}\"", fens.len(), fens_path);
+    }
+
+    None
+}
+
+// Generates distance-to-mate tables and saves them to a directory, e.g. "tbgen tables KQvK KRvK"
+// Tables already in the directory are reused, and the default endings are generated if none are given
+// The engine then uses every table in the directory
+fn cmd_tbgen(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
+    if parts.len() < 2 {
+        return cmd_err!("Missing table directory");
+    }
+
+    let dir = parts[1].as_str();
+    let mut dtm_tablebase = if std::path::Path::new(dir).is_dir() {
+        match DtmTablebase::load_dir(dir) {
+            Ok(dtm_tablebase) => dtm_tablebase,
+            Err(e) => return cmd_err!("{}", e)
+        }
+    } else {
+        DtmTablebase::new()
+    };
+
+    let names: Vec<&str> = if parts.len() > 2 {
+        parts[2..].iter().map(|part| part.as_str()).collect()
+    } else {
+        tablebase::DEFAULT_TABLE_NAMES.to_vec()
+    };
+
+    state.engine.stop_search();
+    for name in names {
+        if let Err(e) = dtm_tablebase.generate(name, true) {
+            return cmd_err!("{}", e);
+        }
+    }
+
+    if let Err(e) = dtm_tablebase.save_dir(dir) {
+        return cmd_err!("{}", e);
+    }
+    println!("info string Saved {} tables to \"{}\"", dtm_tablebase.len(), dir);
+
+    state.engine.set_dtm_tablebase(Some(Arc::new(dtm_tablebase)));
+    None
+}
+
+// Runs perft on the current position, e.g. "perft 6 threads 8 hash 64" or "perft 4 stats"
+// Prints the node count after each root move, and with "stats", the number of each type of move at each depth
+fn cmd_perft(parts: &Vec<String>, state: &mut UCIState) -> Option<String> {
+    let Some(depth) = parts.get(1).and_then(|depth_str| depth_str.parse::<u8>().ok()) else {
+        return cmd_err!("Missing or invalid perft depth");
+    };
+
+    let mut config = perft::PerftConfig::new();
+    config.num_threads = state.get_option_val("Threads") as usize;
+    let mut i = 2;
+    while i < parts.len() {
+        match parts[i].as_str() {
+            "stats" => config.collect_stats = true,
+            "nobulk" => config.bulk_counting = false,
+            "threads" | "hash" => {
+                let Some(val) = parts.get(i + 1).and_then(|val_str| val_str.parse::<usize>().ok()) else {
+                    return cmd_err!("Missing or invalid value for \"{}\"", parts[i]);
+                };
+                if parts[i] == "threads" { config.num_threads = val } else { config.hash_size_mbs = val }
+                i += 1;
+            },
+            _ => return cmd_err!("Bad perft argument \"{}\"", parts[i])
+        }
+        i += 1;
+    }
+
+    state.engine.stop_search();
+    let result = perft::run(state.engine.get_board(), depth, &config);
+    perft::print_result(&result, state.engine.is_chess960());
+    None
+}
+
+const CMD_FNS: [(fn(&Vec<String>, &mut UCIState) -> Option<String>, &str); 15] = [
     (cmd_uci, "uci"),
     (cmd_isready, "isready"),
     (cmd_setoption, "setoption"),
@@ -435,9 +779,14 @@ const CMD_FNS: [(fn(&Vec<String>, &mut UCIState) -> Option<String>, &str); 10] =
     (cmd_position, "position"),
     (cmd_go, "go"),
     (cmd_stop, "stop"),
+    (cmd_ponderhit, "ponderhit"),
     (cmd_eval, "eval"),
     (cmd_ratemoves, "ratemoves"),
-    (cmd_d, "d")
+    (cmd_d, "d"),
+    (cmd_epd, "epd"),
+    (cmd_makebook, "makebook"),
+    (cmd_tbgen, "tbgen"),
+    (cmd_perft, "perft")
 ];
 
 // Returns true if the command was understood and processed correctly
@@ -1,25 +1,28 @@
-use rand::Rng;
 use crate::bitmask::*;
-use crate::board::*;
-extern crate rand;
+use crate::polyglot_keys::POLYGLOT_RANDOM_64;
+
+// We use the Polyglot keys so that hashes are the same across runs, and match other programs and opening books
 
 pub type Hash = u64;
 
-static mut LT_HASH_PIECE: [[[Hash; 64]; NUM_PIECES]; 2] = [[[0; 64]; NUM_PIECES]; 2];
-static mut LT_HASH_CASTLE_RIGHTS: [[Hash; 2]; 2] = [[0; 2]; 2];
-static mut LT_HASH_EN_PASSANT: [Hash; 64] = [0; 64];
-static mut LT_HASH_TURN: Hash = 0;
+const PIECE_KEYS_OFFSET: usize = 0;
+const CASTLE_KEYS_OFFSET: usize = 768;
+const EN_PASSANT_KEYS_OFFSET: usize = 772;
+const TURN_KEY_OFFSET: usize = 780;
 
 pub fn hash_piece(team_idx: usize, piece_idx: usize, pos_idx: usize) -> Hash {
-    unsafe { LT_HASH_PIECE[team_idx][piece_idx][pos_idx] }
+    // Polyglot has black pieces first
+    let kind = piece_idx * 2 + (1 - team_idx);
+    POLYGLOT_RANDOM_64[PIECE_KEYS_OFFSET + kind * 64 + pos_idx]
 }
 
-pub fn hash_castle_rights(castle_rights: [[bool; 2]; 2]) -> Hash {
+pub fn hash_castle_rights(castle_rights: [[Option<u8>; 2]; 2]) -> Hash {
     let mut result = 0;
     for i in 0..2 {
         for j in 0..2 {
-            if castle_rights[i][j] {
-                result ^= unsafe { LT_HASH_CASTLE_RIGHTS[i][j] };
+            if castle_rights[i][j].is_some() {
+                // Polyglot has kingside first
+                result ^= POLYGLOT_RANDOM_64[CASTLE_KEYS_OFFSET + i * 2 + (1 - j)];
             }
         }
     }
@@ -27,37 +30,27 @@ pub fn hash_castle_rights(castle_rights: [[bool; 2]; 2]) -> Hash {
     result
 }
 
-pub fn hash_en_passant(en_passant_mask: BitMask) -> Hash {
-    if en_passant_mask != 0 {
-        unsafe { LT_HASH_EN_PASSANT[bm_to_idx(en_passant_mask)] }
+// Like Polyglot, en passant is only hashed if a pawn of the team to move is next to the pawn that double-moved
+// This way, positions that only differ in an unusable en passant square have the same hash
+pub fn hash_en_passant(en_passant_mask: BitMask, capturer_pawns: BitMask) -> Hash {
+    if en_passant_mask == 0 {
+        return 0;
+    }
+
+    let (x, y) = bm_to_xy(en_passant_mask);
+    let capturer_y = if y == 5 { 4 } else { 3 };
+    let capture_from_mask =
+        (bm_shift(bm_from_xy(x, capturer_y), -1, 0) & !bm_make_column(7)) |
+        (bm_shift(bm_from_xy(x, capturer_y), 1, 0) & !bm_make_column(0));
+
+    if capture_from_mask & capturer_pawns != 0 {
+        POLYGLOT_RANDOM_64[EN_PASSANT_KEYS_OFFSET + x as usize]
     } else {
         0
     }
 }
 
+// Applied when it is white's turn
 pub fn hash_turn() -> Hash {
-    unsafe { LT_HASH_TURN }
+    POLYGLOT_RANDOM_64[TURN_KEY_OFFSET]
 }
-
-pub fn init() {
-    let mut rng = rand::rng();
-    unsafe {
-        for i in 0..2 {
-            for j in 0..NUM_PIECES {
-                for k in 0..64 {
-                    LT_HASH_PIECE[i][j][k] = rng.random::<Hash>();
-                }
-            }
-
-            for j in 0..2 {
-                LT_HASH_CASTLE_RIGHTS[i][j] = rng.random::<Hash>();
-            }
-        }
-
-        for i in 0..64 {
-            LT_HASH_EN_PASSANT[i] = rng.random::<Hash>();
-        }
-
-        LT_HASH_TURN = rng.random::<Hash>();
-    }
-}
\ No newline at end of file
@@ -0,0 +1,171 @@
+use board_crab_lib::async_engine::*;
+use board_crab_lib::board::*;
+use board_crab_lib::eval::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::search::SearchConfig;
+use board_crab_lib::time_manager::TimeState;
+use board_crab_lib::transpos;
+use std::time::{Duration, Instant};
+
+fn make_async_search_config(max_depth: Option<u8>, search_config: SearchConfig) -> AsyncSearchConfig<'static> {
+    AsyncSearchConfig {
+        max_depth,
+        stop_flag: None,
+        start_time: Instant::now(),
+        time_state: None,
+        ponderhit_time: None,
+        print_uci: false,
+        chess960: false,
+
+        search_config
+    }
+}
+
+fn find_move(board: &Board, move_str: &str) -> Move {
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+    moves.iter().copied().find(|mv| format!("{mv}") == move_str).unwrap()
+}
+
+#[test]
+fn search_limits_test() {
+    board_crab_lib::init();
+
+    let board = Board::start_pos();
+
+    // Node limit, without it this would take forever
+    let mut search_config = SearchConfig::new();
+    search_config.max_nodes = Some(5000);
+    let table = transpos::Table::new(4);
+    let result = do_search_thread(&board, &[], &table, &make_async_search_config(None, search_config));
+    assert!(result.best_move.is_some());
+    assert!(result.total_nodes < 10000);
+
+    // Search moves
+    let mut search_config = SearchConfig::new();
+    let a3 = find_move(&board, "a2a3");
+    let h4 = find_move(&board, "h2h4");
+    search_config.root_moves = vec![a3, h4];
+    let table = transpos::Table::new(4);
+    let result = do_search_thread(&board, &[], &table, &make_async_search_config(Some(4), search_config));
+    let (best_move, ponder_move) = (result.best_move, result.ponder_move);
+    assert_eq!(result.depth, 4);
+    assert!(best_move == Some(a3) || best_move == Some(h4));
+
+    // The ponder move is the reply from the PV
+    let mut next_board = board;
+    next_board.do_move(&best_move.unwrap());
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(&next_board, &mut moves);
+    assert!(moves.iter().any(|mv| Some(*mv) == ponder_move));
+
+    // Mate in 2, the depth limit is only there so that a failure doesn't run forever
+    let board = fen::load_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1").unwrap();
+    let mut search_config = SearchConfig::new();
+    search_config.mate_moves = Some(2);
+    let table = transpos::Table::new(4);
+    let start_time = Instant::now();
+    let result = do_search_thread(&board, &[], &table, &make_async_search_config(Some(30), search_config));
+    assert_eq!(result.eval, value_mate_in(3));
+    assert_eq!(result.best_move, Some(find_move(&board, "d5f6")));
+    assert!(start_time.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn async_engine_infinite_test() {
+    board_crab_lib::init();
+
+    // Even after finishing the search, we wait to be stopped
+    let mut engine = AsyncEngine::new(4);
+    engine.start_search(Some(2), None, 2, SearchConfig::new(), true);
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(engine.stop_search().is_some());
+
+    // A ponderhit starts a normal search, which then finishes on its own
+    engine.start_ponder(Some(2), None, 1, SearchConfig::new());
+    assert!(engine.is_pondering());
+    engine.ponderhit();
+    assert!(!engine.is_pondering());
+    assert!(engine.stop_search().is_some());
+
+    // The ponder search ignores its time limit until the ponderhit, and then keeps going until the limit runs out
+    let mut time_state = TimeState::new();
+    time_state.hard_max_time = Some(0.3);
+    engine.start_ponder(None, Some(time_state), 1, SearchConfig::new());
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(engine.is_searching());
+
+    let ponderhit_time = Instant::now();
+    engine.ponderhit();
+    while engine.is_searching() && ponderhit_time.elapsed() < Duration::from_secs(10) {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(!engine.is_searching());
+    assert!(ponderhit_time.elapsed() >= Duration::from_millis(300));
+    assert!(ponderhit_time.elapsed() < Duration::from_secs(2));
+    assert!(engine.stop_search().is_some());
+}
+
+#[test]
+fn vote_best_result_test() {
+    board_crab_lib::init();
+
+    let board = Board::start_pos();
+    let e4 = find_move(&board, "e2e4");
+    let d4 = find_move(&board, "d2d4");
+    let make_result = |best_move: Option<Move>, eval: Value, depth: u8| SearchThreadResult {
+        best_move,
+        eval,
+        ponder_move: None,
+        depth,
+        total_nodes: 0
+    };
+
+    assert!(vote_best_result(&[]).is_none());
+    assert!(vote_best_result(&[make_result(None, 0, 0)]).is_none());
+
+    // Two helpers outvote the main thread
+    let results = [make_result(Some(e4), 30, 10), make_result(Some(d4), 30, 10), make_result(Some(d4), 30, 9), make_result(None, 0, 0)];
+    let result = vote_best_result(&results).unwrap();
+    assert_eq!(result.best_move, Some(d4));
+    assert_eq!(result.depth, 10);
+
+    // A much deeper and better result wins alone
+    let results = [make_result(Some(e4), 150, 14), make_result(Some(d4), 30, 10), make_result(Some(d4), 20, 10)];
+    assert_eq!(vote_best_result(&results).unwrap().best_move, Some(e4));
+
+    // Ties go to the main thread
+    let results = [make_result(Some(e4), 30, 10), make_result(Some(d4), 30, 10)];
+    assert_eq!(vote_best_result(&results).unwrap().best_move, Some(e4));
+}
+
+#[test]
+fn smp_search_test() {
+    board_crab_lib::init();
+
+    let board = fen::load_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+    for num_threads in [1, 4] {
+        let table = transpos::Table::new(4);
+        let result = do_smp_search(&board, &[], &table, &make_async_search_config(Some(4), SearchConfig::new()), num_threads);
+        assert_eq!(result.depth, 4);
+        assert!(result.best_move.is_some());
+        assert!(result.total_nodes > 0);
+    }
+
+    // The node limit counts the nodes of every thread
+    let mut search_config = SearchConfig::new();
+    search_config.max_nodes = Some(5000);
+    let table = transpos::Table::new(4);
+    let result = do_smp_search(&board, &[], &table, &make_async_search_config(None, search_config), 4);
+    assert!(result.best_move.is_some());
+    assert!(result.total_nodes < 10000);
+
+    // Helpers stop along with the main thread, even without any limits of their own
+    let board = fen::load_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1").unwrap();
+    let mut search_config = SearchConfig::new();
+    search_config.mate_moves = Some(2);
+    let table = transpos::Table::new(4);
+    let result = do_smp_search(&board, &[], &table, &make_async_search_config(None, search_config), 4);
+    assert_eq!(result.best_move, Some(find_move(&board, "d5f6")));
+}
@@ -0,0 +1,133 @@
+use board_crab_lib::board::*;
+use board_crab_lib::book;
+use board_crab_lib::fen;
+
+fn make_raw_move(from_idx: u16, to_idx: u16, promotion: u16) -> u16 {
+    to_idx | (from_idx << 6) | (promotion << 12)
+}
+
+fn make_book_bytes(entries: &[(u64, u16, u16)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (key, raw_move, weight) in entries {
+        bytes.extend_from_slice(&key.to_be_bytes());
+        bytes.extend_from_slice(&raw_move.to_be_bytes());
+        bytes.extend_from_slice(&weight.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn book_decode_move_test() {
+    board_crab_lib::init();
+
+    let test_entries = [
+        (fen::FEN_START_POS, make_raw_move(12, 28, 0), Some("e2e4")),
+        (fen::FEN_START_POS, make_raw_move(12, 36, 0), None), // Illegal
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", make_raw_move(4, 7, 0), Some("e1g1")), // King takes rook
+        ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", make_raw_move(60, 56, 0), Some("e8c8")),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", make_raw_move(48, 56, 4), Some("a7a8q")),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", make_raw_move(48, 56, 1), Some("a7a8n")),
+        ("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", make_raw_move(48, 56, 0), None), // Missing promotion
+    ];
+
+    for (fen_str, raw_move, expected) in test_entries {
+        let board = fen::load_fen(fen_str).unwrap();
+        let mv = book::decode_move(&board, raw_move);
+        assert_eq!(mv.map(|mv| format!("{mv}")).as_deref(), expected, "Bad decode of {:#x} in \"{}\"", raw_move, fen_str);
+    }
+}
+
+#[test]
+fn book_lookup_test() {
+    board_crab_lib::init();
+
+    let start_key = 0x463b96181691fc9c;
+    let bytes = make_book_bytes(&[
+        (0x0000000000000001, make_raw_move(12, 28, 0), 100),
+        (start_key, make_raw_move(12, 28, 0), 10), // e2e4
+        (start_key, make_raw_move(11, 27, 0), 5), // d2d4
+        (start_key, make_raw_move(11, 35, 0), 50), // Illegal
+        (start_key, make_raw_move(6, 21, 0), 0), // g1f3, never played
+        (u64::MAX, make_raw_move(12, 28, 0), 100),
+    ]);
+
+    let book = book::Book::from_bytes(&bytes).unwrap();
+    assert_eq!(book.len(), 6);
+    assert!(book::Book::from_bytes(&bytes[1..]).is_err());
+
+    let board = Board::start_pos();
+    assert_eq!(book::polyglot_key(&board), start_key);
+    assert_eq!(book.get_moves(&board).len(), 3);
+    assert_eq!(book.get_best_move(&board).map(|mv| format!("{mv}")).as_deref(), Some("e2e4"));
+
+    for _i in 0..100 {
+        let mv_str = format!("{}", book.get_random_move(&board).unwrap());
+        assert!(mv_str == "e2e4" || mv_str == "d2d4", "Bad random book move {}", mv_str);
+    }
+
+    // Not in the book
+    let other_board = fen::load_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert!(book.get_moves(&other_board).is_empty());
+    assert!(book.get_random_move(&other_board).is_none());
+}
+
+#[test]
+fn book_builder_test() {
+    board_crab_lib::init();
+
+    let pgn_str = r#"
+[Result "1-0"]
+1. e4 e5 2. Nf3 Nc6 1-0
+
+[Result "1/2-1/2"]
+1. e4 e5 2. Nf3 Nc6 3. Bb5 1/2-1/2
+
+[Result "0-1"]
+1. e4 c5 2. Nf3 d6 0-1
+
+[Result "0-1"]
+1. d4 d5 2. c4 0-1
+
+[Result "*"]
+1. e4 e5 *
+"#;
+
+    let mut builder = book::BookBuilder::new(4);
+    for game in board_crab_lib::pgn::PgnReader::new(pgn_str.as_bytes()) {
+        builder.add_pgn_game(&game.unwrap());
+    }
+    assert_eq!(builder.num_games, 5);
+
+    // Statistics are from the perspective of the team that moved
+    let start_board = Board::start_pos();
+    let start_position = &builder.positions[&book::polyglot_key(&start_board)];
+    let e4_stats = start_position.moves[&make_raw_move(12, 28, 0)];
+    assert_eq!(e4_stats, book::MoveStats { count: 4, wins: 1, draws: 1, losses: 1 });
+    let d4_stats = start_position.moves[&make_raw_move(11, 27, 0)];
+    assert_eq!(d4_stats, book::MoveStats { count: 1, wins: 0, draws: 0, losses: 1 });
+
+    let e5_board = *board_crab_lib::pgn::load_pgn("1. e4 e5").unwrap().make_game().board();
+    let e5_position = &builder.positions[&book::polyglot_key(&e5_board)];
+    assert_eq!(e5_position.fen, "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6");
+    assert_eq!(e5_position.moves[&make_raw_move(6, 21, 0)], book::MoveStats { count: 2, wins: 1, draws: 1, losses: 0 });
+
+    // Nothing past the max ply
+    assert_eq!(builder.positions.len(), 8);
+
+    // Write and read back the book
+    let book = book::Book::from_bytes(&builder.make_bin(1)).unwrap();
+    let start_moves = book.get_moves(&start_board);
+    assert_eq!(start_moves.len(), 1); // d4 only lost, so it has no weight
+    assert_eq!(format!("{}", start_moves[0].0), "e2e4");
+    assert_eq!(start_moves[0].1, 3);
+    assert_eq!(format!("{}", book.get_best_move(&e5_board).unwrap()), "g1f3");
+    assert!(book::Book::from_bytes(&builder.make_bin(3)).unwrap().get_moves(&e5_board).is_empty());
+
+    // Positions reached after 4 plies
+    assert_eq!(builder.make_fen_list(1), [
+        "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -",
+        "rnbqkbnr/pp2pppp/3p4/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -",
+    ]);
+    assert_eq!(builder.make_fen_list(2), ["r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -"]);
+}
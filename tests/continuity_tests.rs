@@ -76,4 +76,88 @@ fn continuity_test_2() {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Plays a bunch of random games and makes sure make/unmake restores the board bit for bit
+#[test]
+fn continuity_test_3() {
+    board_crab_lib::init();
+
+    let mut rng = rand::rng();
+
+    const NUM_GAMES: usize = 200;
+    const MAX_MOVES_PER_GAME: usize = 60;
+    for _i in 0..NUM_GAMES {
+        let mut board = Board::start_pos();
+        for _j in 0..MAX_MOVES_PER_GAME {
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_moves(&board, &mut moves);
+
+            if moves.is_empty() {
+                break
+            }
+
+            for mv in moves.iter() {
+                let mut copy_made_board = board;
+                copy_made_board.do_move(mv);
+
+                let mut made_board = board;
+                let undo_info = made_board.make_move(mv);
+                if made_board != copy_made_board {
+                    panic!("Continuity error (make_move doesn't match do_move for {})", mv);
+                }
+
+                made_board.unmake_move(mv, &undo_info);
+                if made_board != board {
+                    panic!("Continuity error (unmake_move didn't restore the board for {})", mv);
+                }
+            }
+
+            if board.checkers == 0 {
+                let mut null_board = board;
+                let undo_info = null_board.make_null_move();
+                null_board.unmake_null_move(&undo_info);
+                if null_board != board {
+                    panic!("Continuity error (unmake_null_move didn't restore the board)");
+                }
+            }
+
+            // Play one of the moves
+            let move_idx = rng.random_range(0..moves.len());
+            board.do_move(&moves[move_idx]);
+        }
+    }
+}
+
+// Plays a bunch of random games and makes sure every move survives being packed and unpacked
+#[test]
+fn continuity_test_4() {
+    board_crab_lib::init();
+
+    let mut rng = rand::rng();
+
+    const NUM_GAMES: usize = 100;
+    const MAX_MOVES_PER_GAME: usize = 80;
+    for _i in 0..NUM_GAMES {
+        let mut board = Board::start_pos();
+        for _j in 0..MAX_MOVES_PER_GAME {
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_moves(&board, &mut moves);
+
+            if moves.is_empty() {
+                break
+            }
+
+            for mv in moves.iter() {
+                let packed_move = PackedMove::from_move(mv);
+                if packed_move.to_move(&board) != Some(*mv) {
+                    panic!("Continuity error (packed move {:?} doesn't unpack to {})", packed_move, mv);
+                }
+            }
+
+            // Play one of the moves
+            let move_idx = rng.random_range(0..moves.len());
+            board.do_move(&moves[move_idx]);
+        }
+    }
+}
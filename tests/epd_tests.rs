@@ -0,0 +1,56 @@
+use board_crab_lib::epd;
+use board_crab_lib::fen;
+use board_crab_lib::search::SearchConfig;
+
+#[test]
+fn epd_parse_test() {
+    board_crab_lib::init();
+
+    let entry = epd::load_epd(
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; am Qxg7+ Nxd5; id \"WAC.001\"; acd 12; c0 \"quoted; with semicolon\"; c9 last; hmvc 0;"
+    ).unwrap();
+
+    assert_eq!(fen::make_fen(&entry.board), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+    assert_eq!(entry.best_moves.iter().map(|mv| format!("{mv}")).collect::<Vec<_>>(), ["g3g6"]);
+    assert_eq!(entry.avoid_moves.iter().map(|mv| format!("{mv}")).collect::<Vec<_>>(), ["g3g7", "f6d5"]);
+    assert_eq!(entry.id.as_deref(), Some("WAC.001"));
+    assert_eq!(entry.analysis_depth, Some(12));
+    assert_eq!(entry.direct_mate, None);
+    assert_eq!(entry.comments[0].as_deref(), Some("quoted; with semicolon"));
+    assert_eq!(entry.comments[9].as_deref(), Some("last"));
+    assert_eq!(entry.other_ops, vec![("hmvc".to_string(), vec!["0".to_string()])]);
+
+    // Missing final semicolon and UCI notation moves
+    let entry = epd::load_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm a1a8; dm 1").unwrap();
+    assert_eq!(entry.best_moves.iter().map(|mv| format!("{mv}")).collect::<Vec<_>>(), ["a1a8"]);
+    assert_eq!(entry.direct_mate, Some(1));
+
+    let bad_epds = [
+        "6k1/5ppp/8/8/8/8/8/R5K1 w -", // Missing en passant field
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Rb9;",
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm;",
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - dm one;",
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - id \"unterminated;",
+    ];
+    for bad_epd in bad_epds {
+        assert!(epd::load_epd(bad_epd).is_err(), "EPD should have failed to load: {}", bad_epd);
+    }
+}
+
+#[test]
+fn epd_test_suite_test() {
+    board_crab_lib::init();
+
+    let entries = [
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; dm 1; id \"mate in 1\";",
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5Q2/PPPP1PPP/RNB1KBNR w KQkq - am Qxf7+; id \"no queen sac\";",
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - id \"nothing to test\";",
+        "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Kf1; id \"wrong\";",
+    ].map(|epd_str| epd::load_epd(epd_str).unwrap());
+
+    let results = epd::run_test_suite(&entries, Some(4), None, 4, &SearchConfig::new(), false);
+    assert_eq!(results.num_passed, 2);
+    assert_eq!(results.num_failed, 1);
+    assert_eq!(results.num_skipped, 1);
+    assert_eq!(results.failed_ids, ["wrong"]);
+}
@@ -0,0 +1,113 @@
+use board_crab_lib::board::*;
+use board_crab_lib::fen;
+use board_crab_lib::game::*;
+use board_crab_lib::move_gen;
+
+fn play_moves(game: &mut Game, move_strs: &[&str]) {
+    for move_str in move_strs {
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(game.board(), &mut moves);
+
+        let mv = *moves.iter().find(|mv| format!("{mv}") == *move_str)
+            .unwrap_or_else(|| panic!("Move {} not found in \"{}\"", move_str, fen::make_fen(game.board())));
+        game.do_move(&mv);
+    }
+}
+
+#[test]
+fn checkmate_test() {
+    board_crab_lib::init();
+
+    let mut game = Game::new(&Board::start_pos());
+    play_moves(&mut game, &["f2f3", "e7e5", "g2g4"]);
+    assert_eq!(game.get_result(), None);
+
+    play_moves(&mut game, &["d8h4"]);
+    assert_eq!(game.get_result(), Some(GameResult::Checkmate { winner_team_idx: 1 }));
+}
+
+#[test]
+fn stalemate_test() {
+    board_crab_lib::init();
+
+    let game = Game::new(&fen::load_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap());
+    assert_eq!(game.get_result(), Some(GameResult::Stalemate));
+}
+
+#[test]
+fn threefold_repetition_test() {
+    board_crab_lib::init();
+
+    let mut game = Game::new(&Board::start_pos());
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+    play_moves(&mut game, &shuffle);
+    assert_eq!(game.repetition_count(), 2);
+    assert_eq!(game.get_result(), None);
+
+    play_moves(&mut game, &shuffle);
+    assert_eq!(game.repetition_count(), 3);
+    assert_eq!(game.get_result(), Some(GameResult::ThreefoldRepetition));
+}
+
+#[test]
+fn fifty_move_rule_test() {
+    board_crab_lib::init();
+
+    // The half-move counter is in plies, so the rule kicks in at 100
+    let mut game = Game::new(&fen::load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 98 80").unwrap());
+    play_moves(&mut game, &["a1a2"]);
+    assert_eq!(game.get_result(), None);
+
+    play_moves(&mut game, &["e8d8"]);
+    assert_eq!(game.get_result(), Some(GameResult::FiftyMoveRule));
+
+    // Captures reset the counter
+    let mut game = Game::new(&fen::load_fen("3rk3/8/8/8/8/8/8/R2RK3 w - - 99 80").unwrap());
+    play_moves(&mut game, &["d1d8"]);
+    assert_eq!(game.board().half_move_counter, 0);
+    assert_eq!(game.get_result(), None);
+}
+
+#[test]
+fn full_move_counter_test() {
+    board_crab_lib::init();
+
+    // Only goes up after black moves, and is kept by FENs
+    let mut game = Game::new(&fen::load_fen("4k3/8/8/8/8/8/8/R3K3 b - - 5 40").unwrap());
+    play_moves(&mut game, &["e8d8"]);
+    assert_eq!(game.board().full_move_counter, 41);
+    play_moves(&mut game, &["a1a2"]);
+    assert_eq!(fen::make_fen(game.board()), "3k4/8/8/8/8/8/R7/4K3 b - - 7 41");
+
+    let mut board = *game.board();
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(&board, &mut moves);
+    let undo_info = board.make_move(&moves[0]);
+    assert_eq!(board.full_move_counter, 42);
+    board.unmake_move(&moves[0], &undo_info);
+    assert_eq!(board, *game.board());
+
+    assert_eq!(Board::start_pos().full_move_counter, 1);
+    assert!(fen::load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 x").is_err());
+}
+
+#[test]
+fn insufficient_material_test() {
+    board_crab_lib::init();
+
+    let test_entries = [
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true), // Kings only
+        ("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", true), // Single bishop
+        ("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1", true), // Single knight
+        ("4k3/8/8/4b3/8/8/8/2B1K3 w - - 0 1", true), // Same-colored bishops
+        ("4k3/8/8/3b4/8/8/8/2B1K3 w - - 0 1", false), // Opposite-colored bishops
+        ("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1", false), // Two knights can still mate if the opponent blunders
+        ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+    ];
+
+    for (fen_str, expected) in test_entries {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert_eq!(is_insufficient_material(&board), expected, "Wrong insufficient material result for \"{}\"", fen_str);
+    }
+}
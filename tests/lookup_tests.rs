@@ -0,0 +1,47 @@
+use board_crab_lib::bitmask::*;
+use board_crab_lib::board::*;
+use board_crab_lib::lookup_gen;
+use rand::{Rng, SeedableRng};
+
+// Checks every slider lookup backend against the slow version
+#[test]
+fn slider_tos_test() {
+    board_crab_lib::init();
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    for pos_idx in 0..64 {
+        let piece_pos = bm_from_idx(pos_idx);
+        for i in 0..1000 {
+            // Mix sparse and dense occupancies
+            let occupy: BitMask = match i % 3 {
+                0 => rng.random::<u64>() & rng.random::<u64>() & rng.random::<u64>(),
+                1 => rng.random::<u64>(),
+                _ => rng.random::<u64>() | rng.random::<u64>()
+            } | piece_pos;
+
+            for piece_idx in [PIECE_BISHOP, PIECE_ROOK, PIECE_QUEEN] {
+                let expected = lookup_gen::get_slider_tos_slow(piece_idx, pos_idx, occupy & !piece_pos);
+                let backend_results = vec![
+                    ("default", lookup_gen::get_piece_tos(piece_idx, piece_pos, pos_idx, occupy)),
+                    ("magic", lookup_gen::get_slider_tos_magic(piece_idx, pos_idx, occupy & !piece_pos))
+                ];
+
+                #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+                let backend_results = {
+                    let mut backend_results = backend_results;
+                    if board_crab_lib::lookup_gen_pext::is_enabled() {
+                        backend_results.extend([("pext", lookup_gen::get_slider_tos_pext(piece_idx, pos_idx, occupy & !piece_pos))]);
+                    }
+                    backend_results
+                };
+
+                for (backend_name, result) in backend_results {
+                    assert_eq!(
+                        result, expected, "Bad {} {} moves from {} with occupancy {:#018x}",
+                        backend_name, PIECE_CHARS[piece_idx], bm_to_coord(piece_pos), occupy
+                    );
+                }
+            }
+        }
+    }
+}
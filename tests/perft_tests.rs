@@ -1,6 +1,54 @@
+use board_crab_lib::board::*;
+use board_crab_lib::epd;
 use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::perft;
+use board_crab_lib::perft::PerftConfig;
 use board_crab_lib::search;
 
+// Perft that only uses the staged move generators
+// At every node, checks that they split up the full set of legal moves exactly
+fn staged_perft(board: &Board, depth: u8) -> usize {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut all_moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut all_moves);
+
+    let mut staged_moves = move_gen::MoveBuffer::new();
+    if board.checkers != 0 {
+        move_gen::generate_evasions(board, &mut staged_moves);
+    } else {
+        let mut captures = move_gen::MoveBuffer::new();
+        move_gen::generate_captures(board, &mut captures);
+        let mut quiets = move_gen::MoveBuffer::new();
+        move_gen::generate_quiets(board, &mut quiets);
+
+        for mv in captures.iter() {
+            assert!(!mv.is_quiet(), "generate_captures() gave quiet move {}", mv);
+            staged_moves.push(*mv);
+        }
+        for mv in quiets.iter() {
+            assert!(mv.is_quiet(), "generate_quiets() gave loud move {}", mv);
+            staged_moves.push(*mv);
+        }
+    }
+
+    assert_eq!(staged_moves.len(), all_moves.len(), "Staged move count mismatch in \"{}\"", fen::make_fen(board));
+    for mv in staged_moves.iter() {
+        assert!(all_moves.iter().any(|omv| omv == mv), "Staged move {} is not legal in \"{}\"", mv, fen::make_fen(board));
+    }
+
+    let mut total = 0;
+    for mv in staged_moves.iter() {
+        let mut next_board = *board;
+        next_board.do_move(mv);
+        total += staged_perft(&next_board, depth - 1);
+    }
+    total
+}
+
 fn do_test(name: &str, position_fen: &str, depth: usize, target_node_count: usize) {
     board_crab_lib::init();
     let board = fen::load_fen(position_fen).unwrap();
@@ -13,6 +61,22 @@ fn do_test(name: &str, position_fen: &str, depth: usize, target_node_count: usiz
             name, depth, perft_count, target_node_count, position_fen
         );
     }
+
+    let make_unmake_perft_count = search::perft_make_unmake(&board, depth as u8, false);
+    if make_unmake_perft_count != perft_count {
+        panic!(
+            "Make/unmake perft mismatch for position \"{}\" at depth {} (got: {}, copy-make got: {}), fen: \"{}\"",
+            name, depth, make_unmake_perft_count, perft_count, position_fen
+        );
+    }
+
+    let staged_perft_count = staged_perft(&board, depth as u8);
+    if staged_perft_count != perft_count {
+        panic!(
+            "Staged perft mismatch for position \"{}\" at depth {} (got: {}, full generation got: {}), fen: \"{}\"",
+            name, depth, staged_perft_count, perft_count, position_fen
+        );
+    }
 }
 
 #[test]
@@ -85,4 +149,125 @@ fn super_perft_test() {
             do_test(name, fen_str, depth, target_perft_results[i]);
         }
     }
-}
\ No newline at end of file
+}
+
+// Chess960 positions from https://www.chessprogramming.org/Chess960_Perft_Results
+#[test]
+fn chess960_perft_test() {
+    let test_entries = [
+        ("960 #1 (Shredder-FEN)", "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", vec![21, 528, 12189]),
+        ("960 #1 (X-FEN)", "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", vec![21, 528, 12189]),
+        ("960 #2", "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", vec![21, 807, 18002]),
+        ("960 #3", "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", vec![20, 479, 10471]),
+        ("960 #4", "1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", vec![28, 1120, 31058]),
+    ];
+
+    for pair in test_entries {
+        let name = pair.0;
+        let fen_str = pair.1;
+        let target_perft_results = pair.2;
+
+        for i in 0..target_perft_results.len() {
+            let depth = i + 1;
+            do_test(name, fen_str, depth, target_perft_results[i]);
+        }
+    }
+}
+
+// Stats from https://www.chessprogramming.org/Perft_Results
+// Each row is nodes, captures, en passants, castles, promotions, checks, discovered checks, double checks, checkmates
+#[test]
+fn perft_stats_test() {
+    board_crab_lib::init();
+
+    let test_entries = [
+        ("Initial position", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", vec![
+            [20, 0, 0, 0, 0, 0, 0, 0, 0],
+            [400, 0, 0, 0, 0, 0, 0, 0, 0],
+            [8902, 34, 0, 0, 0, 12, 0, 0, 0],
+            [197281, 1576, 0, 0, 0, 469, 0, 0, 8]
+        ]),
+        ("Complex 1 (Kiwipete)", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", vec![
+            [48, 8, 0, 2, 0, 0, 0, 0, 0],
+            [2039, 351, 1, 91, 0, 3, 0, 0, 0],
+            [97862, 17102, 45, 3162, 0, 993, 0, 0, 1]
+        ]),
+        ("Rooks and En Passant", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", vec![
+            [14, 1, 0, 0, 0, 2, 0, 0, 0],
+            [191, 14, 0, 0, 0, 10, 0, 0, 0],
+            [2812, 209, 2, 0, 0, 267, 3, 0, 0],
+            [43238, 3348, 123, 0, 0, 1680, 106, 0, 17]
+        ])
+    ];
+
+    for (name, fen_str, target_depth_stats) in test_entries {
+        let board = fen::load_fen(fen_str).unwrap();
+        let mut config = PerftConfig::new();
+        config.collect_stats = true;
+        config.num_threads = 4;
+        let result = perft::run(&board, target_depth_stats.len() as u8, &config);
+
+        for (i, target) in target_depth_stats.iter().enumerate() {
+            let stats = result.depth_stats[i];
+            let stats_row = [
+                stats.nodes, stats.captures, stats.en_passants, stats.castles, stats.promotions,
+                stats.checks, stats.discovered_checks, stats.double_checks, stats.checkmates
+            ];
+            assert_eq!(&stats_row, target, "Bad stats for position \"{}\" at depth {}", name, i + 1);
+        }
+        assert_eq!(result.nodes, target_depth_stats.last().unwrap()[0]);
+    }
+}
+
+// Every perft configuration has to agree with the simple perft, including at each root move
+#[test]
+fn perft_config_test() {
+    board_crab_lib::init();
+
+    let test_entries = [
+        ("Complex 2 (Talkchess)", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 3),
+        ("Rooks and En Passant", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", 4),
+        ("960 #2", "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 3),
+        ("Checkmated", "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3", 2)
+    ];
+
+    for (name, fen_str, depth) in test_entries {
+        let board = fen::load_fen(fen_str).unwrap();
+        let target_nodes = search::perft(&board, depth, false);
+
+        for (bulk_counting, hash_size_mbs, num_threads) in [(true, 0, 1), (false, 0, 1), (true, 1, 1), (true, 0, 4), (false, 1, 4)] {
+            let mut config = PerftConfig::new();
+            config.bulk_counting = bulk_counting;
+            config.hash_size_mbs = hash_size_mbs;
+            config.num_threads = num_threads;
+            let result = perft::run(&board, depth, &config);
+            assert_eq!(result.nodes, target_nodes, "Bad perft for position \"{}\" with {:?}", name, config);
+
+            for (mv, nodes) in &result.divide {
+                let mut next_board = board;
+                next_board.do_move(mv);
+                assert_eq!(*nodes, search::perft(&next_board, depth - 1, false), "Bad divide for {} in position \"{}\"", mv, name);
+            }
+        }
+    }
+}
+
+#[test]
+fn perft_suite_test() {
+    board_crab_lib::init();
+
+    let entries = epd::load_epd_file("data/perftsuite.epd").unwrap();
+    assert_eq!(entries[0].perft_counts[..3], [(1, 20), (2, 400), (3, 8902)]);
+
+    let results = perft::run_test_suite(&entries, 3, 4, false);
+    assert_eq!(results.num_passed, entries.len());
+    assert_eq!(results.num_failed, 0);
+
+    // A wrong count has to fail at that depth, with a divide of the root moves
+    let bad_entry = epd::load_epd("4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 67 ;D3 1197").unwrap();
+    let results = perft::run_test_suite(&[bad_entry], 3, 1, false);
+    assert_eq!(results.num_failed, 1);
+    let failure = &results.failures[0];
+    assert_eq!((failure.depth, failure.nodes, failure.expected_nodes), (2, 66, 67));
+    assert_eq!(failure.divide.len(), 15);
+}
@@ -0,0 +1,244 @@
+use rand::Rng;
+use board_crab_lib::board::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::pgn;
+extern crate rand;
+
+fn parse_to_uci(fen_str: &str, move_str: &str) -> Option<String> {
+    let board = fen::load_fen(fen_str).unwrap();
+    pgn::parse_algebraic(&board, move_str).ok().map(|mv| format!("{mv}"))
+}
+
+#[test]
+fn parse_algebraic_test() {
+    board_crab_lib::init();
+
+    let test_entries = [
+        (fen::FEN_START_POS, "e4", Some("e2e4")),
+        (fen::FEN_START_POS, "Nf3", Some("g1f3")),
+        (fen::FEN_START_POS, "Nf3!?", Some("g1f3")),
+        (fen::FEN_START_POS, "e5", None), // Illegal
+        (fen::FEN_START_POS, "Ke2", None), // Illegal
+        (fen::FEN_START_POS, "xyz", None), // Nonsense
+
+        // Disambiguation
+        ("4k3/3p4/8/2N1N3/8/8/8/4K3 w - - 0 1", "Ncxd7", Some("c5d7")),
+        ("4k3/3p4/8/2N1N3/8/8/8/4K3 w - - 0 1", "Nxd7", None), // Ambiguous
+        ("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "Rd1", None), // Ambiguous
+        ("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "Rad1", Some("a1d1")),
+        ("4k3/8/8/8/8/R7/8/R3K3 w - - 0 1", "R1a2", Some("a1a2")),
+        ("4k3/8/8/8/8/R7/8/R3K3 w - - 0 1", "Ra2", None), // Ambiguous
+        ("4k3/8/8/8/Q6Q/8/8/Q3K3 w - - 0 1", "Qa1d4", Some("a1d4")),
+
+        // Castling
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O", Some("e1g1")),
+        ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "0-0-0", Some("e1c1")),
+        ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "O-O-O+", Some("e8c8")),
+        ("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1", "O-O", None), // No rights
+
+        // Promotions
+        ("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c8=Q", Some("c7c8q")),
+        ("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c8N", Some("c7c8n")),
+        ("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "cxd8=R+", Some("c7d8r")),
+        ("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "c8", None), // Missing promotion piece
+        ("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1", "Nc8=Q", None),
+
+        // En passant
+        ("4k3/8/8/3Pp3/8/8/8/4K3 w - e6 0 1", "dxe6", Some("d5e6")),
+    ];
+
+    for (fen_str, move_str, expected) in test_entries {
+        assert_eq!(
+            parse_to_uci(fen_str, move_str).as_deref(), expected,
+            "Wrong parse of \"{}\" in \"{}\"", move_str, fen_str
+        );
+    }
+}
+
+// Plays a bunch of random games and makes sure every move survives being written and read as algebraic notation
+#[test]
+fn algebraic_round_trip_test() {
+    board_crab_lib::init();
+
+    let mut rng = rand::rng();
+
+    const NUM_GAMES: usize = 50;
+    const MAX_MOVES_PER_GAME: usize = 100;
+    for _i in 0..NUM_GAMES {
+        let mut board = Board::start_pos();
+        for _j in 0..MAX_MOVES_PER_GAME {
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_moves(&board, &mut moves);
+
+            if moves.is_empty() {
+                break
+            }
+
+            for mv in moves.iter() {
+                let move_str = pgn::move_to_algebraic_str(&board, mv).unwrap();
+                let parsed_move = pgn::parse_algebraic(&board, &move_str).unwrap();
+                assert_eq!(parsed_move, *mv, "Algebraic round trip failed for \"{}\"", move_str);
+            }
+
+            let move_idx = rng.random_range(0..moves.len());
+            board.do_move(&moves[move_idx]);
+        }
+    }
+}
+
+const MULTI_GAME_PGN: &str = r#"[Event "Test \"One\""]
+[Site "?"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3 $1 Nc6 (2... d6 {Philidor} 3. d4 (3. Bc4 Be7) exd4)
+3. Bb5!? a6 ; Morphy defense
+4. Ba4 {multi
+line comment} Nf6 1-0
+
+[Event "Broken"]
+[Result "*"]
+
+1. e4 e5 2. Ke3 Nc6 *
+
+[Event "From position"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1"]
+
+1... Kd7 2.e4 Ke6 3.Kf2 1/2-1/2
+"#;
+
+#[test]
+fn pgn_reader_test() {
+    board_crab_lib::init();
+
+    let games: Vec<_> = pgn::PgnReader::new(MULTI_GAME_PGN.as_bytes()).collect();
+    assert_eq!(games.len(), 3);
+
+    // First game
+    {
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.get_tag("Event"), Some("Test \"One\""));
+        assert_eq!(game.get_tag("Black"), Some("B"));
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.comment.as_deref(), Some("Opening comment"));
+        assert_eq!(pgn::make_pgn(&game.start_board, &game.moves).unwrap(), "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6");
+
+        let nodes = &game.nodes;
+        assert_eq!(nodes[2].nags, vec![1]);
+        assert_eq!(nodes[4].nags, vec![5]);
+        assert_eq!(nodes[5].comment.as_deref(), Some("Morphy defense"));
+        assert_eq!(nodes[6].comment.as_deref(), Some("multi line comment"));
+
+        // Variation replacing 2... Nc6
+        assert_eq!(nodes[3].variations.len(), 1);
+        let variation = &nodes[3].variations[0];
+        assert_eq!(variation.iter().map(|node| format!("{}", node.mv)).collect::<Vec<_>>(), ["d7d6", "d2d4", "e5d4"]);
+        assert_eq!(variation[0].comment.as_deref(), Some("Philidor"));
+
+        // Nested variation replacing 3. d4
+        assert_eq!(variation[1].variations.len(), 1);
+        assert_eq!(variation[1].variations[0].iter().map(|node| format!("{}", node.mv)).collect::<Vec<_>>(), ["f1c4", "f8e7"]);
+    }
+
+    // Second game has an illegal move, but shouldn't stop the third from being read
+    assert!(games[1].is_err());
+
+    // Third game
+    {
+        let game = games[2].as_ref().unwrap();
+        assert_eq!(fen::make_fen(&game.start_board), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 1");
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.result, "1/2-1/2");
+        assert!(fen::make_fen(game.make_game().board()).starts_with("8/8/4k3/8/4P3/8/5K2/8 b - -"));
+    }
+}
+
+#[test]
+fn pgn_reader_malformed_test() {
+    board_crab_lib::init();
+
+    let bad_pgns = [
+        "[Event \"Unterminated\"\n\n1. e4 *",
+        "1. e4 {unterminated comment *",
+        "1. e4 (1. d4 *",
+        "1. e4 e5) *",
+        "(1. e4) *",
+        "1. e4 e5 1-0 2. Nf3",
+        "[FEN \"not a fen\"]\n\n1. e4 *",
+    ];
+
+    for bad_pgn in bad_pgns {
+        assert!(pgn::load_pgn(bad_pgn).is_err(), "PGN should have failed to load: {}", bad_pgn);
+    }
+
+    // A single game without tags or a result is still fine
+    let game = pgn::load_pgn("1.e4 e5 2.Nf3").unwrap();
+    assert_eq!(game.moves.len(), 3);
+    assert_eq!(game.result, "*");
+}
+
+#[test]
+fn pgn_writer_test() {
+    board_crab_lib::init();
+
+    let mut game = pgn::PgnGame::new(&Board::start_pos());
+    game.set_tag("White", "BoardCrab \"new\"");
+    game.set_tag("TimeControl", "20+0");
+    game.result = "0-1".to_string();
+    for move_str in ["f3", "e5", "g4", "Qh4#"] {
+        let board = *game.make_game().board();
+        game.add_move(pgn::parse_algebraic(&board, move_str).unwrap(), Some("+0.34/12 0.8s".to_string()));
+    }
+    game.nodes[1].nags.push(1);
+
+    assert_eq!(
+        pgn::write_pgn(&game).unwrap(),
+        concat!(
+            "[Event \"?\"]\n",
+            "[Site \"?\"]\n",
+            "[Date \"????.??.??\"]\n",
+            "[Round \"?\"]\n",
+            "[White \"BoardCrab \\\"new\\\"\"]\n",
+            "[Black \"?\"]\n",
+            "[Result \"0-1\"]\n",
+            "[TimeControl \"20+0\"]\n",
+            "\n",
+            "1. f3 {+0.34/12 0.8s} 1... e5 $1 {+0.34/12 0.8s} 2. g4 {+0.34/12 0.8s} 2... Qh4#\n",
+            "{+0.34/12 0.8s} 0-1\n"
+        )
+    );
+
+    // Move numbers come from the starting position, even without a FEN tag
+    let start_board = fen::load_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+    let mut game = pgn::PgnGame::new(&start_board);
+    for move_str in ["Kd7", "e4"] {
+        let board = *game.make_game().board();
+        game.add_move(pgn::parse_algebraic(&board, move_str).unwrap(), None);
+    }
+    let pgn_str = pgn::write_pgn(&game).unwrap();
+    assert!(pgn_str.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n"));
+    assert!(pgn_str.ends_with("\n40... Kd7 41. e4 *\n"), "Bad move numbers in \"{}\"", pgn_str);
+    assert_eq!(pgn::make_pgn(&start_board, &game.moves).unwrap().lines().last(), Some("40... Kd7 41. e4"));
+}
+
+#[test]
+fn pgn_write_read_test() {
+    board_crab_lib::init();
+
+    let games: Vec<_> = pgn::PgnReader::new(MULTI_GAME_PGN.as_bytes()).filter_map(|game| game.ok()).collect();
+    for game in games {
+        let pgn_str = pgn::write_pgn(&game).unwrap();
+        for line in pgn_str.lines() {
+            assert!(line.len() <= pgn::MAX_LINE_LENGTH, "Line is too long: \"{}\"", line);
+        }
+
+        // Writing what we read should give the same text
+        let read_game = pgn::load_pgn(&pgn_str).unwrap();
+        assert_eq!(pgn::write_pgn(&read_game).unwrap(), pgn_str);
+        assert_eq!(read_game.moves, game.moves);
+        assert_eq!(fen::make_fen(&read_game.start_board), fen::make_fen(&game.start_board));
+    }
+}
@@ -1,6 +1,6 @@
-use board_crab_lib::eval::Value;
 use board_crab_lib::fen;
 use board_crab_lib::search;
+use board_crab_lib::search::SearchConfig;
 use board_crab_lib::transpos;
 use board_crab_lib::thread_flag::ThreadFlag;
 extern crate rand;
@@ -14,7 +14,7 @@ fn search_consistency_test() {
 
     let fens= include_str!("../data/gm_fen_positions.txt").split('\n').collect::<Vec<&str>>();
 
-    let mut table = transpos::Table::new(4); // Small for low depth
+    let table = transpos::Table::new(4); // Small for low depth
 
     let mut total_move_matches: usize = 0;
     let mut total_positions: usize = 0;
@@ -25,8 +25,9 @@ fn search_consistency_test() {
         }
 
         let board = fen::load_fen(cur_fen).unwrap();
-        let best_move_a = search::search(&board, &mut table, MAX_DEPTH - 1, None, None, None).1.root_best_move_idx;
-        let best_move_b = search::search(&board, &mut table, MAX_DEPTH, None, None, None).1.root_best_move_idx;
+        let search_config = SearchConfig::new();
+        let best_move_a = search::search(&board, &[], &table, &search_config, MAX_DEPTH - 1, None, None, None).1.root_best_move;
+        let best_move_b = search::search(&board, &[], &table, &search_config, MAX_DEPTH, None, None, None).1.root_best_move;
 
         if best_move_a == best_move_b {
             total_move_matches += 1;
@@ -34,7 +35,7 @@ fn search_consistency_test() {
         total_positions += 1;
     }
 
-    let consistent_frac = (total_move_matches as Value) / (total_positions as Value);
+    let consistent_frac = (total_move_matches as f32) / (total_positions as f32);
     println!("Search consistency: {}%", consistent_frac * 100.0);
 
     if consistent_frac < 0.3 {
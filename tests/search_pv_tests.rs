@@ -0,0 +1,166 @@
+use board_crab_lib::board::*;
+use board_crab_lib::eval::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::search;
+use board_crab_lib::search::SearchConfig;
+use board_crab_lib::transpos;
+
+// Plays out the PV, making sure every move is legal, and returns the final board
+fn play_pv(board: &Board, pv: &[Move]) -> Board {
+    let mut board = *board;
+    let mut moves = move_gen::MoveBuffer::new();
+    for mv in pv {
+        moves.clear();
+        move_gen::generate_moves(&board, &mut moves);
+        assert!(moves.iter().any(|legal_mv| legal_mv == mv), "Illegal PV move {} in \"{}\"", mv, fen::make_fen(&board));
+        board.do_move(mv);
+    }
+    board
+}
+
+#[test]
+fn search_pv_test() {
+    board_crab_lib::init();
+
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"
+    ];
+
+    // The same table is used for every depth, like in iterative deepening
+    let table = transpos::Table::new(4);
+    for fen_str in fens {
+        let board = fen::load_fen(fen_str).unwrap();
+        for depth in 1..=5 {
+            let (_, search_info) = search::search(&board, &[], &table, &SearchConfig::new(), depth, None, None, None);
+            let pv = search_info.get_pv();
+            assert!(!pv.is_empty(), "Empty PV at depth {} for \"{}\"", depth, fen_str);
+            assert_eq!(Some(pv[0]), search_info.root_best_move);
+            play_pv(&board, pv);
+        }
+    }
+}
+
+#[test]
+fn search_pv_mate_test() {
+    board_crab_lib::init();
+
+    let test_positions = [
+        ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 1), // Back rank mate
+        ("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1", 3), // Nf6+ gxf6 Bxf7#
+        ("k7/8/1K6/8/8/8/8/7R b - - 0 1", 2) // Getting mated
+    ];
+
+    for (fen_str, mate_plies) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        let table = transpos::Table::new(4);
+        let (eval, search_info) = search::search(&board, &[], &table, &SearchConfig::new(), 4, None, None, None);
+        assert_eq!(get_mate_plies(eval.unwrap()).map(i32::abs), Some(mate_plies as i32), "Bad mate score for \"{}\"", fen_str);
+
+        // The PV has to reach the mate that the score reports
+        let pv = search_info.get_pv();
+        assert_eq!(pv.len(), mate_plies, "Incomplete PV for \"{}\"", fen_str);
+
+        let final_board = play_pv(&board, pv);
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(&final_board, &mut moves);
+        assert!(moves.is_empty() && final_board.checkers != 0, "PV doesn't end in mate for \"{}\"", fen_str);
+    }
+}
+
+#[test]
+fn search_mate_table_test() {
+    board_crab_lib::init();
+
+    let test_positions = [
+        ("k7/8/2K5/8/8/8/8/1Q6 w - - 0 1", value_mate_in(1)),
+        ("8/8/8/8/8/2k5/8/K1q5 w - - 0 1", value_mated_in(2))
+    ];
+
+    // Like in iterative deepening, the deeper searches find mates from the table at other plies, which shouldn't change the mate distance
+    for (fen_str, mate_eval) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        let table = transpos::Table::new(4);
+        for depth in 3..=7 {
+            let (eval, _) = search::search(&board, &[], &table, &SearchConfig::new(), depth, None, None, None);
+            assert_eq!(eval, Some(mate_eval), "Bad mate score at depth {} for \"{}\"", depth, fen_str);
+        }
+    }
+}
+
+#[test]
+fn search_multi_pv_test() {
+    board_crab_lib::init();
+
+    let board = fen::load_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    let mut search_config = SearchConfig::new();
+    search_config.multi_pv = 4;
+    let table = transpos::Table::new(4);
+    let results = search::search_multi_pv(&board, &[], &table, &search_config, 4, &[], None, None).unwrap();
+    assert_eq!(results.len(), 4);
+
+    // Only the first move mates
+    assert_eq!(results[0].0, value_mate_in(1));
+    assert_eq!(fen::make_fen(&play_pv(&board, results[0].1.get_pv())), "3R2k1/5ppp/8/8/8/8/5PPP/6K1 b - - 1 1");
+
+    let mut best_moves = Vec::new();
+    for (i, (eval, search_info)) in results.iter().enumerate() {
+        let best_move = search_info.root_best_move.unwrap();
+        assert!(!best_moves.contains(&best_move), "Repeated best move {}", best_move);
+        best_moves.push(best_move);
+
+        assert_eq!(search_info.get_pv()[0], best_move);
+        play_pv(&board, search_info.get_pv());
+        if i > 0 {
+            assert!(*eval < VALUE_CHECKMATE_MIN);
+            assert!(*eval <= results[i - 1].0);
+            assert_eq!(search_info.total_nodes, results[0].1.total_nodes);
+        }
+    }
+
+    // Only one legal move
+    let board = fen::load_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+    let results = search::search_multi_pv(&board, &[], &table, &search_config, 4, &[], None, None).unwrap();
+    assert_eq!(results.len(), 1);
+
+    // Checkmated, so there are no moves at all
+    let board = fen::load_fen("R5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+    let results = search::search_multi_pv(&board, &[], &table, &search_config, 4, &[], None, None).unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, -VALUE_CHECKMATE);
+    assert!(results[0].1.root_best_move.is_none());
+}
+
+#[test]
+fn search_pvs_test() {
+    board_crab_lib::init();
+
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1"
+    ];
+
+    // Without any pruning, the null window searches can't change the eval, only how we get to it
+    let mut search_config = SearchConfig::new();
+    search_config.null_move_pruning = false;
+    search_config.futility_pruning = false;
+    search_config.late_move_reduction_factor = 0.0;
+    let mut pvs_search_config = search_config.clone();
+    search_config.principal_variation_search = false;
+    pvs_search_config.principal_variation_search = true;
+
+    for fen_str in fens {
+        let board = fen::load_fen(fen_str).unwrap();
+        for depth in 1..=4 {
+            let (eval, _) = search::search(&board, &[], &transpos::Table::new(4), &search_config, depth, None, None, None);
+            let (pvs_eval, pvs_search_info) = search::search(&board, &[], &transpos::Table::new(4), &pvs_search_config, depth, None, None, None);
+            assert_eq!(pvs_eval, eval, "Different eval at depth {} for \"{}\"", depth, fen_str);
+            assert_eq!(Some(pvs_search_info.get_pv()[0]), pvs_search_info.root_best_move);
+            play_pv(&board, pvs_search_info.get_pv());
+        }
+    }
+}
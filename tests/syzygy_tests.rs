@@ -0,0 +1,361 @@
+use board_crab_lib::bitmask::*;
+use board_crab_lib::board::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::search;
+use board_crab_lib::syzygy::*;
+use board_crab_lib::transpos;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Makes a KQvK WDL table where every position with white to move is a win, and every position with black to move is a loss
+// This is far from correct, but lets us test everything around the decompression
+fn make_single_value_kqvk_bytes() -> Vec<u8> {
+    let mut bytes = vec![
+        0x71, 0xE8, 0x23, 0x5D, // Magic
+        0x01, // Split (different pieces per side), no pawns
+        0x00, // Group order
+        0x66, 0x55, 0xEE, // Pieces for each side: K, Q, k
+        0x00, // Alignment
+        0x80, (WDL_WIN + 2) as u8, // White to move: single value
+        0x80, (WDL_LOSS + 2) as u8 // Black to move: single value
+    ];
+    bytes.resize(64, 0);
+    bytes
+}
+
+// Tables are only read when first probed, so the directory should be removed at the end of the test
+// Tests run in parallel, so each one needs its own directory
+fn make_test_tablebase(test_name: &str) -> (Tablebase, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("board_crab_{}_{}", test_name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("KQvK.rtbw"), make_single_value_kqvk_bytes()).unwrap();
+    std::fs::write(dir.join("readme.txt"), "Not a table").unwrap();
+
+    (Tablebase::new(dir.to_str().unwrap()), dir)
+}
+
+#[test]
+fn syzygy_single_value_test() {
+    board_crab_lib::init();
+
+    let tablebase = Tablebase::new("/this/path/does/not/exist");
+    assert_eq!(tablebase.num_wdl_tables(), 0);
+    assert_eq!(tablebase.max_pieces(), 0);
+    let board = fen::load_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1").unwrap();
+    assert_eq!(tablebase.probe_wdl(&board), None);
+    assert!(!tablebase.can_probe(&board));
+
+    let (tablebase, dir) = make_test_tablebase("syzygy_single_value_test");
+    assert_eq!(tablebase.table_names(), vec!["KQvK".to_string()]);
+    assert_eq!(tablebase.num_dtz_tables(), 0);
+    assert_eq!(tablebase.max_pieces(), 3);
+
+    let test_positions = [
+        ("4k3/8/8/8/8/8/8/4K2Q w - - 0 1", Some(WDL_WIN)),
+        ("4k3/8/8/8/8/8/8/4K2Q b - - 0 1", Some(WDL_LOSS)),
+        ("8/8/8/8/8/8/3kQ3/K7 b - - 0 1", Some(WDL_DRAW)), // Black takes the queen
+        ("4k3/8/8/8/8/8/q7/7K w - - 0 1", Some(WDL_LOSS)), // Black has the queen
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", Some(WDL_DRAW)), // King vs king never needs a table
+        ("4k3/8/8/8/8/8/8/3RK3 w - - 0 1", None) // Missing table
+    ];
+
+    for (fen_str, expected) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert_eq!(tablebase.probe_wdl(&board), expected, "Bad WDL for \"{}\"", fen_str);
+    }
+
+    // No tables for castling positions
+    let board = fen::load_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+    assert!(!tablebase.can_probe(&board));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn syzygy_search_test() {
+    board_crab_lib::init();
+
+    let (tablebase, dir) = make_test_tablebase("syzygy_search_test");
+    let tablebase = Arc::new(tablebase);
+
+    // Any move that leaves the queen next to the black king draws
+    let board = fen::load_fen("8/8/8/4k3/8/3Q4/8/K7 w - - 0 1").unwrap();
+    let black_king_area = move_gen::generate_attacks(&board, 1, PIECE_KING, board.pieces[1][PIECE_KING]);
+    let root_moves = tablebase.filter_root_moves(&board, &[]).unwrap();
+
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(&board, &mut moves);
+    for mv in moves.iter() {
+        let hangs_queen = mv.from_piece_idx == PIECE_QUEEN && (mv.to & black_king_area) != 0;
+        assert_eq!(root_moves.contains(mv), !hangs_queen, "Bad root move filtering for {} ({})", mv, bm_to_coord(mv.to));
+    }
+
+    let mut search_config = search::SearchConfig::new();
+    search_config.tablebase = Some(tablebase.clone());
+    search_config.root_moves = root_moves.clone();
+    let table = transpos::Table::new(4);
+    let (_, search_info) = search::search(&board, &[], &table, &search_config, 4, None, None, None);
+    assert!(root_moves.contains(&search_info.root_best_move.unwrap()));
+    assert!(search_info.tb_hits > 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// 3 piece tables in the standard format, made by data/syzygy/generate_tables.py
+// These check the results and the decoder's consistency, but were encoded from the same reading of the format as the decoder
+fn data_tablebase() -> Tablebase {
+    let tablebase = Tablebase::new(concat!(env!("CARGO_MANIFEST_DIR"), "/data/syzygy"));
+    assert_eq!(tablebase.num_wdl_tables(), 5);
+    assert_eq!(tablebase.num_dtz_tables(), 3);
+    tablebase
+}
+
+// Makes a FEN with the given pieces (like 'K' or 'q') on the given squares
+fn make_fen(pieces: &[(char, usize)], turn: char) -> String {
+    let mut fen_str = String::new();
+    for y in (0..8).rev() {
+        let mut empty = 0;
+        for x in 0..8 {
+            match pieces.iter().find(|(_, sq)| *sq == x + y * 8) {
+                Some((ch, _)) => {
+                    if empty > 0 {
+                        fen_str += &empty.to_string();
+                        empty = 0;
+                    }
+                    fen_str.push(*ch);
+                }
+                None => empty += 1
+            }
+        }
+        if empty > 0 {
+            fen_str += &empty.to_string();
+        }
+        if y > 0 {
+            fen_str.push('/');
+        }
+    }
+    format!("{} {} - - 0 1", fen_str, turn)
+}
+
+// Positions from a few placements of white's king, white's piece and black's king, skipping illegal ones
+fn make_test_boards(piece_ch: char) -> Vec<Board> {
+    let mut boards = Vec::new();
+    for black_king_sq in [0usize, 18, 27, 39, 56] {
+        for white_king_sq in (0usize..64).step_by(5) {
+            for piece_sq in (0..64).step_by(3) {
+                let squares = [white_king_sq, piece_sq, black_king_sq];
+                let kings_touch = (white_king_sq % 8).abs_diff(black_king_sq % 8) <= 1 && (white_king_sq / 8).abs_diff(black_king_sq / 8) <= 1;
+                if squares[0] == squares[1] || squares[1] == squares[2] || squares[0] == squares[2] || kings_touch {
+                    continue;
+                }
+                if piece_ch == 'P' && (piece_sq < 8 || piece_sq >= 56) {
+                    continue;
+                }
+
+                let pieces = [('K', white_king_sq), (piece_ch, piece_sq), ('k', black_king_sq)];
+                let black_board = fen::load_fen(&make_fen(&pieces, 'b')).unwrap();
+                if black_board.checkers == 0 {
+                    boards.push(fen::load_fen(&make_fen(&pieces, 'w')).unwrap()); // Black can't be in check with white to move
+                }
+                boards.push(black_board);
+            }
+        }
+    }
+    boards
+}
+
+#[test]
+fn syzygy_data_wdl_test() {
+    board_crab_lib::init();
+
+    let tablebase = data_tablebase();
+    assert_eq!(tablebase.max_pieces(), 3);
+
+    let test_positions = [
+        ("4k3/8/8/8/8/8/8/4K2Q w - - 0 1", WDL_WIN),
+        ("4k3/8/8/8/8/8/8/4K2Q b - - 0 1", WDL_LOSS),
+        ("4K3/8/8/8/8/8/8/4k2q b - - 0 1", WDL_WIN), // Same, with the colors swapped
+        ("4K3/8/8/8/8/8/8/4k2q w - - 0 1", WDL_LOSS),
+        ("4k3/8/8/8/8/8/8/4KR2 b - - 0 1", WDL_LOSS),
+        ("8/8/8/8/8/8/3kQ3/K7 b - - 0 1", WDL_DRAW), // Black takes the queen
+        ("8/8/8/8/8/8/3kR3/K7 w - - 0 1", WDL_WIN), // White moves the rook away
+        ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", WDL_DRAW), // Stalemate
+        ("k7/8/8/8/8/8/8/KB6 w - - 0 1", WDL_DRAW),
+        ("k7/8/8/8/8/8/8/KN6 b - - 0 1", WDL_DRAW),
+        ("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1", WDL_DRAW),
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", WDL_WIN), // King on the 6th rank in front of the pawn
+        ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", WDL_LOSS),
+        ("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1", WDL_LOSS), // Same, with the colors swapped
+        ("k7/8/8/8/8/8/P7/7K w - - 0 1", WDL_DRAW), // Rook pawn with the king in the corner
+        ("8/4P3/8/8/8/8/k7/4K3 b - - 0 1", WDL_LOSS), // Pawn promotes
+        ("8/8/8/8/8/4k3/8/4K3 w - - 0 1", WDL_DRAW)
+    ];
+
+    for (fen_str, expected) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert_eq!(tablebase.probe_wdl(&board), Some(expected), "Bad WDL for \"{}\"", fen_str);
+    }
+
+    // The result has to match the best result after each move
+    for piece_ch in ['Q', 'R', 'P'] {
+        for board in make_test_boards(piece_ch) {
+            let wdl = tablebase.probe_wdl(&board).unwrap();
+
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_moves(&board, &mut moves);
+            let best_child_wdl = moves.iter().map(|mv| {
+                let mut next_board = board;
+                next_board.do_move(mv);
+                -tablebase.probe_wdl(&next_board).unwrap()
+            }).max();
+
+            let expected = match best_child_wdl {
+                Some(best_child_wdl) => best_child_wdl,
+                None if board.checkers != 0 => WDL_LOSS,
+                None => WDL_DRAW
+            };
+            assert_eq!(wdl, expected, "WDL doesn't match the moves for {}", board);
+        }
+    }
+}
+
+#[test]
+fn syzygy_data_dtz_test() {
+    board_crab_lib::init();
+
+    let tablebase = data_tablebase();
+    let test_positions = [
+        ("k7/8/1K6/8/8/8/8/7R w - - 0 1", 1), // Mate in 1
+        ("k7/7R/1K6/8/8/8/8/8 b - - 0 1", -2),
+        ("R1k5/8/2K5/8/8/8/8/8 b - - 0 1", -1), // Checkmated
+        ("8/8/8/8/8/8/3kQ3/K7 b - - 0 1", 0),
+        ("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", 1), // Pawn move
+        ("8/4P3/8/8/8/8/k7/4K3 b - - 0 1", -2),
+        ("7r/8/8/8/8/1k6/8/K7 b - - 0 1", 1) // Same, with the colors swapped
+    ];
+
+    for (fen_str, expected) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert_eq!(tablebase.probe_dtz(&board), Some(expected), "Bad DTZ for \"{}\"", fen_str);
+    }
+
+    // Without pawns, DTZ is the distance to mate, which is at most 10 moves for KQvK and 16 moves for KRvK
+    for (piece_ch, max_dtz) in [('Q', 20), ('R', 32), ('P', 100)] {
+        for board in make_test_boards(piece_ch) {
+            let wdl = tablebase.probe_wdl(&board).unwrap();
+            let dtz = tablebase.probe_dtz(&board).unwrap();
+            assert_eq!(dtz.signum(), wdl.signum(), "DTZ {} doesn't match WDL {} for {}", dtz, wdl, board);
+            assert!(dtz.abs() <= max_dtz, "DTZ {} is too long for {}", dtz, board);
+
+            // The winner takes the fastest win and the loser takes the slowest loss, counting one ply for the move
+            let mut moves = move_gen::MoveBuffer::new();
+            move_gen::generate_moves(&board, &mut moves);
+            let move_dtzs: Vec<i32> = moves.iter().map(|mv| {
+                let mut next_board = board;
+                next_board.do_move(mv);
+                if next_board.half_move_counter == 0 {
+                    -tablebase.probe_wdl(&next_board).unwrap().signum()
+                } else {
+                    let next_dtz = -tablebase.probe_dtz(&next_board).unwrap();
+                    if next_dtz == 1 { 1 } else { next_dtz + next_dtz.signum() } // Checkmate is also 1
+                }
+            }).collect();
+
+            if dtz > 0 {
+                let fastest_win = move_dtzs.iter().filter(|move_dtz| **move_dtz > 0).min();
+                assert_eq!(fastest_win, Some(&dtz), "Fastest win doesn't match DTZ for {}", board);
+            } else if dtz < 0 && !move_dtzs.is_empty() {
+                assert!(move_dtzs.iter().all(|move_dtz| *move_dtz < 0), "Loss with a move that doesn't lose for {}", board);
+                assert_eq!(move_dtzs.iter().min(), Some(&dtz), "Slowest loss doesn't match DTZ for {}", board);
+            }
+        }
+    }
+}
+
+#[test]
+fn syzygy_data_root_moves_test() {
+    board_crab_lib::init();
+
+    let tablebase = data_tablebase();
+    let test_positions = [
+        "8/8/8/8/8/8/3kQ3/K7 w - - 0 1", // The queen is attacked
+        "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+        "8/8/8/8/8/4k3/4P3/4K3 w - - 0 1",
+        "8/8/8/8/8/4k3/4P3/4K3 b - - 0 1",
+        "k7/8/1K6/8/8/8/8/7R w - - 0 1",
+        "8/8/8/3k4/8/8/8/R3K3 b - - 0 1"
+    ];
+
+    // Without a 50 move rule problem, the root moves are the ones that keep the best result
+    for fen_str in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        let wdl = tablebase.probe_wdl(&board).unwrap();
+        let root_moves = tablebase.filter_root_moves(&board, &[]).unwrap();
+        assert!(!root_moves.is_empty());
+
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(&board, &mut moves);
+        for mv in moves.iter() {
+            let mut next_board = board;
+            next_board.do_move(mv);
+            let keeps_result = -tablebase.probe_wdl(&next_board).unwrap() == wdl;
+            assert_eq!(root_moves.contains(mv), keeps_result, "Bad root move filtering for {} in \"{}\"", mv, fen_str);
+        }
+    }
+
+    // Close to the 50 move rule, only the fastest wins are kept
+    let board = fen::load_fen("k7/8/1K6/8/8/8/8/7R w - - 98 80").unwrap();
+    let root_moves = tablebase.filter_root_moves(&board, &[]).unwrap();
+    let mut mate_board = board;
+    mate_board.do_move(&root_moves[0]);
+    assert_eq!(root_moves.len(), 1);
+    assert_eq!(tablebase.probe_dtz(&mate_board), Some(-1));
+}
+
+// Only runs with real tables, set SYZYGY_PATH to the directory of the 3-4-5 piece tables
+#[test]
+fn syzygy_real_tables_test() {
+    board_crab_lib::init();
+
+    let Ok(path) = std::env::var("SYZYGY_PATH") else {
+        println!("SYZYGY_PATH is not set, skipping");
+        return;
+    };
+
+    let tablebase = Tablebase::new(&path);
+    assert!(tablebase.max_pieces() >= 3, "No tables found in \"{}\"", path);
+
+    let test_positions = [
+        ("4k3/8/8/8/8/8/8/4K2Q w - - 0 1", WDL_WIN),
+        ("4k3/8/8/8/8/8/8/4KR2 b - - 0 1", WDL_LOSS),
+        ("8/8/8/8/8/8/3kQ3/K7 b - - 0 1", WDL_DRAW),
+        ("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1", WDL_DRAW),
+        ("8/8/8/8/8/4k3/8/4K3 w - - 0 1", WDL_DRAW),
+        ("8/8/8/8/8/k7/8/KBN5 w - - 0 1", WDL_WIN)
+    ];
+
+    for (fen_str, expected) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        if !tablebase.can_probe(&board) {
+            continue;
+        }
+
+        let wdl = tablebase.probe_wdl(&board).unwrap();
+        assert_eq!(wdl, expected, "Bad WDL for \"{}\"", fen_str);
+
+        // The result has to match the best result after each move
+        let mut moves = move_gen::MoveBuffer::new();
+        move_gen::generate_moves(&board, &mut moves);
+        let best_child_wdl = moves.iter().map(|mv| {
+            let mut next_board = board;
+            next_board.do_move(mv);
+            -tablebase.probe_wdl(&next_board).unwrap()
+        }).max().unwrap();
+        assert_eq!(wdl, best_child_wdl, "WDL doesn't match the moves for \"{}\"", fen_str);
+
+        if let Some(dtz) = tablebase.probe_dtz(&board) {
+            assert_eq!(dtz.signum(), wdl.signum(), "DTZ {} doesn't match WDL {} for \"{}\"", dtz, wdl, fen_str);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use board_crab_lib::eval::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+use board_crab_lib::search;
+use board_crab_lib::tablebase::*;
+use board_crab_lib::transpos;
+use std::sync::{Arc, OnceLock};
+
+// Larger tables are too slow to generate in tests, and these are shared between tests
+fn get_test_tablebase() -> Arc<DtmTablebase> {
+    static TEST_TABLEBASE: OnceLock<Arc<DtmTablebase>> = OnceLock::new();
+    TEST_TABLEBASE.get_or_init(|| {
+        board_crab_lib::init();
+        let mut dtm_tablebase = DtmTablebase::new();
+        dtm_tablebase.generate("KPvK", false).unwrap();
+        Arc::new(dtm_tablebase)
+    }).clone()
+}
+
+#[test]
+fn tablebase_generate_test() {
+    board_crab_lib::init();
+
+    let dtm_tablebase = get_test_tablebase();
+
+    // Promotions need the queen and rook tables
+    let mut names = dtm_tablebase.table_names();
+    names.sort();
+    assert_eq!(names, vec!["KPvK", "KQvK", "KRvK"]);
+    assert!(dtm_tablebase.get_table("KBvK").is_none());
+
+    let test_positions = [
+        ("4k3/8/4K3/8/8/8/8/7Q w - - 0 1", DtmResult::Win(1)), // Qh8#
+        ("4k3/8/4K3/8/8/8/8/7Q b - - 0 1", DtmResult::Loss(4)),
+        ("4k3/4Q3/4K3/8/8/8/8/8 b - - 0 1", DtmResult::Loss(0)), // Checkmated
+        ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", DtmResult::Draw), // Stalemate
+        ("8/8/8/8/8/8/3kQ3/K7 b - - 0 1", DtmResult::Draw), // Black takes the queen
+        ("7k/8/6K1/8/8/8/8/R7 w - - 0 1", DtmResult::Win(1)),
+        ("8/8/8/8/8/8/1q6/K1k5 w - - 0 1", DtmResult::Loss(0)), // Black has the queen
+        ("k7/8/K7/P7/8/8/8/8 w - - 0 1", DtmResult::Draw), // Rook pawn with the king in the corner
+        ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", DtmResult::Win(21)),
+        ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", DtmResult::Draw) // King vs king never needs a table
+    ];
+
+    for (fen_str, expected) in test_positions {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert!(dtm_tablebase.can_probe(&board));
+        assert_eq!(dtm_tablebase.probe(&board), Some(expected), "Bad result for \"{}\"", fen_str);
+    }
+
+    // Missing table
+    let board = fen::load_fen("4k3/8/8/8/8/8/8/2B1K1N1 w - - 0 1").unwrap();
+    assert_eq!(dtm_tablebase.probe(&board), None);
+
+    // No tables for castling positions
+    let board = fen::load_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+    assert!(!dtm_tablebase.can_probe(&board));
+
+    // Mirror images share an index, with the white king on a1-d1-d4 (or files a-d with pawns)
+    assert_eq!(dtm_tablebase.get_table("KQvK").unwrap().num_positions(), 2 * 10 * 64 * 64);
+    assert_eq!(dtm_tablebase.get_table("KPvK").unwrap().num_positions(), 2 * 32 * 64 * 64);
+    let mirrored_positions: [&[&str]; 3] = [
+        &["8/8/8/8/3k4/8/8/KQ6 w - - 0 1", "8/8/8/8/4k3/8/8/6QK w - - 0 1", "KQ6/8/8/3k4/8/8/8/8 w - - 0 1", "8/8/8/8/3k4/8/Q7/K7 w - - 0 1"],
+        &["8/8/8/8/8/2k5/8/R5K1 b - - 0 1", "8/8/8/8/8/5k2/8/1K5R b - - 0 1", "R5K1/8/2k5/8/8/8/8/8 b - - 0 1", "8/K7/8/8/8/2k5/8/R7 b - - 0 1"],
+        &["8/8/8/4k3/8/8/4P3/4K3 w - - 0 1", "8/8/8/3k4/8/8/3P4/3K4 w - - 0 1"]
+    ];
+    for fen_strs in mirrored_positions {
+        let expected = dtm_tablebase.probe(&fen::load_fen(fen_strs[0]).unwrap());
+        for fen_str in fen_strs {
+            assert_eq!(dtm_tablebase.probe(&fen::load_fen(fen_str).unwrap()), expected, "Bad result for \"{}\"", fen_str);
+        }
+    }
+
+    // The longest mates are well known: 10 moves for KQvK, and 16 moves for KRvK (counted from the losing side to move)
+    assert_eq!(dtm_tablebase.get_table("KQvK").unwrap().get_longest_mate(), 20);
+    assert_eq!(dtm_tablebase.get_table("KRvK").unwrap().get_longest_mate(), 32);
+}
+
+#[test]
+fn tablebase_consistency_test() {
+    board_crab_lib::init();
+
+    let dtm_tablebase = get_test_tablebase();
+
+    // Every result has to match the best result after each move
+    let test_fens = [
+        "8/8/8/8/3k4/8/8/KQ6 w - - 0 1",
+        "8/8/8/8/3k4/8/8/KQ6 b - - 0 1",
+        "8/8/8/8/8/2k5/8/R5K1 w - - 0 1",
+        "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1",
+        "8/8/8/4k3/8/8/4P3/4K3 b - - 0 1",
+        "8/8/8/8/8/4k3/4P3/4K3 w - - 0 1",
+        "8/8/2k5/8/8/8/P7/K7 b - - 0 1",
+        "8/3P4/8/8/1k6/8/8/K7 w - - 0 1"
+    ];
+
+    let mut num_checked = 0;
+    for fen_str in test_fens {
+        // Check the position and everything reachable in two plies
+        let board = fen::load_fen(fen_str).unwrap();
+        let mut boards = vec![board];
+        let mut moves = move_gen::MoveBuffer::new();
+        for _ in 0..2 {
+            let mut next_boards = Vec::new();
+            for board in &boards {
+                moves.clear();
+                move_gen::generate_moves(board, &mut moves);
+                for mv in moves.iter() {
+                    let mut next_board = *board;
+                    next_board.do_move(mv);
+                    next_boards.push(next_board);
+                }
+            }
+            boards.extend(next_boards);
+        }
+
+        for board in boards {
+            let result = dtm_tablebase.probe(&board).unwrap();
+
+            moves.clear();
+            move_gen::generate_moves(&board, &mut moves);
+            let child_results: Vec<DtmResult> = moves.iter().map(|mv| {
+                let mut next_board = board;
+                next_board.do_move(mv);
+                dtm_tablebase.probe(&next_board).unwrap()
+            }).collect();
+
+            let expected = if child_results.is_empty() {
+                if board.checkers != 0 { DtmResult::Loss(0) } else { DtmResult::Draw }
+            } else if let Some(plies) = child_results.iter().filter_map(|r| match r { DtmResult::Loss(p) => Some(*p), _ => None }).min() {
+                DtmResult::Win(plies + 1)
+            } else if child_results.contains(&DtmResult::Draw) {
+                DtmResult::Draw
+            } else {
+                let plies = child_results.iter().filter_map(|r| match r { DtmResult::Win(p) => Some(*p), _ => None }).max().unwrap();
+                DtmResult::Loss(plies + 1)
+            };
+
+            assert_eq!(result, expected, "Result doesn't match the moves for \"{}\"", fen::make_fen(&board));
+            num_checked += 1;
+        }
+    }
+
+    assert!(num_checked > 500);
+}
+
+#[test]
+fn tablebase_save_load_test() {
+    board_crab_lib::init();
+
+    let dtm_tablebase = get_test_tablebase();
+
+    let table = dtm_tablebase.get_table("KPvK").unwrap();
+    let bytes = table.to_bytes();
+    let loaded_table = DtmTable::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded_table.name, "KPvK");
+    assert_eq!(loaded_table.to_bytes(), bytes);
+
+    assert!(DtmTable::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(DtmTable::from_bytes(b"not a table").is_err());
+
+    let dir = std::env::temp_dir().join(format!("board_crab_tablebase_save_load_test_{}", std::process::id()));
+    let dir_str = dir.to_str().unwrap();
+    dtm_tablebase.save_dir(dir_str).unwrap();
+    std::fs::write(dir.join("readme.txt"), "Not a table").unwrap();
+
+    let loaded_tablebase = DtmTablebase::load_dir(dir_str).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut names = loaded_tablebase.table_names();
+    names.sort();
+    assert_eq!(names, vec!["KPvK", "KQvK", "KRvK"]);
+
+    // Black to move, and with the colors swapped
+    for fen_str in ["4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", "8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", "8/8/8/8/3k4/8/8/KQ6 w - - 0 1"] {
+        let board = fen::load_fen(fen_str).unwrap();
+        assert_eq!(loaded_tablebase.probe(&board), dtm_tablebase.probe(&board), "Bad result for \"{}\"", fen_str);
+    }
+
+    assert!(DtmTablebase::load_dir("/this/path/does/not/exist").is_err());
+}
+
+#[test]
+fn tablebase_search_test() {
+    board_crab_lib::init();
+
+    let dtm_tablebase = get_test_tablebase();
+
+    let board = fen::load_fen("8/8/8/8/3k4/8/8/KQ6 w - - 0 1").unwrap();
+    let Some(DtmResult::Win(plies)) = dtm_tablebase.probe(&board) else {
+        panic!("Expected a win");
+    };
+
+    let mut search_config = search::SearchConfig::new();
+    search_config.dtm_tablebase = Some(dtm_tablebase.clone());
+    let table = transpos::Table::new(4);
+    let (eval, search_info) = search::search(&board, &[], &table, &search_config, 2, None, None, None);
+    assert_eq!(eval, Some(VALUE_CHECKMATE - plies as Value));
+    assert!(search_info.tb_hits > 0);
+
+    // The best move has to keep the shortest mate
+    let mut next_board = board;
+    next_board.do_move(&search_info.root_best_move.unwrap());
+    assert_eq!(dtm_tablebase.probe(&next_board), Some(DtmResult::Loss(plies - 1)));
+    assert_eq!(dtm_tablebase.get_best_move(&board).map(|mv| {
+        let mut best_board = board;
+        best_board.do_move(&mv);
+        dtm_tablebase.probe(&best_board)
+    }), Some(Some(DtmResult::Loss(plies - 1))));
+}
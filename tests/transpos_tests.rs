@@ -0,0 +1,154 @@
+use board_crab_lib::board::PackedMove;
+use board_crab_lib::eval::*;
+use board_crab_lib::transpos::*;
+use board_crab_lib::zobrist::Hash;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+// Every field is derived from the hash, so a torn entry (with fields from two different writes) can be detected
+fn make_test_entry(hash: Hash) -> (Value, PackedMove, u8, EntryType) {
+    let entry_type = match hash % 3 {
+        0 => EntryType::Exact,
+        1 => EntryType::FailLow,
+        _ => EntryType::FailHigh
+    };
+    ((hash >> 40) as u16 as Value, PackedMove((hash >> 20) as u16), (hash >> 10) as u8, entry_type)
+}
+
+fn check_entry(entry: &Entry, hash: Hash) {
+    let (eval, best_move, depth_remaining, entry_type) = make_test_entry(hash);
+    assert_eq!(entry.hash, hash);
+    assert_eq!(entry.eval, eval, "Bad eval for {:#018x}", hash);
+    assert_eq!(entry.best_move, best_move, "Bad best move for {:#018x}", hash);
+    assert_eq!(entry.depth_remaining, depth_remaining, "Bad depth for {:#018x}", hash);
+    assert_eq!(entry.entry_type, entry_type, "Bad entry type for {:#018x}", hash);
+}
+
+#[test]
+fn transpos_test() {
+    let table = Table::new(1);
+    assert!(!table.get_fast(12345).is_valid());
+
+    table.set(12345, -350, PackedMove(777), 6, EntryType::FailHigh);
+    let entry = table.get_fast(12345);
+    assert!(entry.is_valid());
+    assert_eq!(entry.eval, -350);
+    assert_eq!(entry.best_move, PackedMove(777));
+    assert_eq!(entry.depth_remaining, 6);
+    assert_eq!(entry.entry_type, EntryType::FailHigh);
+
+    // Replaces the same entry
+    table.set(12345, 125, PackedMove::NULL, 2, EntryType::Exact);
+    let entry = table.get_fast(12345);
+    assert_eq!(entry.eval, 125);
+    assert_eq!(entry.entry_type, EntryType::Exact);
+
+    assert!(!table.get_fast(12346).is_valid());
+}
+
+#[test]
+fn transpos_replace_test() {
+    let table = Table::new(1);
+    let num_buckets = table.get_num_buckets() as Hash;
+    let hashes: Vec<Hash> = (1..=5).map(|i| i * num_buckets + 3).collect(); // All in the same bucket
+
+    // Within a search, the entry with the lowest depth is replaced
+    for (i, hash) in hashes[..4].iter().enumerate() {
+        table.set(*hash, 0, PackedMove::NULL, 10 - i as u8, EntryType::Exact);
+    }
+    table.set(hashes[4], 0, PackedMove::NULL, 12, EntryType::Exact);
+    assert!(!table.get_fast(hashes[3]).is_valid());
+
+    // Entries from an older search are replaced first, even if they're deeper
+    table.new_search();
+    table.set(hashes[3], 0, PackedMove::NULL, 1, EntryType::Exact);
+    assert!(!table.get_fast(hashes[2]).is_valid());
+    table.set(hashes[2], 0, PackedMove::NULL, 1, EntryType::Exact);
+    assert!(!table.get_fast(hashes[1]).is_valid());
+    for hash in [hashes[0], hashes[2], hashes[3], hashes[4]] {
+        assert!(table.get_fast(hash).is_valid());
+    }
+    assert_eq!(table.get_fast(hashes[3]).generation, 1);
+}
+
+// Hammers a tiny table from many threads, checking that every entry read back is one that was fully written
+#[test]
+fn transpos_stress_test() {
+    const NUM_THREADS: usize = 16;
+    const NUM_OPS_PER_THREAD: usize = 200_000;
+    const NUM_HASHES: usize = 64;
+
+    let table = Table::new(1);
+
+    // All of the hashes go in the same bucket, so that threads keep writing different entries over each other
+    let num_buckets = table.get_num_buckets() as Hash;
+    let mut rng = StdRng::seed_from_u64(0);
+    let hashes: Vec<Hash> = (0..NUM_HASHES).map(|_| (rng.random::<Hash>() / num_buckets) * num_buckets + 7).collect();
+
+    let num_found: usize = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..NUM_THREADS).map(|thread_idx| {
+            let table = &table;
+            let hashes = &hashes;
+            scope.spawn(move || {
+                let mut rng = StdRng::seed_from_u64(thread_idx as u64 + 1);
+                let mut num_found = 0;
+                for _ in 0..NUM_OPS_PER_THREAD {
+                    let hash = hashes[rng.random_range(0..hashes.len())];
+                    if rng.random_bool(0.5) {
+                        let (eval, best_move, depth_remaining, entry_type) = make_test_entry(hash);
+                        table.set(hash, eval, best_move, depth_remaining, entry_type);
+                    } else {
+                        let entry = table.get_fast(hash);
+                        if entry.is_valid() {
+                            check_entry(&entry, hash);
+                            num_found += 1;
+                        }
+                    }
+                }
+                num_found
+            })
+        }).collect();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+    });
+
+    assert!(num_found > 0);
+
+    // Everything left in the table is still intact
+    for &hash in &hashes {
+        let entry = table.get_fast(hash);
+        if entry.is_valid() {
+            check_entry(&entry, hash);
+        }
+    }
+}
+
+#[test]
+fn transpos_mate_value_test() {
+    // Mate scores are stored relative to the node, so they can be read back at another ply
+    let table = Table::new(1);
+    table.set(12345, value_to_table(value_mate_in(5), 3), PackedMove::NULL, 4, EntryType::Exact);
+    let entry = table.get_fast(12345);
+    assert_eq!(entry.eval, value_mate_in(2));
+    assert_eq!(value_from_table(entry.eval, 3), value_mate_in(5));
+    assert_eq!(value_from_table(entry.eval, 7), value_mate_in(9));
+
+    table.set(12345, value_to_table(value_mated_in(6), 2), PackedMove::NULL, 4, EntryType::Exact);
+    assert_eq!(value_from_table(table.get_fast(12345).eval, 1), value_mated_in(5));
+
+    // So are tablebase wins and losses
+    table.set(12345, value_to_table(VALUE_TABLEBASE_WIN - 3, 3), PackedMove::NULL, 4, EntryType::FailHigh);
+    assert_eq!(value_from_table(table.get_fast(12345).eval, 7), VALUE_TABLEBASE_WIN - 7);
+    table.set(12345, value_to_table(-VALUE_TABLEBASE_WIN + 4, 4), PackedMove::NULL, 4, EntryType::FailLow);
+    assert_eq!(value_from_table(table.get_fast(12345).eval, 2), -VALUE_TABLEBASE_WIN + 2);
+
+    // Normal evals are left alone
+    assert_eq!(value_to_table(-250, 10), -250);
+    assert_eq!(value_from_table(5000, 10), 5000);
+
+    assert_eq!(get_mate_moves(value_mate_in(3)), Some(2));
+    assert_eq!(get_mate_moves(value_mated_in(4)), Some(-2));
+    assert_eq!(get_mate_moves(VALUE_TABLEBASE_WIN), None);
+    assert_eq!(eval_to_str(value_mate_in(1)), "#1");
+    assert_eq!(eval_to_str(-125), "-1.25");
+}
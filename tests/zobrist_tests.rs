@@ -0,0 +1,41 @@
+use board_crab_lib::board::*;
+use board_crab_lib::fen;
+use board_crab_lib::move_gen;
+
+fn find_move(board: &Board, move_str: &str) -> Move {
+    let mut moves = move_gen::MoveBuffer::new();
+    move_gen::generate_moves(board, &mut moves);
+    *moves.iter().find(|mv| format!("{mv}") == move_str).expect(move_str)
+}
+
+// Known hashes from the Polyglot book format spec (http://hgm.nubati.net/book_format.html)
+#[test]
+fn polyglot_hash_test() {
+    board_crab_lib::init();
+
+    let test_entries: [(&str, u64); 9] = [
+        ("", 0x463b96181691fc9c),
+        ("e2e4", 0x823c9b50fd114196),
+        ("e2e4 d7d5", 0x0756b94461c50fb0),
+        ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+        ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+        ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+        ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+        ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+        ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+    ];
+
+    for (moves_str, expected_hash) in test_entries {
+        let mut board = Board::start_pos();
+        for move_str in moves_str.split_whitespace() {
+            let mv = find_move(&board, move_str);
+            board.do_move(&mv);
+        }
+
+        assert_eq!(board.hash, expected_hash, "Wrong hash after moves \"{}\"", moves_str);
+
+        // A full update from a FEN should give the same hash as incremental updates
+        let fen_board = fen::load_fen(&fen::make_fen(&board)).unwrap();
+        assert_eq!(fen_board.hash, expected_hash, "Wrong hash for FEN after moves \"{}\"", moves_str);
+    }
+}